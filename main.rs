@@ -5,6 +5,7 @@ use crate::num_core::{
     smp_vector::SmpVector,
     n_body::NBody,
     io_config::{self, *},
+    simulation,
 };
 
 mod num_core;
@@ -18,10 +19,36 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     });
     // read yaml
     let yaml_file = io_config::read_setting_yaml(config).unwrap();
-    let n_body: NBody = serde_yaml::from_str(&yaml_file).unwrap();
-    n_body.check().unwrap();
+    let mut n_body: NBody = serde_yaml::from_str(&yaml_file).unwrap();
+    let run = io_config::read_run(&yaml_file)?;
+
+    match run {
+        Some(run) => {
+            simulation::prepare(&mut n_body, &run)?;
+            let stdout = std::io::stdout();
+            let mut diagnostics = match &run.diagnostics_file {
+                Some(file_name) => Some(std::fs::File::create(file_name)?),
+                None => None,
+            };
+            let mut events = match &run.events_file {
+                Some(file_name) => Some(std::fs::File::create(file_name)?),
+                None => None,
+            };
+            simulation::simulate(
+                &n_body,
+                &run,
+                &mut stdout.lock(),
+                diagnostics.as_mut().map(|f| f as &mut dyn std::io::Write),
+                events.as_mut().map(|f| f as &mut dyn std::io::Write),
+            )?;
+        }
+        None => {
+            // no Run section: only show the parsed setting
+            n_body.check().unwrap();
+            println!("{:?}", n_body);
+        }
+    }
+
 
-    println!("{:?}", n_body);
-    
     Ok(())
 }
@@ -0,0 +1,665 @@
+// Butcher tableaux of explicit Runge–Kutta methods.
+//
+//  c_1 |
+//  c_2 | a_21
+//  ... | ...
+//  c_s | a_s1 ... a_s,s-1
+//  ----+------------------
+//      | b_1  ... b_s        (order p, propagated)
+//      | bh_1 ... bh_s       (embedded order p^, error estimate only)
+//      | bc_1 ... bc_s       (optional second estimate of order p^^ < p^)
+//
+// ref. Hairer, Nørsett, Wanner, Solving ordinary differential equations I (1993), Sec. II.1–II.5.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ButcherTableau {
+    pub c: Vec<f64>,
+    // a[i] has i entries (strictly lower triangular)
+    pub a: Vec<Vec<f64>>,
+    pub b: Vec<f64>,
+    pub b_hat: Option<Vec<f64>>,
+    pub order: usize,
+    // order of b_hat, 0 without an embedded formula
+    pub embedded_order: usize,
+    // second, lower order formula that only sharpens the error estimate (DOP853)
+    pub b_check: Option<Vec<f64>>,
+    // order of b_check, 0 without it
+    pub check_order: usize,
+}
+
+impl ButcherTableau {
+    pub fn num_of_stages(&self) -> usize {
+        self.c.len()
+    }
+
+    // classic Runge–Kutta method of order 4 (no error estimate)
+    pub fn rk4() -> ButcherTableau {
+        ButcherTableau {
+            c: vec![0.0, 1.0 / 2.0, 1.0 / 2.0, 1.0],
+            a: vec![
+                vec![],
+                vec![1.0 / 2.0],
+                vec![0.0, 1.0 / 2.0],
+                vec![0.0, 0.0, 1.0],
+            ],
+            b: vec![1.0 / 6.0, 1.0 / 3.0, 1.0 / 3.0, 1.0 / 6.0],
+            b_hat: None,
+            order: 4,
+            embedded_order: 0,
+            b_check: None,
+            check_order: 0,
+        }
+    }
+
+    // Bogacki–Shampine 3(2), Appl. Math. Lett. 2, 321 (1989)
+    pub fn bogacki_shampine32() -> ButcherTableau {
+        ButcherTableau {
+            c: vec![0.0, 1.0 / 2.0, 3.0 / 4.0, 1.0],
+            a: vec![
+                vec![],
+                vec![1.0 / 2.0],
+                vec![0.0, 3.0 / 4.0],
+                vec![2.0 / 9.0, 1.0 / 3.0, 4.0 / 9.0],
+            ],
+            b: vec![2.0 / 9.0, 1.0 / 3.0, 4.0 / 9.0, 0.0],
+            b_hat: Some(vec![7.0 / 24.0, 1.0 / 4.0, 1.0 / 3.0, 1.0 / 8.0]),
+            order: 3,
+            embedded_order: 2,
+            b_check: None,
+            check_order: 0,
+        }
+    }
+
+    // Cash–Karp 5(4), ACM Trans. Math. Softw. 16, 201 (1990)
+    pub fn cash_karp45() -> ButcherTableau {
+        ButcherTableau {
+            c: vec![0.0, 1.0 / 5.0, 3.0 / 10.0, 3.0 / 5.0, 1.0, 7.0 / 8.0],
+            a: vec![
+                vec![],
+                vec![1.0 / 5.0],
+                vec![3.0 / 40.0, 9.0 / 40.0],
+                vec![3.0 / 10.0, -9.0 / 10.0, 6.0 / 5.0],
+                vec![-11.0 / 54.0, 5.0 / 2.0, -70.0 / 27.0, 35.0 / 27.0],
+                vec![
+                    1631.0 / 55296.0,
+                    175.0 / 512.0,
+                    575.0 / 13824.0,
+                    44275.0 / 110592.0,
+                    253.0 / 4096.0,
+                ],
+            ],
+            b: vec![
+                37.0 / 378.0,
+                0.0,
+                250.0 / 621.0,
+                125.0 / 594.0,
+                0.0,
+                512.0 / 1771.0,
+            ],
+            b_hat: Some(vec![
+                2825.0 / 27648.0,
+                0.0,
+                18575.0 / 48384.0,
+                13525.0 / 55296.0,
+                277.0 / 14336.0,
+                1.0 / 4.0,
+            ]),
+            order: 5,
+            embedded_order: 4,
+            b_check: None,
+            check_order: 0,
+        }
+    }
+
+    // Dormand–Prince 5(4), J. Comput. Appl. Math. 6, 19 (1980)
+    pub fn dormand_prince54() -> ButcherTableau {
+        ButcherTableau {
+            c: vec![0.0, 1.0 / 5.0, 3.0 / 10.0, 4.0 / 5.0, 8.0 / 9.0, 1.0, 1.0],
+            a: vec![
+                vec![],
+                vec![1.0 / 5.0],
+                vec![3.0 / 40.0, 9.0 / 40.0],
+                vec![44.0 / 45.0, -56.0 / 15.0, 32.0 / 9.0],
+                vec![
+                    19372.0 / 6561.0,
+                    -25360.0 / 2187.0,
+                    64448.0 / 6561.0,
+                    -212.0 / 729.0,
+                ],
+                vec![
+                    9017.0 / 3168.0,
+                    -355.0 / 33.0,
+                    46732.0 / 5247.0,
+                    49.0 / 176.0,
+                    -5103.0 / 18656.0,
+                ],
+                vec![
+                    35.0 / 384.0,
+                    0.0,
+                    500.0 / 1113.0,
+                    125.0 / 192.0,
+                    -2187.0 / 6784.0,
+                    11.0 / 84.0,
+                ],
+            ],
+            b: vec![
+                35.0 / 384.0,
+                0.0,
+                500.0 / 1113.0,
+                125.0 / 192.0,
+                -2187.0 / 6784.0,
+                11.0 / 84.0,
+                0.0,
+            ],
+            b_hat: Some(vec![
+                5179.0 / 57600.0,
+                0.0,
+                7571.0 / 16695.0,
+                393.0 / 640.0,
+                -92097.0 / 339200.0,
+                187.0 / 2100.0,
+                1.0 / 40.0,
+            ]),
+            order: 5,
+            embedded_order: 4,
+            b_check: None,
+            check_order: 0,
+        }
+    }
+
+    // Verner 6(5) "DVERK", SIAM J. Numer. Anal. 15, 772 (1978)
+    pub fn verner65() -> ButcherTableau {
+        ButcherTableau {
+            c: vec![
+                0.0,
+                1.0 / 6.0,
+                4.0 / 15.0,
+                2.0 / 3.0,
+                5.0 / 6.0,
+                1.0,
+                1.0 / 15.0,
+                1.0,
+            ],
+            a: vec![
+                vec![],
+                vec![1.0 / 6.0],
+                vec![4.0 / 75.0, 16.0 / 75.0],
+                vec![5.0 / 6.0, -8.0 / 3.0, 5.0 / 2.0],
+                vec![-165.0 / 64.0, 55.0 / 6.0, -425.0 / 64.0, 85.0 / 96.0],
+                vec![12.0 / 5.0, -8.0, 4015.0 / 612.0, -11.0 / 36.0, 88.0 / 255.0],
+                vec![
+                    -8263.0 / 15000.0,
+                    124.0 / 75.0,
+                    -643.0 / 680.0,
+                    -81.0 / 250.0,
+                    2484.0 / 10625.0,
+                    0.0,
+                ],
+                vec![
+                    3501.0 / 1720.0,
+                    -300.0 / 43.0,
+                    297275.0 / 52632.0,
+                    -319.0 / 2322.0,
+                    24068.0 / 84065.0,
+                    0.0,
+                    3850.0 / 26703.0,
+                ],
+            ],
+            b: vec![
+                3.0 / 40.0,
+                0.0,
+                875.0 / 2244.0,
+                23.0 / 72.0,
+                264.0 / 1955.0,
+                0.0,
+                125.0 / 11592.0,
+                43.0 / 616.0,
+            ],
+            b_hat: Some(vec![
+                13.0 / 160.0,
+                0.0,
+                2375.0 / 5984.0,
+                5.0 / 16.0,
+                12.0 / 85.0,
+                3.0 / 44.0,
+                0.0,
+                0.0,
+            ]),
+            order: 6,
+            embedded_order: 5,
+            b_check: None,
+            check_order: 0,
+        }
+    }
+
+    // Fehlberg 7(8), NASA TR R-287 (1968), Table X.
+    // Propagated with the 8th order weights (local extrapolation).
+    pub fn fehlberg78() -> ButcherTableau {
+        ButcherTableau {
+            c: vec![
+                0.0,
+                2.0 / 27.0,
+                1.0 / 9.0,
+                1.0 / 6.0,
+                5.0 / 12.0,
+                1.0 / 2.0,
+                5.0 / 6.0,
+                1.0 / 6.0,
+                2.0 / 3.0,
+                1.0 / 3.0,
+                1.0,
+                0.0,
+                1.0,
+            ],
+            a: vec![
+                vec![],
+                vec![2.0 / 27.0],
+                vec![1.0 / 36.0, 1.0 / 12.0],
+                vec![1.0 / 24.0, 0.0, 1.0 / 8.0],
+                vec![5.0 / 12.0, 0.0, -25.0 / 16.0, 25.0 / 16.0],
+                vec![1.0 / 20.0, 0.0, 0.0, 1.0 / 4.0, 1.0 / 5.0],
+                vec![
+                    -25.0 / 108.0,
+                    0.0,
+                    0.0,
+                    125.0 / 108.0,
+                    -65.0 / 27.0,
+                    125.0 / 54.0,
+                ],
+                vec![
+                    31.0 / 300.0,
+                    0.0,
+                    0.0,
+                    0.0,
+                    61.0 / 225.0,
+                    -2.0 / 9.0,
+                    13.0 / 900.0,
+                ],
+                vec![
+                    2.0,
+                    0.0,
+                    0.0,
+                    -53.0 / 6.0,
+                    704.0 / 45.0,
+                    -107.0 / 9.0,
+                    67.0 / 90.0,
+                    3.0,
+                ],
+                vec![
+                    -91.0 / 108.0,
+                    0.0,
+                    0.0,
+                    23.0 / 108.0,
+                    -976.0 / 135.0,
+                    311.0 / 54.0,
+                    -19.0 / 60.0,
+                    17.0 / 6.0,
+                    -1.0 / 12.0,
+                ],
+                vec![
+                    2383.0 / 4100.0,
+                    0.0,
+                    0.0,
+                    -341.0 / 164.0,
+                    4496.0 / 1025.0,
+                    -301.0 / 82.0,
+                    2133.0 / 4100.0,
+                    45.0 / 82.0,
+                    45.0 / 164.0,
+                    18.0 / 41.0,
+                ],
+                vec![
+                    3.0 / 205.0,
+                    0.0,
+                    0.0,
+                    0.0,
+                    0.0,
+                    -6.0 / 41.0,
+                    -3.0 / 205.0,
+                    -3.0 / 41.0,
+                    3.0 / 41.0,
+                    6.0 / 41.0,
+                    0.0,
+                ],
+                vec![
+                    -1777.0 / 4100.0,
+                    0.0,
+                    0.0,
+                    -341.0 / 164.0,
+                    4496.0 / 1025.0,
+                    -289.0 / 82.0,
+                    2193.0 / 4100.0,
+                    51.0 / 82.0,
+                    33.0 / 164.0,
+                    12.0 / 41.0,
+                    0.0,
+                    1.0,
+                ],
+            ],
+            b: vec![
+                0.0,
+                0.0,
+                0.0,
+                0.0,
+                0.0,
+                34.0 / 105.0,
+                9.0 / 35.0,
+                9.0 / 35.0,
+                9.0 / 280.0,
+                9.0 / 280.0,
+                0.0,
+                41.0 / 840.0,
+                41.0 / 840.0,
+            ],
+            b_hat: Some(vec![
+                41.0 / 840.0,
+                0.0,
+                0.0,
+                0.0,
+                0.0,
+                34.0 / 105.0,
+                9.0 / 35.0,
+                9.0 / 35.0,
+                9.0 / 280.0,
+                9.0 / 280.0,
+                41.0 / 840.0,
+                0.0,
+                0.0,
+            ]),
+            order: 8,
+            embedded_order: 7,
+            b_check: None,
+            check_order: 0,
+        }
+    }
+
+    // Dormand–Prince 8(5,3) (Hairer, Nørsett, Wanner, Sec. II.10, code DOP853).
+    // b_hat = b - e with the 5th order error coefficients e of the code, b_check is
+    // the 3rd order formula; the extra stages of the dense output are in dop853.
+    #[allow(clippy::excessive_precision)]
+    pub fn dormand_prince853() -> ButcherTableau {
+        let b = vec![
+            5.42937341165687622380535766363e-2,
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+            4.45031289275240888144113950566,
+            1.89151789931450038304281599044,
+            -5.8012039600105847814672114227,
+            3.1116436695781989440891606237e-1,
+            -1.52160949662516078556178806805e-1,
+            2.01365400804030348374776537501e-1,
+            4.47106157277725905176885569043e-2,
+        ];
+        let e = [
+            0.1312004499419488073250102996e-1,
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+            -0.1225156446376204440720569753e1,
+            -0.4957589496572501915214079952,
+            0.1664377182454986536961530415e1,
+            -0.3503288487499736816886487290,
+            0.3341791187130174790297318841,
+            0.8192320648511571246570742613e-1,
+            -0.2235530786388629525884427845e-1,
+        ];
+        let b_hat = b.iter().zip(e.iter()).map(|(b, e)| b - e).collect();
+        ButcherTableau {
+            c: vec![
+                0.0,
+                0.526001519587677318785587544488e-1,
+                0.789002279381515978178381316732e-1,
+                0.118350341907227396726757197510,
+                0.281649658092772603273242802490,
+                1.0 / 3.0,
+                0.25,
+                4.0 / 13.0,
+                127.0 / 195.0,
+                0.6,
+                6.0 / 7.0,
+                1.0,
+            ],
+            a: vec![
+                vec![],
+                vec![5.26001519587677318785587544488e-2],
+                vec![
+                    1.97250569845378994544595329183e-2,
+                    5.91751709536136983633785987549e-2,
+                ],
+                vec![
+                    2.95875854768068491816892993775e-2,
+                    0.0,
+                    8.87627564304205475450678981324e-2,
+                ],
+                vec![
+                    2.41365134159266685502369798665e-1,
+                    0.0,
+                    -8.84549479328286085344864962717e-1,
+                    9.24834003261792003115737966543e-1,
+                ],
+                vec![
+                    3.7037037037037037037037037037e-2,
+                    0.0,
+                    0.0,
+                    1.70828608729473871279604482173e-1,
+                    1.25467687566822425016691814123e-1,
+                ],
+                vec![
+                    3.7109375e-2,
+                    0.0,
+                    0.0,
+                    1.70252211019544039314978060272e-1,
+                    6.02165389804559606850219397283e-2,
+                    -1.7578125e-2,
+                ],
+                vec![
+                    3.70920001185047927108779319836e-2,
+                    0.0,
+                    0.0,
+                    1.70383925712239993810214054705e-1,
+                    1.07262030446373284651809199168e-1,
+                    -1.53194377486244017527936158236e-2,
+                    8.27378916381402288758473766002e-3,
+                ],
+                vec![
+                    6.24110958716075717114429577812e-1,
+                    0.0,
+                    0.0,
+                    -3.36089262944694129406857109825,
+                    -8.68219346841726006818189891453e-1,
+                    2.75920996994467083049415600797e1,
+                    2.01540675504778934086186788979e1,
+                    -4.34898841810699588477366255144e1,
+                ],
+                vec![
+                    4.77662536438264365890433908527e-1,
+                    0.0,
+                    0.0,
+                    -2.48811461997166764192642586468,
+                    -5.90290826836842996371446475743e-1,
+                    2.12300514481811942347288949897e1,
+                    1.52792336328824235832596922938e1,
+                    -3.32882109689848629194453265587e1,
+                    -2.03312017085086261358222928593e-2,
+                ],
+                vec![
+                    -9.3714243008598732571704021658e-1,
+                    0.0,
+                    0.0,
+                    5.18637242884406370830023853209,
+                    1.09143734899672957818500254654,
+                    -8.14978701074692612513997267357,
+                    -1.85200656599969598641566180701e1,
+                    2.27394870993505042818970056734e1,
+                    2.49360555267965238987089396762,
+                    -3.0467644718982195003823669022,
+                ],
+                vec![
+                    2.27331014751653820792359768449,
+                    0.0,
+                    0.0,
+                    -1.05344954667372501984066689879e1,
+                    -2.00087205822486249909675718444,
+                    -1.79589318631187989172765950534e1,
+                    2.79488845294199600508499808837e1,
+                    -2.85899827713502369474065508674,
+                    -8.87285693353062954433549289258,
+                    1.23605671757943030647266201528e1,
+                    6.43392746015763530355970484046e-1,
+                ],
+            ],
+            b,
+            b_hat: Some(b_hat),
+            order: 8,
+            embedded_order: 5,
+            b_check: Some(vec![
+                0.244094488188976377952755905512,
+                0.0,
+                0.0,
+                0.0,
+                0.0,
+                0.0,
+                0.0,
+                0.0,
+                0.733846688281611857341361741547,
+                0.0,
+                0.0,
+                0.220588235294117647058823529412e-1,
+            ]),
+            check_order: 3,
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod butcher_tableau_tests {
+    extern crate approx;
+    use crate::num_core::butcher_tableau::ButcherTableau;
+    use std::collections::BTreeSet;
+
+    // rooted tree: the list of the subtrees hanging from the root, kept sorted
+    #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+    struct Tree(Vec<Tree>);
+
+    impl Tree {
+        fn order(&self) -> usize {
+            1 + self.0.iter().map(|t| t.order()).sum::<usize>()
+        }
+
+        // gamma(t) = |t| prod gamma(t_i)
+        fn density(&self) -> f64 {
+            self.order() as f64 * self.0.iter().map(|t| t.density()).product::<f64>()
+        }
+
+        // every tree obtained by attaching one leaf to a node of self
+        fn grow(&self) -> Vec<Tree> {
+            let mut children = self.0.clone();
+            children.push(Tree(vec![]));
+            children.sort();
+            let mut ret = vec![Tree(children)];
+            for (i, child) in self.0.iter().enumerate() {
+                for grown in child.grow() {
+                    let mut children = self.0.clone();
+                    children[i] = grown;
+                    children.sort();
+                    ret.push(Tree(children));
+                }
+            }
+            ret
+        }
+
+        // elementary weight Phi_i(t): 1 for the leaf, prod_k (A Phi(t_k))_i otherwise
+        fn weights(&self, tableau: &ButcherTableau) -> Vec<f64> {
+            let s = tableau.num_of_stages();
+            let mut ret = vec![1.0; s];
+            for child in self.0.iter() {
+                let phi = child.weights(tableau);
+                for (i, r) in ret.iter_mut().enumerate() {
+                    *r *= (0..i).map(|j| tableau.a[i][j] * phi[j]).sum::<f64>();
+                }
+            }
+            ret
+        }
+    }
+
+    // all rooted trees of order 1..=max_order (1, 1, 2, 4, 9, 20, 48, 115, 286, ...)
+    fn trees(max_order: usize) -> Vec<Vec<Tree>> {
+        let mut ret = vec![vec![Tree(vec![])]];
+        for _ in 1..max_order {
+            let set: BTreeSet<Tree> = ret.last().unwrap().iter().flat_map(|t| t.grow()).collect();
+            ret.push(set.into_iter().collect());
+        }
+        ret
+    }
+
+    // largest p such that sum_i b_i Phi_i(t) = 1 / gamma(t) for every tree up to order p
+    fn order_of(tableau: &ButcherTableau, b: &[f64], all_trees: &[Vec<Tree>]) -> usize {
+        for (p, trees) in all_trees.iter().enumerate() {
+            for t in trees.iter() {
+                let phi = t.weights(tableau);
+                let sum: f64 = b.iter().zip(phi.iter()).map(|(b, phi)| b * phi).sum();
+                if (sum - 1.0 / t.density()).abs() > 1.0e-13 {
+                    return p;
+                }
+            }
+        }
+        all_trees.len()
+    }
+
+    fn all_tableaux() -> Vec<ButcherTableau> {
+        vec![
+            ButcherTableau::rk4(),
+            ButcherTableau::bogacki_shampine32(),
+            ButcherTableau::cash_karp45(),
+            ButcherTableau::dormand_prince54(),
+            ButcherTableau::verner65(),
+            ButcherTableau::fehlberg78(),
+            ButcherTableau::dormand_prince853(),
+        ]
+    }
+
+    #[test]
+    fn number_of_trees() {
+        let count: Vec<usize> = trees(9).iter().map(|t| t.len()).collect();
+        assert_eq!(count, vec![1, 1, 2, 4, 9, 20, 48, 115, 286]);
+    }
+
+    #[test]
+    fn shape() {
+        for tableau in all_tableaux() {
+            let s = tableau.num_of_stages();
+            assert_eq!(tableau.a.len(), s);
+            assert_eq!(tableau.b.len(), s);
+            for (i, row) in tableau.a.iter().enumerate() {
+                assert_eq!(row.len(), i);
+                // c_i = sum_j a_ij
+                approx::assert_abs_diff_eq!(
+                    row.iter().sum::<f64>(),
+                    tableau.c[i],
+                    epsilon = 1.0e-14
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn order_conditions() {
+        // one order beyond the highest one, to see that the conditions do fail there
+        let all_trees = trees(9);
+        for tableau in all_tableaux() {
+            let p = tableau.order;
+            assert_eq!(order_of(&tableau, &tableau.b, &all_trees[..p + 1]), p);
+            if let Some(b_hat) = &tableau.b_hat {
+                let q = tableau.embedded_order;
+                assert_eq!(order_of(&tableau, b_hat, &all_trees[..q + 1]), q);
+            }
+            if let Some(b_check) = &tableau.b_check {
+                let q = tableau.check_order;
+                assert_eq!(order_of(&tableau, b_check, &all_trees[..q + 1]), q);
+            }
+        }
+    }
+}
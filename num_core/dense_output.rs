@@ -0,0 +1,94 @@
+//
+// Continuous extension (dense output) of one accepted Runge–Kutta step.
+//
+// The polynomial is kept in the nested form of the codes DOPRI5 and DOP853
+//   r_1 + s (r_2 + s1 (r_3 + s (r_4 + s1 (r_5 + s (r_6 + ...)))))
+// with s = (t - time) / dt and s1 = 1 - s. r_1 .. r_4 make the cubic Hermite
+// interpolant of both ends, the higher coefficients are method specific.
+//
+use crate::num_core::smp_vector::SmpVector;
+
+pub struct DenseOutput {
+    time: f64,
+    end_time: f64,
+    coefficients: Vec<SmpVector>,
+}
+
+impl DenseOutput {
+    // Step x0 -> x1 with k_first = dt f(x0) and k_last = dt f(x1); the method
+    // specific coefficients r_5, r_6, ... are appended to the Hermite part.
+    pub fn new(
+        x0: &SmpVector,
+        x1: &SmpVector,
+        k_first: &SmpVector,
+        k_last: &SmpVector,
+        higher: Vec<SmpVector>,
+    ) -> DenseOutput {
+        let diff = x1 - x0;
+        let bspl = k_first - &diff;
+        let mut coefficients = vec![
+            x0.clone(),
+            diff.clone(),
+            bspl.clone(),
+            &(&diff - k_last) - &bspl,
+        ];
+        coefficients.extend(higher);
+        DenseOutput {
+            time: x0.time,
+            end_time: x1.time,
+            coefficients,
+        }
+    }
+
+    // between both ends of the step, forward or backward in time
+    pub fn contains(&self, t: f64) -> bool {
+        (t - self.time) * (t - self.end_time) <= 0.0
+    }
+
+    pub fn evaluate(&self, t: f64) -> SmpVector {
+        let s = (t - self.time) / (self.end_time - self.time);
+        let s1 = 1.0 - s;
+        let n = self.coefficients.len();
+        let mut ret = self.coefficients[n - 1].clone();
+        for i in (1..n - 1).rev() {
+            // s1 after r_2, r_4, ... and s after r_3, r_5, ...
+            let factor = if i % 2 == 1 { s1 } else { s };
+            ret = &self.coefficients[i] + &(factor * &ret);
+        }
+        ret = &self.coefficients[0] + &(s * &ret);
+        ret.time = t;
+        ret
+    }
+}
+
+#[cfg(test)]
+pub mod dense_output_tests {
+    extern crate approx;
+    use crate::num_core::dense_output::DenseOutput;
+    use crate::num_core::smp_vector::SmpVector;
+
+    fn scalar(time: f64, v: f64) -> SmpVector {
+        SmpVector { time, vec: vec![v] }
+    }
+
+    #[test]
+    fn cubic_hermite() {
+        // x(t) = t^3 on [1, 3] is reproduced by the Hermite part alone
+        let (t0, t1) = (1.0, 3.0);
+        let dt = t1 - t0;
+        let dense = DenseOutput::new(
+            &scalar(t0, 1.0),
+            &scalar(t1, 27.0),
+            &scalar(t0, dt * 3.0),
+            &scalar(t1, dt * 27.0),
+            vec![],
+        );
+        for i in 0..=8 {
+            let t = t0 + 0.25 * i as f64;
+            let x = dense.evaluate(t);
+            approx::assert_abs_diff_eq!(x.time, t);
+            approx::assert_relative_eq!(x.vec[0], t * t * t, max_relative = 1.0e-14);
+        }
+        assert!(dense.contains(t0) && dense.contains(t1) && !dense.contains(3.5));
+    }
+}
@@ -0,0 +1,290 @@
+//
+// Conserved quantities of the N-body state and their drift over a run.
+//
+// All quantities are in the input units (Units) and ignore the spins.
+// In Mode: TwoBody the state is the relative coordinate in the center-of-mass
+// frame, so the momentum and the center of mass vanish.
+//
+use crate::num_core::n_body::{Mode, NBody, DIM};
+use crate::num_core::smp_vector::SmpVector;
+use std::io::{self, Write};
+
+#[inline(always)]
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b.iter()).map(|(a, b)| a * b).sum()
+}
+
+#[inline(always)]
+fn distance(pos: &[f64], i: usize, j: usize) -> f64 {
+    let mut r2 = 0.0;
+    for d in 0..DIM {
+        let dx = pos[DIM * i + d] - pos[DIM * j + d];
+        r2 += dx * dx;
+    }
+    r2.sqrt()
+}
+
+impl NBody {
+    // (reduced mass, total mass) of the binary in Mode: TwoBody
+    fn reduced_mass(&self) -> (f64, f64) {
+        let m = self.mass[0] + self.mass[1];
+        (self.mass[0] * self.mass[1] / m, m)
+    }
+
+    // Newtonian energy: sum m v^2 / 2 - sum_{i<j} G m_i m_j / r_ij
+    pub fn newton_energy(&self, x: &SmpVector) -> f64 {
+        let g = self.units.gravitational_constant();
+        let n = DIM * self.num_of_points();
+        let (pos, vel) = (&x.vec[..n], &x.vec[n..2 * n]);
+        if self.mode == Mode::TwoBody {
+            let (mu, m) = self.reduced_mass();
+            return mu * (0.5 * dot(vel, vel) - g * m / dot(pos, pos).sqrt());
+        }
+        let mut ret = 0.0;
+        for i in 0..self.number_of_bodies {
+            let v = &vel[DIM * i..DIM * (i + 1)];
+            ret += 0.5 * self.mass[i] * dot(v, v);
+            for j in (i + 1)..self.number_of_bodies {
+                ret -= g * self.mass[i] * self.mass[j] / distance(pos, i, j);
+            }
+        }
+        ret
+    }
+
+    // Newtonian energy + the 1PN (Einstein–Infeld–Hoffmann) correction
+    //   sum_a 3/8 m_a v_a^4
+    //   + 1/2 sum_a sum_{b!=a} (G m_a m_b / r_ab) [3 v_a^2 - 7/2 v_a.v_b - 1/2 (n_ab.v_a)(n_ab.v_b)]
+    //   + 1/2 sum_a sum_{b!=a} sum_{c!=a} G^2 m_a m_b m_c / (r_ab r_ac),
+    // all divided by c^2. Mode: TwoBody uses its center-of-mass form.
+    pub fn energy_1pn(&self, x: &SmpVector) -> f64 {
+        let g = self.units.gravitational_constant();
+        let inv_c2 = 1.0 / self.light_speed().powi(2);
+        let n = DIM * self.num_of_points();
+        let (pos, vel) = (&x.vec[..n], &x.vec[n..2 * n]);
+        if self.mode == Mode::TwoBody {
+            let (mu, m) = self.reduced_mass();
+            let nu = mu / m;
+            let gm = g * m;
+            let r = dot(pos, pos).sqrt();
+            let rd = dot(pos, vel) / r;
+            let v2 = dot(vel, vel);
+            let e_1pn = mu
+                * (0.375 * (1.0 - 3.0 * nu) * v2 * v2
+                    + 0.5 * gm / r * ((3.0 + nu) * v2 + nu * rd * rd)
+                    + 0.5 * gm * gm / (r * r));
+            return self.newton_energy(x) + e_1pn * inv_c2;
+        }
+        let num = self.number_of_bodies;
+        let mass = &self.mass;
+        let mut e_1pn = 0.0;
+        for a in 0..num {
+            let v_a = &vel[DIM * a..DIM * (a + 1)];
+            let v_a2 = dot(v_a, v_a);
+            e_1pn += 0.375 * mass[a] * v_a2 * v_a2;
+            for b in (0..num).filter(|&b| b != a) {
+                let v_b = &vel[DIM * b..DIM * (b + 1)];
+                let r_ab = distance(pos, a, b);
+                let mut n_ab = [0.0; DIM];
+                for d in 0..DIM {
+                    n_ab[d] = (pos[DIM * a + d] - pos[DIM * b + d]) / r_ab;
+                }
+                e_1pn += 0.5 * g * mass[a] * mass[b] / r_ab
+                    * (3.0 * v_a2 - 3.5 * dot(v_a, v_b) - 0.5 * dot(&n_ab, v_a) * dot(&n_ab, v_b));
+                for c in (0..num).filter(|&c| c != a) {
+                    e_1pn +=
+                        0.5 * g * g * mass[a] * mass[b] * mass[c] / (r_ab * distance(pos, a, c));
+                }
+            }
+        }
+        self.newton_energy(x) + e_1pn * inv_c2
+    }
+
+    // total linear momentum sum m v
+    pub fn linear_momentum(&self, x: &SmpVector) -> [f64; DIM] {
+        let mut ret = [0.0; DIM];
+        if self.mode == Mode::TwoBody {
+            return ret;
+        }
+        let n = DIM * self.num_of_points();
+        for i in 0..self.number_of_bodies {
+            for (d, p) in ret.iter_mut().enumerate() {
+                *p += self.mass[i] * x.vec[n + DIM * i + d];
+            }
+        }
+        ret
+    }
+
+    // total orbital angular momentum sum m x * v (mu x * v in Mode: TwoBody)
+    pub fn angular_momentum(&self, x: &SmpVector) -> [f64; DIM] {
+        let n = DIM * self.num_of_points();
+        let mass = match self.mode {
+            Mode::NBody => self.mass.clone(),
+            Mode::TwoBody => vec![self.reduced_mass().0],
+        };
+        let mut ret = [0.0; DIM];
+        for (i, m) in mass.iter().enumerate() {
+            let p = &x.vec[DIM * i..DIM * (i + 1)];
+            let v = &x.vec[n + DIM * i..n + DIM * (i + 1)];
+            ret[0] += m * (p[1] * v[2] - p[2] * v[1]);
+            ret[1] += m * (p[2] * v[0] - p[0] * v[2]);
+            ret[2] += m * (p[0] * v[1] - p[1] * v[0]);
+        }
+        ret
+    }
+
+    // center-of-mass position sum m x / sum m
+    pub fn center_of_mass(&self, x: &SmpVector) -> [f64; DIM] {
+        let mut ret = [0.0; DIM];
+        if self.mode == Mode::TwoBody {
+            return ret;
+        }
+        let total: f64 = self.mass.iter().sum();
+        for i in 0..self.number_of_bodies {
+            for (d, r) in ret.iter_mut().enumerate() {
+                *r += self.mass[i] * x.vec[DIM * i + d] / total;
+            }
+        }
+        ret
+    }
+}
+
+fn norm(a: &[f64]) -> f64 {
+    dot(a, a).sqrt()
+}
+
+fn difference(a: &[f64; DIM], b: &[f64; DIM]) -> f64 {
+    let mut ret = [0.0; DIM];
+    for d in 0..DIM {
+        ret[d] = a[d] - b[d];
+    }
+    norm(&ret)
+}
+
+// Stream of the drift of the conserved quantities with respect to the initial state.
+pub struct Diagnostics {
+    energy_newton: f64,
+    energy_1pn: f64,
+    momentum: [f64; DIM],
+    angular_momentum: [f64; DIM],
+    center_of_mass: [f64; DIM],
+}
+
+impl Diagnostics {
+    pub fn new(n_body: &NBody, x0: &SmpVector) -> Diagnostics {
+        Diagnostics {
+            energy_newton: n_body.newton_energy(x0),
+            energy_1pn: n_body.energy_1pn(x0),
+            momentum: n_body.linear_momentum(x0),
+            angular_momentum: n_body.angular_momentum(x0),
+            center_of_mass: n_body.center_of_mass(x0),
+        }
+    }
+
+    pub fn write_header(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(
+            out,
+            "# time energy_newton energy_1pn rel_drift_energy_newton rel_drift_energy_1pn \
+             drift_momentum rel_drift_angular_momentum drift_center_of_mass"
+        )
+    }
+
+    // Energies, relative drifts of the energies and |L|, and the absolute
+    // drifts of P and of the center of mass (which may both start at zero).
+    pub fn write(&self, out: &mut dyn Write, n_body: &NBody, x: &SmpVector) -> io::Result<()> {
+        let energy_newton = n_body.newton_energy(x);
+        let energy_1pn = n_body.energy_1pn(x);
+        let drift_angular_momentum =
+            difference(&n_body.angular_momentum(x), &self.angular_momentum);
+        writeln!(
+            out,
+            "{:.14} {:.14e} {:.14e} {:.6e} {:.6e} {:.6e} {:.6e} {:.6e}",
+            x.time,
+            energy_newton,
+            energy_1pn,
+            ((energy_newton - self.energy_newton) / self.energy_newton).abs(),
+            ((energy_1pn - self.energy_1pn) / self.energy_1pn).abs(),
+            difference(&n_body.linear_momentum(x), &self.momentum),
+            drift_angular_momentum / norm(&self.angular_momentum),
+            difference(&n_body.center_of_mass(x), &self.center_of_mass),
+        )
+    }
+}
+
+#[cfg(test)]
+pub mod diagnostics_tests {
+    extern crate approx;
+    use crate::num_core::diagnostics::Diagnostics;
+    use crate::num_core::dop54::DOP54;
+    use crate::num_core::n_body::{Mode, NBody};
+
+    fn binary() -> NBody {
+        let yaml = "
+SettingName: \"binary\"
+NumberOfBodies: 2
+Mass: [0.7, 0.3]
+Position: [[0.21, 0.1, 0.0], [-0.49, -0.2333333333333333, 0.0]]
+Velocity: [[-0.09, 0.27, 0.03], [0.21, -0.63, -0.07]]
+SpeedOfLight: 20.0
+";
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn two_body_limit() {
+        // the N-body sums reduce to the center-of-mass expressions
+        let mut n_body = binary();
+        let x = n_body.to_smp_vector();
+        let energy_newton = n_body.newton_energy(&x);
+        let energy_1pn = n_body.energy_1pn(&x);
+        let l = n_body.angular_momentum(&x);
+        for p in n_body.linear_momentum(&x).iter() {
+            approx::assert_abs_diff_eq!(*p, 0.0, epsilon = 1.0e-15);
+        }
+        for r in n_body.center_of_mass(&x).iter() {
+            approx::assert_abs_diff_eq!(*r, 0.0, epsilon = 1.0e-15);
+        }
+
+        n_body.mode = Mode::TwoBody;
+        let x = n_body.to_smp_vector();
+        approx::assert_relative_eq!(
+            n_body.newton_energy(&x),
+            energy_newton,
+            max_relative = 1.0e-14
+        );
+        approx::assert_relative_eq!(n_body.energy_1pn(&x), energy_1pn, max_relative = 1.0e-14);
+        let l_rel = n_body.angular_momentum(&x);
+        for d in 0..3 {
+            approx::assert_abs_diff_eq!(l_rel[d], l[d], epsilon = 1.0e-15);
+        }
+    }
+
+    #[test]
+    fn energy_1pn_conservation() {
+        // a 1PN run keeps the 1PN energy, not the Newtonian one (v / c ~ 0.05)
+        let mut n_body = binary();
+        n_body.post_newtonian.pn1 = true;
+        assert!(n_body.check().is_ok());
+        let mut x = n_body.to_smp_vector();
+        let diagnostics = Diagnostics::new(&n_body, &x);
+
+        let mut sol = DOP54::new(1.0e-3, 1.0e-2, 1.0e-13, 1.01, 0.9);
+        let func = n_body.eom();
+        while x.time < 10.0 {
+            x = sol.solve(func, &x);
+        }
+        let mut out = Vec::new();
+        diagnostics.write_header(&mut out).unwrap();
+        diagnostics.write(&mut out, &n_body, &x).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let drift: Vec<f64> = out
+            .lines()
+            .nth(1)
+            .unwrap()
+            .split_whitespace()
+            .map(|v| v.parse().unwrap())
+            .collect();
+        // the truncated EIH equations keep the 1PN energy up to O(1/c^4)
+        assert!(drift[3] > 1.0e-3);
+        assert!(drift[4] < 0.02 * drift[3]);
+    }
+}
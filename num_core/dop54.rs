@@ -1,65 +1,48 @@
 // Dormand–Prince method 4(5)
+//
+// Tableau: ButcherTableau::dormand_prince54, stepper: ExplicitRK.
+// Dense output: the continuous extension of order 4 of Dormand and Prince
+// (Hairer, Nørsett, Wanner, Sec. II.6, code DOPRI5); the last stage is f at the
+// end of the step, so it costs no further evaluation.
+//
+// The stepper is written out here: the last stage f(x1) is the first one of the
+// next step (First Same As Last), a rejected step keeps f(x0) for the retry, and
+// the stages live in buffers that are updated in place.
+use crate::num_core::butcher_tableau::ButcherTableau;
+use crate::num_core::dense_output::DenseOutput;
+use crate::num_core::explicit_rk::ExplicitRK;
+use crate::num_core::integrator::Integrator;
+use crate::num_core::step_control::{PIController, StepResult, StepStatistics};
 use crate::SmpVector;
 
+const STAGES: usize = 7;
+
+// d_1 .. d_7 of the interpolation polynomial
+const D: [f64; 7] = [
+    -12715105075.0 / 11282082432.0,
+    0.0,
+    87487479700.0 / 32700410799.0,
+    -10690763975.0 / 1880347072.0,
+    701980252875.0 / 199316789632.0,
+    -1453857185.0 / 822651844.0,
+    69997945.0 / 29380423.0,
+];
+
 pub struct DOP54 {
-    // deltaT
-    dt: f64,
-    abs_tol: f64,
-    // c: Butcher tableau
-    // c1: T,
-    c2: f64,
-    c3: f64,
-    c4: f64,
-    c5: f64,
-    c6: f64,
-    c7: f64,
-    // A: Butcher tableau
-    a21: f64,
-    a31: f64,
-    a32: f64,
-    a41: f64,
-    a42: f64,
-    a43: f64,
-    a51: f64,
-    a52: f64,
-    a53: f64,
-    a54: f64,
-    a61: f64,
-    a62: f64,
-    a63: f64,
-    a64: f64,
-    a65: f64,
-    a71: f64,
-    a72: f64,
-    a73: f64,
-    a74: f64,
-    a75: f64,
-    a76: f64,
-    // b1: Butcher tableau
-    b11: f64,
-    b12: f64,
-    b13: f64,
-    b14: f64,
-    b15: f64,
-    b16: f64,
-    b17: f64,
-    // b1: Butcher tableau
-    b21: f64,
-    b22: f64,
-    b23: f64,
-    b24: f64,
-    b25: f64,
-    b26: f64,
-    b27: f64,
-    // dt scale factor
-    dt_scale_up_factor: f64,
-    dt_scale_down_factor: f64,
-    dt_max: f64,
-    // solver order
-    order: f64,
-    // for debug
-    num_dt_switch: i64,
-    num_steps: i64,
+    rk: ExplicitRK,
+    dense_output: bool,
+    // interpolation polynomial of the last accepted step
+    last_step: Option<DenseOutput>,
+    // f_i, k_i = dt f_i and the argument of f, allocated at the first step
+    f: Vec<SmpVector>,
+    k: Vec<SmpVector>,
+    xi: SmpVector,
+    err: SmpVector,
+    // the state of f_1 = f(x) (end of the last accepted step, or start of a
+    // rejected one); NaN time when there is none. Steps in a row are assumed
+    // to pass the same func; set_delta_t (and so set_direction) and
+    // set_dense_output drop it, so a new func starts from there.
+    x_f1: SmpVector,
 }
 
 impl DOP54 {
@@ -69,131 +52,50 @@ impl DOP54 {
         dt_max1: f64,
         abs_tol1: f64,
         dt_scale_up_factor1: f64,
-        dt_sale_down_factor1: f64,
+        dt_scale_down_factor1: f64,
     ) -> DOP54 {
         DOP54 {
-            // c: Butcher tableau
-            dt: dt1,
-            abs_tol: abs_tol1,
-            //c1: 0.0,
-            c2: 1.0 / 5.0,
-            c3: 3.0 / 10.0,
-            c4: 4.0 / 5.0,
-            c5: 8.0 / 9.0,
-            c6: 1.0,
-            c7: 1.0,
-            // A: Butcher tableau
-            a21: 1.0 / 5.0,
-            a31: 3.0 / 40.0,
-            a32: 9.0 / 40.0,
-            a41: 44.0 / 45.0,
-            a42: -56.0 / 15.0,
-            a43: 32.0 / 9.0,
-            a51: 19372.0 / 6561.0,
-            a52: -25360.0 / 2187.0,
-            a53: 64448.0 / 6561.0,
-            a54: -212.0 / 729.0,
-            a61: 9017.0 / 3168.0,
-            a62: -355.0 / 33.0,
-            a63: 46732.0 / 5247.0,
-            a64: 49.0 / 176.0,
-            a65: -5103.0 / 18656.0,
-            a71: 35.0 / 384.0,
-            a72: 0.0,
-            a73: 500.0 / 1113.0,
-            a74: 125.0 / 192.0,
-            a75: -2187.0 / 6784.0,
-            a76: 11.0 / 84.0,
-            // b1: Butcher tableau
-            b11: 35.0 / 384.0,
-            b12: 0.0,
-            b13: 500.0 / 1113.0,
-            b14: 125.0 / 192.0,
-            b15: -2187.0 / 6784.0,
-            b16: 11.0 / 84.0,
-            b17: 0.0,
-            // b1: Butcher tableau
-            b21: 5179.0 / 57600.0,
-            b22: 0.0,
-            b23: 7571.0 / 16695.0,
-            b24: 393.0 / 640.0,
-            b25: -92097.0 / 339200.0,
-            b26: 187.0 / 2100.0,
-            b27: 1.0 / 40.0,
-            // dt scale factor
-            dt_scale_down_factor: dt_sale_down_factor1,
-            dt_scale_up_factor: dt_scale_up_factor1,
-            dt_max: dt_max1,
-            // solver order
-            order: 5.0,
-            // for debug
-            num_dt_switch: 0,
-            num_steps: 0,
+            rk: ExplicitRK::new(
+                ButcherTableau::dormand_prince54(),
+                dt1,
+                dt_max1,
+                abs_tol1,
+                dt_scale_up_factor1,
+                dt_scale_down_factor1,
+            ),
+            dense_output: false,
+            last_step: None,
+            f: Vec::new(),
+            k: Vec::new(),
+            xi: SmpVector::set_zero_vector(0),
+            err: SmpVector::set_zero_vector(0),
+            x_f1: SmpVector {
+                time: f64::NAN,
+                vec: Vec::new(),
+            },
         }
     }
 
+    // keep the interpolation polynomial of every accepted step
+    #[allow(dead_code)]
+    pub fn set_dense_output(&mut self, dense_output: bool) {
+        self.dense_output = dense_output;
+        self.last_step = None;
+        self.x_f1.time = f64::NAN;
+    }
+
+    // 5th order solution (propagated)
+    #[allow(dead_code)]
     #[inline(always)]
     pub fn solve_5th_order(
         &self,
         func: impl Fn(&SmpVector) -> SmpVector,
         x: &SmpVector,
     ) -> SmpVector {
-        // 1
-        let k1 = self.dt * &func(x);
-        // 2
-        let t21 = self.a21 * &k1;
-        let mut t22 = x + &t21;
-        t22.time = x.time + self.c2 * self.dt;
-        let k2 = self.dt * &func(&t22);
-        // 3
-        let t31 = &(self.a31 * &k1) + &(self.a32 * &k2);
-        let mut t32 = x + &t31;
-        t32.time = x.time + self.c3 * self.dt;
-        let k3 = self.dt * &func(&t32);
-        // 4
-        let t41 = &(self.a41 * &k1) + &(self.a42 * &k2);
-        let t42 = &t41 + &(self.a43 * &k3);
-        let mut t45 = x + &t42;
-        t45.time = x.time + self.c4 * self.dt;
-        let k4 = self.dt * &func(&t45);
-        // 5
-        let t51 = &(self.a51 * &k1) + &(self.a52 * &k2);
-        let t52 = &t51 + &(self.a53 * &k3);
-        let t53 = &t52 + &(self.a54 * &k4);
-        let mut t54 = x + &t53;
-        t54.time = x.time + self.c5 * self.dt;
-        let k5 = self.dt * &func(&t54);
-        // 6
-        let t61 = &(self.a61 * &k1) + &(self.a62 * &k2);
-        let t62 = &t61 + &(self.a63 * &k3);
-        let t63 = &t62 + &(self.a64 * &k4);
-        let t64 = &t63 + &(self.a65 * &k5);
-        let mut t65 = x + &t64;
-        t65.time = x.time + self.c6 * self.dt;
-        let k6 = self.dt * &func(&t65);
-        // 7
-        let t71 = &(self.a71 * &k1) + &(self.a72 * &k2);
-        let t72 = &t71 + &(self.a73 * &k3);
-        let t73 = &t72 + &(self.a74 * &k4);
-        let t74 = &t73 + &(self.a75 * &k5);
-        let t75 = &t74 + &(self.a76 * &k6);
-        let mut t76 = x + &t75;
-        t76.time = x.time + self.c7 * self.dt;
-        let k7 = self.dt * &func(&t76);
-        // 5th order sol.
-        let s1 = &(self.b11 * &k1) + &(self.b12 * &k2);
-        let s2 = &s1 + &(self.b13 * &k3);
-        let s3 = &s2 + &(self.b14 * &k4);
-        let s4 = &s3 + &(self.b15 * &k5);
-        let s5 = &s4 + &(self.b16 * &k6);
-        let s6 = &s5 + &(self.b17 * &k7);
-        //
-        SmpVector {
-            time: self.dt + x.time,
-            vec: (x + &s6).vec,
-        }
+        self.rk.solve_fixed(func, x)
     }
 
+    // 4th order solution (error estimate)
     #[allow(dead_code)]
     #[inline(always)]
     pub fn solve_4th_order(
@@ -201,202 +103,209 @@ impl DOP54 {
         func: impl Fn(&SmpVector) -> SmpVector,
         x: &SmpVector,
     ) -> SmpVector {
-        // 1
-        let k1 = self.dt * &func(x);
-        // 2
-        let t21 = self.a21 * &k1;
-        let mut t22 = x + &t21;
-        t22.time = x.time + self.c2 * self.dt;
-        let k2 = self.dt * &func(&t22);
-        // 3
-        let t31 = &(self.a31 * &k1) + &(self.a32 * &k2);
-        let mut t32 = x + &t31;
-        t32.time = x.time + self.c3 * self.dt;
-        let k3 = self.dt * &func(&t32);
-        // 4
-        let t41 = &(self.a41 * &k1) + &(self.a42 * &k2);
-        let t42 = &t41 + &(self.a43 * &k3);
-        let mut t43 = x + &t42;
-        t43.time = x.time + self.c4 * self.dt;
-        let k4 = self.dt * &func(&t43);
-        // 5
-        let t51 = &(self.a51 * &k1) + &(self.a52 * &k2);
-        let t52 = &t51 + &(self.a53 * &k3);
-        let t53 = &t52 + &(self.a54 * &k4);
-        let mut t54 = x + &t53;
-        t54.time = x.time + self.c5 * self.dt;
-        let k5 = self.dt * &func(&t54);
-        // 6
-        let t61 = &(self.a61 * &k1) + &(self.a62 * &k2);
-        let t62 = &t61 + &(self.a63 * &k3);
-        let t63 = &t62 + &(self.a64 * &k4);
-        let t64 = &t63 + &(self.a65 * &k5);
-        let mut t65 = x + &t64;
-        t65.time = x.time + self.c6 * self.dt;
-        let k6 = self.dt * &func(&t65);
-        // 7
-        let t71 = &(self.a71 * &k1) + &(self.a72 * &k2);
-        let t72 = &t71 + &(self.a73 * &k3);
-        let t73 = &t72 + &(self.a74 * &k4);
-        let t74 = &t73 + &(self.a75 * &k5);
-        let t75 = &t74 + &(self.a76 * &k6);
-        let mut t76 = x + &t75;
-        t76.time = x.time + self.c7 * self.dt;
-        let k7 = self.dt * &func(&t76);
-        // 5th order sol.
-        let s1 = &(self.b21 * &k1) + &(self.b22 * &k2);
-        let s2 = &s1 + &(self.b23 * &k3);
-        let s3 = &s2 + &(self.b24 * &k4);
-        let s4 = &s3 + &(self.b25 * &k5);
-        let s5 = &s4 + &(self.b26 * &k6);
-        let s6 = &s5 + &(self.b27 * &k7);
-        //
-        SmpVector {
-            time: self.dt + x.time,
-            vec: (x + &s6).vec,
-        }
+        self.rk.solve_embedded(func, x)
     }
 
+    // Adaptive step; a rejected one returns x (see Integrator::adaptive_step).
     #[allow(dead_code)]
     #[inline(always)]
     pub fn solve(&mut self, func: impl Fn(&SmpVector) -> SmpVector, x: &SmpVector) -> SmpVector {
-        // 1
-        let k1 = self.dt * &func(x);
-        // 2
-        let t21 = self.a21 * &k1;
-        let mut t22 = x + &t21;
-        t22.time = x.time + self.c2 * self.dt;
-        let k2 = self.dt * &func(&t22);
-        // 3
-        let t31 = &(self.a31 * &k1) + &(self.a32 * &k2);
-        let mut t32 = x + &t31;
-        t32.time = x.time + self.c3 * self.dt;
-        let k3 = self.dt * &func(&t32);
-        // 4
-        let t41 = &(self.a41 * &k1) + &(self.a42 * &k2);
-        let t42 = &t41 + &(self.a43 * &k3);
-        let mut t45 = x + &t42;
-        t45.time = x.time + self.c4 * self.dt;
-        let k4 = self.dt * &func(&t45);
-        // 5
-        let t51 = &(self.a51 * &k1) + &(self.a52 * &k2);
-        let t52 = &t51 + &(self.a53 * &k3);
-        let t53 = &t52 + &(self.a54 * &k4);
-        let mut t54 = x + &t53;
-        t54.time = x.time + self.c5 * self.dt;
-        let k5 = self.dt * &func(&t54);
-        // 6
-        let t61 = &(self.a61 * &k1) + &(self.a62 * &k2);
-        let t62 = &t61 + &(self.a63 * &k3);
-        let t63 = &t62 + &(self.a64 * &k4);
-        let t64 = &t63 + &(self.a65 * &k5);
-        let mut t65 = x + &t64;
-        t65.time = x.time + self.c6 * self.dt;
-        let k6 = self.dt * &func(&t65);
-        // 7
-        let t71 = &(self.a71 * &k1) + &(self.a72 * &k2);
-        let t72 = &t71 + &(self.a73 * &k3);
-        let t73 = &t72 + &(self.a74 * &k4);
-        let t74 = &t73 + &(self.a75 * &k5);
-        let t75 = &t74 + &(self.a76 * &k6);
-        let mut t76 = x + &t75;
-        t76.time = x.time + self.c7 * self.dt;
-        let k7 = self.dt * &func(&t76);
-        // 4th order sol.
-        let s41 = &(self.b21 * &k1) + &(self.b22 * &k2);
-        let s42 = &s41 + &(self.b23 * &k3);
-        let s43 = &s42 + &(self.b24 * &k4);
-        let s44 = &s43 + &(self.b25 * &k5);
-        let s45 = &s44 + &(self.b26 * &k6);
-        let s46 = &s45 + &(self.b27 * &k7);
-        //
-        let x_order_4 = SmpVector {
-            time: self.dt + x.time,
-            vec: (x + &s46).vec,
-        };
-        // 5th order sol.
-        let s51 = &(self.b11 * &k1) + &(self.b12 * &k2);
-        let s52 = &s51 + &(self.b13 * &k3);
-        let s53 = &s52 + &(self.b14 * &k4);
-        let s54 = &s53 + &(self.b15 * &k5);
-        let s55 = &s54 + &(self.b16 * &k6);
-        let s56 = &s55 + &(self.b17 * &k7);
-        //
-        let x_order_5 = SmpVector {
-            time: self.dt + x.time,
-            vec: (x + &s56).vec,
-        };
-        // adaptive dt
-        let err_45 = (&x_order_5 - &x_order_4).norm();
-        //println!("{:.14}", self.num_dt_switch);
-        if err_45 > self.abs_tol {
-            self.dt = self.dt_scale_down_factor
-                * self.dt
-                * (self.abs_tol / err_45).powf(1.0 / self.order);
-            self.num_dt_switch += 1;
-            self.num_steps += 1;
-            x.clone()
-        } else {
-            self.dt *= self.dt_scale_up_factor;
-            if self.dt > self.dt_max {
-                self.dt = self.dt_max;
+        self.try_step(func, x).x
+    }
+
+    // Adaptive step with its outcome.
+    pub fn try_step(
+        &mut self,
+        func: impl Fn(&SmpVector) -> SmpVector,
+        x: &SmpVector,
+    ) -> StepResult {
+        let n = x.vec.len();
+        if self.xi.vec.len() != n {
+            self.f = vec![SmpVector::set_zero_vector(n); STAGES];
+            self.k = vec![SmpVector::set_zero_vector(n); STAGES];
+            self.xi = SmpVector::set_zero_vector(n);
+            self.err = SmpVector::set_zero_vector(n);
+            self.x_f1.time = f64::NAN;
+        }
+        let dt = self.rk.get_delta_t();
+        let tableau = self.rk.get_tableau();
+        let mut evaluations = STAGES as i64 - 1;
+        if !(self.x_f1.time == x.time && self.x_f1.vec == x.vec) {
+            self.f[0] = func(x);
+            self.x_f1.clone_from(x);
+            evaluations += 1;
+        }
+        for i in 0..STAGES {
+            if i > 0 {
+                // x_i = x + sum_j a_ij k_j
+                for (m, xm) in self.xi.vec.iter_mut().enumerate() {
+                    let mut sum = 0.0;
+                    for (aij, kj) in tableau.a[i].iter().zip(self.k.iter()) {
+                        if *aij != 0.0 {
+                            sum += aij * kj.vec[m];
+                        }
+                    }
+                    *xm = x.vec[m] + sum;
+                }
+                self.xi.time = x.time + tableau.c[i] * dt;
+                self.f[i] = func(&self.xi);
+            }
+            for (km, fm) in self.k[i].vec.iter_mut().zip(self.f[i].vec.iter()) {
+                *km = dt * fm;
+            }
+        }
+        // a_7j = b_j: the argument of the last stage is the 5th order solution
+        let mut y = self.xi.clone();
+        y.time = dt + x.time;
+        let b_hat = tableau.b_hat.as_ref().unwrap();
+        for (m, em) in self.err.vec.iter_mut().enumerate() {
+            let mut sum = 0.0;
+            for (bj, kj) in b_hat.iter().zip(self.k.iter()) {
+                if *bj != 0.0 {
+                    sum += bj * kj.vec[m];
+                }
             }
-            self.num_steps += 1;
-            x_order_5
+            *em = y.vec[m] - (x.vec[m] + sum);
+        }
+        let order = (tableau.embedded_order + 1) as f64;
+        let err = self.rk.error_norm(&self.err, x, &y);
+        self.rk.add_evaluations(evaluations);
+        let accepted = self.rk.accept(err, order);
+        let result = |x: SmpVector, dt_proposed: f64| StepResult {
+            x,
+            accepted,
+            error: err,
+            dt_used: dt,
+            dt_proposed,
+        };
+        if !accepted {
+            // f_1 = f(x) is kept for the retry
+            return result(x.clone(), self.rk.get_delta_t());
         }
+        if self.dense_output {
+            let higher = vec![ExplicitRK::weighted_sum(&D, &self.k)];
+            self.last_step = Some(DenseOutput::new(x, &y, &self.k[0], &self.k[6], higher));
+        }
+        // FSAL
+        self.f.swap(0, STAGES - 1);
+        self.x_f1.clone_from(&y);
+        result(y, self.rk.get_delta_t())
+    }
+
+    // State at time t within the last accepted step (None outside of it or
+    // without set_dense_output(true)).
+    #[allow(dead_code)]
+    pub fn dense_output(&self, t: f64) -> Option<SmpVector> {
+        let step = self.last_step.as_ref()?;
+        if !step.contains(t) {
+            return None;
+        }
+        Some(step.evaluate(t))
     }
 
     #[allow(dead_code)]
     #[inline(always)]
     pub fn get_num_of_dt_switch(&self) -> i64 {
-        self.num_dt_switch
+        self.rk.get_num_of_dt_switch()
     }
 
     #[allow(dead_code)]
     #[inline(always)]
     pub fn get_num_of_steps(&self) -> i64 {
-        self.num_steps
+        self.rk.get_num_of_steps()
+    }
+
+    #[allow(dead_code)]
+    pub fn statistics(&self) -> &StepStatistics {
+        self.rk.statistics()
     }
 
     #[allow(dead_code)]
-    #[inline(always)]
     pub fn solve_to_end_time(
         &mut self,
         end_time: f64,
-        func: impl Fn(&SmpVector) -> SmpVector + 'static + Copy,
+        func: impl Fn(&SmpVector) -> SmpVector,
         x: &SmpVector,
     ) -> SmpVector {
-        let mut _y_new = x.clone();
-        let mut y_old = x.clone();
-        loop {
-            _y_new = self.solve(func, &y_old);
-            if _y_new.time > end_time {
-                let to_end = end_time - y_old.time;
-                self.set_delta_t(to_end);
-                _y_new = self.solve_5th_order(func, &y_old);
-                println!("{:.14} {:.14}", _y_new.time, _y_new.vec[0]);
-                break;
-            }
-            y_old = _y_new.clone();
-            println!("{:.14} {:.14}", _y_new.time, _y_new.vec[0]);
-        }
-        _y_new
+        self.integrate_to(end_time, &func, x)
     }
 
+    #[allow(dead_code)]
     pub fn set_delta_t(&mut self, dt: f64) {
-        self.dt = dt;
+        self.rk.set_delta_t(dt);
+        self.x_f1.time = f64::NAN;
     }
 
     #[allow(dead_code)]
     pub fn get_delta_t(&self) -> f64 {
-        self.dt
+        self.rk.get_delta_t()
+    }
+
+    // scaled atol/rtol error norm and PI step control instead of the scale factors
+    #[allow(dead_code)]
+    pub fn set_controller(&mut self, controller: PIController) {
+        self.rk.set_controller(controller);
+    }
+}
+
+impl Integrator for DOP54 {
+    fn step(&self, func: &dyn Fn(&SmpVector) -> SmpVector, x: &SmpVector) -> SmpVector {
+        self.rk.step(func, x)
+    }
+
+    fn adaptive_step(
+        &mut self,
+        func: &dyn Fn(&SmpVector) -> SmpVector,
+        x: &SmpVector,
+    ) -> SmpVector {
+        self.solve(func, x)
+    }
+
+    fn set_delta_t(&mut self, dt: f64) {
+        DOP54::set_delta_t(self, dt)
+    }
+
+    fn get_delta_t(&self) -> f64 {
+        self.rk.get_delta_t()
+    }
+
+    fn get_num_of_steps(&self) -> i64 {
+        self.rk.get_num_of_steps()
+    }
+
+    fn get_num_of_dt_switch(&self) -> i64 {
+        self.rk.get_num_of_dt_switch()
+    }
+
+    fn set_controller(&mut self, controller: PIController) {
+        self.rk.set_controller(controller)
+    }
+
+    fn statistics(&self) -> &StepStatistics {
+        self.rk.statistics()
+    }
+
+    fn set_dense_output(&mut self, dense_output: bool) -> Result<(), &'static str> {
+        DOP54::set_dense_output(self, dense_output);
+        Ok(())
+    }
+
+    fn has_dense_output(&self) -> bool {
+        self.dense_output
+    }
+
+    fn dense_output(&self, t: f64) -> Option<SmpVector> {
+        DOP54::dense_output(self, t)
     }
 }
 
 #[cfg(test)]
 pub mod dop54_tests {
     use crate::num_core::dop54::DOP54;
+    use crate::num_core::n_body::NBody;
     use crate::num_core::smp_vector::SmpVector;
+    use crate::num_core::step_control::{PIController, Tolerance};
+    use crate::num_core::units::{ASTRONOMICAL_UNIT_SI, GRAVITATIONAL_CONSTANT_SI, SOLAR_GM_SI};
 
     #[inline(always)]
     pub fn func1(x: &SmpVector) -> SmpVector {
@@ -432,8 +341,8 @@ pub mod dop54_tests {
             }
         }
 
-        assert_eq!(sol.num_dt_switch, 98);
-        assert_eq!(sol.num_steps, 4661);
+        assert_eq!(sol.get_num_of_dt_switch(), 98);
+        assert_eq!(sol.get_num_of_steps(), 4661);
         approx::assert_abs_diff_eq!(y1.time, 10.00061711082885, epsilon = 1.0e-14);
         approx::assert_abs_diff_eq!(y1.vec[0], 22040.062760356937, epsilon = 1.0e-14);
     }
@@ -502,4 +411,156 @@ pub mod dop54_tests {
         //
         approx::assert_abs_diff_eq!(y1.vec[99], std::f64::consts::E, epsilon = 1.0e-13);
     }
+
+    #[test]
+    fn mixed_scales() {
+        extern crate approx;
+        // the Earth (as a test mass) around the Sun in SI units: |x| ~ 1e11 m, |v| ~ 3e4 m/s
+        let r = ASTRONOMICAL_UNIT_SI;
+        let v = (SOLAR_GM_SI / r).sqrt();
+        let yaml = format!(
+            "
+SettingName: \"sun-earth\"
+NumberOfBodies: 2
+Mass: [{:e}, 1.0]
+Position: [[0.0, 0.0, 0.0], [{:e}, 0.0, 0.0]]
+Velocity: [[0.0, 0.0, 0.0], [0.0, {:e}, 0.0]]
+Units: SI
+",
+            SOLAR_GM_SI / GRAVITATIONAL_CONSTANT_SI,
+            r,
+            v
+        );
+        let n_body: NBody = serde_yaml::from_str(&yaml).unwrap();
+        let x = n_body.to_smp_vector();
+        let period = 2.0 * std::f64::consts::PI * r / v;
+
+        let mut sol = DOP54::new(1.0e3, 1.0e7, 1.0e-12, 1.01, 0.9);
+        // 1 m and 1 um/s, 1e-10 relative
+        let mut atol = vec![1.0; 6];
+        atol.extend(vec![1.0e-6; 6]);
+        let rtol = Tolerance::Scalar(1.0e-10);
+        sol.set_controller(PIController::new(Tolerance::PerComponent(atol), rtol));
+        let y = sol.solve_to_end_time(period, n_body.eom(), &x);
+        approx::assert_relative_eq!(y.vec[3], r, max_relative = 1.0e-8);
+        approx::assert_abs_diff_eq!(y.vec[4] / r, 0.0, epsilon = 1.0e-8);
+        approx::assert_relative_eq!(y.vec[10], v, max_relative = 1.0e-8);
+        assert!(sol.get_num_of_steps() < 1000);
+    }
+
+    #[test]
+    fn dense_output() {
+        extern crate approx;
+        // harmonic oscillator x'' = -x
+        let oscillator = |x: &SmpVector| SmpVector {
+            time: x.time,
+            vec: vec![x.vec[1], -x.vec[0]],
+        };
+        let x = SmpVector {
+            time: 0.0,
+            vec: vec![1.0, 0.0],
+        };
+        // one accepted step of dt of x' = x^2, x = 1 / (1 - t); the error inside
+        // it is O(dt^5) (one order more for linear problems)
+        let square = |x: &SmpVector| SmpVector {
+            time: x.time,
+            vec: vec![x.vec[0] * x.vec[0]],
+        };
+        let interpolation_error = |dt: f64| {
+            let mut sol = DOP54::new(dt, dt, 1.0, 1.0, 1.0);
+            sol.set_dense_output(true);
+            let x = SmpVector::set_one_fill(1);
+            let y = sol.solve(square, &x);
+            assert_eq!(y.time, dt);
+            // both ends are reproduced
+            approx::assert_abs_diff_eq!(sol.dense_output(0.0).unwrap().vec[0], 1.0);
+            approx::assert_abs_diff_eq!(sol.dense_output(dt).unwrap().vec[0], y.vec[0]);
+            assert!(sol.dense_output(1.1 * dt).is_none());
+            let z = sol.dense_output(0.5 * dt).unwrap();
+            (z.vec[0] - 1.0 / (1.0 - 0.5 * dt)).abs()
+        };
+        let ratio = interpolation_error(0.04) / interpolation_error(0.02);
+        approx::assert_relative_eq!(ratio.log2(), 5.0, max_relative = 0.1);
+
+        let mut sol = DOP54::new(0.1, 0.1, 1.0e-12, 1.0, 0.9);
+        assert!(sol.dense_output(0.0).is_none());
+        sol.set_dense_output(true);
+        let mut y = x.clone();
+        while y.time == 0.0 {
+            y = sol.solve(oscillator, &x);
+        }
+        let t = 0.3 * y.time;
+        approx::assert_abs_diff_eq!(
+            sol.dense_output(t).unwrap().vec[1],
+            -t.sin(),
+            epsilon = 1.0e-12
+        );
+        sol.set_dense_output(false);
+        sol.solve(oscillator, &y);
+        assert!(sol.dense_output(t).is_none());
+    }
+
+    #[test]
+    fn first_same_as_last() {
+        extern crate approx;
+        let count = std::cell::Cell::new(0);
+        let counted = |x: &SmpVector| {
+            count.set(count.get() + 1);
+            func1(x)
+        };
+        // rejections from the large initial dt, then accepted steps
+        let mut sol = DOP54::new(1.0, 0.2, 1.0e-12, 1.005, 0.9);
+        let y = sol.solve_to_end_time(1.0, counted, &SmpVector::set_one_fill(2));
+        approx::assert_abs_diff_eq!(y.vec[1], std::f64::consts::E, epsilon = 1.0e-11);
+        assert!(sol.get_num_of_dt_switch() > 0);
+        // f(x0) once and 6 evaluations per step, accepted or rejected; the last
+        // step that lands on the end time starts with a fresh f(x)
+        let steps = sol.get_num_of_steps();
+        assert!(count.get() <= 6 * steps + 2);
+        assert!(count.get() < 7 * steps);
+
+        // a new state invalidates f_1
+        count.set(0);
+        let mut x = y;
+        x.vec[0] = 2.0;
+        let y = sol.solve(counted, &x);
+        assert_eq!(count.get(), 7);
+
+        // so does set_delta_t: another func on the same state starts afresh
+        let decay = |x: &SmpVector| -1.0 * x;
+        let dt = sol.get_delta_t();
+        sol.set_delta_t(dt);
+        let z = sol.solve(decay, &y);
+        let mut fresh = DOP54::new(dt, 0.2, 1.0e-12, 1.005, 0.9);
+        assert_eq!(z.vec, fresh.solve(decay, &y).vec);
+    }
+
+    #[test]
+    fn step_result() {
+        extern crate approx;
+        let mut sol = DOP54::new(1.0, 0.2, 1.0e-12, 1.005, 0.9);
+        let x = SmpVector::set_one_fill(2);
+        let rejected = sol.try_step(func1, &x);
+        assert!(!rejected.accepted && rejected.error > 1.0e-12);
+        assert_eq!(rejected.x.time, x.time);
+        approx::assert_abs_diff_eq!(rejected.dt_used, 1.0);
+        assert!(rejected.dt_proposed < 0.2);
+        approx::assert_abs_diff_eq!(sol.get_delta_t(), rejected.dt_proposed);
+
+        let mut step = rejected;
+        while !step.accepted {
+            step = sol.try_step(func1, &x);
+        }
+        assert!(step.error <= 1.0e-12);
+        approx::assert_abs_diff_eq!(step.x.time, step.dt_used);
+        approx::assert_abs_diff_eq!(step.dt_proposed, 1.005 * step.dt_used);
+
+        let stats = sol.statistics();
+        assert_eq!(stats.accepted, 1);
+        assert_eq!(stats.rejected, sol.get_num_of_dt_switch());
+        // f(x) only once over the retries
+        assert_eq!(stats.evaluations, 1 + 6 * (stats.rejected + 1));
+        approx::assert_abs_diff_eq!(stats.min_dt, step.dt_used);
+        approx::assert_abs_diff_eq!(stats.max_dt, step.dt_used);
+    }
 }
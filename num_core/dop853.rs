@@ -0,0 +1,372 @@
+// Dormand–Prince method 8(5,3) with dense output of order 7
+//
+// Hairer, Nørsett, Wanner, Solving ordinary differential equations I (1993),
+// Sec. II.10 and the code DOP853.
+// Tableau: ButcherTableau::dormand_prince853, stepper: ExplicitRK (the same
+// adaptive driver as DOP54, with the 5th and 3rd order error estimates combined).
+// The dense output needs 4 more evaluations per accepted step: k_13 = dt f(x_1)
+// and the stages 14–16 below.
+// The coefficients keep the digits of the code.
+#![allow(clippy::excessive_precision)]
+use crate::num_core::butcher_tableau::ButcherTableau;
+use crate::num_core::dense_output::DenseOutput;
+use crate::num_core::explicit_rk::ExplicitRK;
+use crate::num_core::integrator::Integrator;
+use crate::num_core::step_control::{PIController, StepStatistics};
+use crate::SmpVector;
+
+// c_14, c_15, c_16
+const C_DENSE: [f64; 3] = [0.1, 0.2, 7.0 / 9.0];
+
+// a_14,j (j = 1..13), a_15,j (j = 1..14), a_16,j (j = 1..15)
+const A14: [f64; 13] = [
+    5.61675022830479523392909219681e-2,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
+    2.53500210216624811088794765333e-1,
+    -2.46239037470802489917441475441e-1,
+    -1.24191423263816360469010140626e-1,
+    1.5329179827876569731206322685e-1,
+    8.20105229563468988491666602057e-3,
+    7.56789766054569976138603589584e-3,
+    -8.298e-3,
+];
+const A15: [f64; 14] = [
+    3.18346481635021405060768473261e-2,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
+    2.83009096723667755288322961402e-2,
+    5.35419883074385676223797384372e-2,
+    -5.49237485713909884646569340306e-2,
+    0.0,
+    0.0,
+    -1.08347328697249322858509316994e-4,
+    3.82571090835658412954920192323e-4,
+    -3.40465008687404560802977114492e-4,
+    1.41312443674632500278074618366e-1,
+];
+const A16: [f64; 15] = [
+    -4.28896301583791923408573538692e-1,
+    0.0,
+    0.0,
+    0.0,
+    0.0,
+    -4.69762141536116384314449447206,
+    7.68342119606259904184240953878,
+    4.06898981839711007970213554331,
+    3.56727187455281109270669543021e-1,
+    0.0,
+    0.0,
+    0.0,
+    -1.39902416515901462129418009734e-3,
+    2.9475147891527723389556272149,
+    -9.15095847217987001081870187138,
+];
+
+// d_4j .. d_7j (j = 1..16) of the interpolation polynomial
+const D: [[f64; 16]; 4] = [
+    [
+        -0.84289382761090128651353491142e1,
+        0.0,
+        0.0,
+        0.0,
+        0.0,
+        0.56671495351937776962531783590,
+        -0.30689499459498916912797304727e1,
+        0.23846676565120698287728149680e1,
+        0.21170345824450282767155149946e1,
+        -0.87139158377797299206789907490,
+        0.22404374302607882758541771650e1,
+        0.63157877876946881815570249290,
+        -0.88990336451333310820698117400e-1,
+        0.18148505520854727256656404962e2,
+        -0.91946323924783554000451984436e1,
+        -0.44360363875948939664310572000e1,
+    ],
+    [
+        0.10427508642579134603413151009e2,
+        0.0,
+        0.0,
+        0.0,
+        0.0,
+        0.24228349177525818288430175319e3,
+        0.16520045171727028198505394887e3,
+        -0.37454675472269020279518312152e3,
+        -0.22113666853125306036270938578e2,
+        0.77334326684722638389603898808e1,
+        -0.30674084731089398182061213626e2,
+        -0.93321305264302278729567221706e1,
+        0.15697238121770843886131091075e2,
+        -0.31139403219565177677282850411e2,
+        -0.93529243588444783865713862664e1,
+        0.35816841486394083752465898540e2,
+    ],
+    [
+        0.19985053242002433820987653617e2,
+        0.0,
+        0.0,
+        0.0,
+        0.0,
+        -0.38703730874935176555105901742e3,
+        -0.18917813819516756882830838328e3,
+        0.52780815920542364900561016686e3,
+        -0.11573902539959630126141871134e2,
+        0.68812326946963000169666922661e1,
+        -0.10006050966910838403183860980e1,
+        0.77771377980534432092869265740,
+        -0.27782057523535084065932004339e1,
+        -0.60196695231264120758267380846e2,
+        0.84320405506677161018159903784e2,
+        0.11992291136182789328035130030e2,
+    ],
+    [
+        -0.25693933462703749003312586129e2,
+        0.0,
+        0.0,
+        0.0,
+        0.0,
+        -0.15418974869023643374053993627e3,
+        -0.23152937917604549567536039109e3,
+        0.35763911791061412378285349910e3,
+        0.93405324183624310003907691704e2,
+        -0.37458323136451633156875139351e2,
+        0.10409964950896230045147246184e3,
+        0.29840293426660503123344363579e2,
+        -0.43533456590011143754432175058e2,
+        0.96324553959188282948394950600e2,
+        -0.39177261675615439165231486172e2,
+        -0.14972683625798562581422125276e3,
+    ],
+];
+
+pub struct DOP853 {
+    rk: ExplicitRK,
+    dense_output: bool,
+    // interpolation polynomial of the last accepted step
+    last_step: Option<DenseOutput>,
+}
+
+impl DOP853 {
+    #[allow(dead_code)]
+    pub fn new(
+        dt1: f64,
+        dt_max1: f64,
+        abs_tol1: f64,
+        dt_scale_up_factor1: f64,
+        dt_scale_down_factor1: f64,
+    ) -> DOP853 {
+        DOP853 {
+            rk: ExplicitRK::new(
+                ButcherTableau::dormand_prince853(),
+                dt1,
+                dt_max1,
+                abs_tol1,
+                dt_scale_up_factor1,
+                dt_scale_down_factor1,
+            ),
+            dense_output: false,
+            last_step: None,
+        }
+    }
+
+    // keep the interpolation polynomial of every accepted step (4 more evaluations)
+    #[allow(dead_code)]
+    pub fn set_dense_output(&mut self, dense_output: bool) {
+        self.dense_output = dense_output;
+        self.last_step = None;
+    }
+
+    // 8th order solution (propagated)
+    #[allow(dead_code)]
+    #[inline(always)]
+    pub fn solve_8th_order(
+        &self,
+        func: impl Fn(&SmpVector) -> SmpVector,
+        x: &SmpVector,
+    ) -> SmpVector {
+        self.rk.solve_fixed(func, x)
+    }
+
+    // 5th order solution (error estimate)
+    #[allow(dead_code)]
+    #[inline(always)]
+    pub fn solve_5th_order(
+        &self,
+        func: impl Fn(&SmpVector) -> SmpVector,
+        x: &SmpVector,
+    ) -> SmpVector {
+        self.rk.solve_embedded(func, x)
+    }
+
+    #[allow(dead_code)]
+    pub fn solve(&mut self, func: impl Fn(&SmpVector) -> SmpVector, x: &SmpVector) -> SmpVector {
+        let dt = self.rk.get_delta_t();
+        let (y, k) = self.rk.solve_with_stages(&func, x);
+        if self.dense_output && y.time != x.time {
+            self.last_step = Some(DOP853::interpolation(&func, x, &y, k, dt));
+            self.rk.add_evaluations(4);
+        }
+        y
+    }
+
+    // rcont5 .. rcont8 of DOP853 from the stages k_i = dt f(...) of the step x0 -> x1
+    fn interpolation(
+        func: impl Fn(&SmpVector) -> SmpVector,
+        x0: &SmpVector,
+        x1: &SmpVector,
+        mut k: Vec<SmpVector>,
+        dt: f64,
+    ) -> DenseOutput {
+        k.push(dt * &func(x1));
+        let rows: [&[f64]; 3] = [&A14, &A15, &A16];
+        for (ci, ai) in C_DENSE.iter().zip(rows.iter()) {
+            let mut xi = x0 + &ExplicitRK::weighted_sum(ai, &k);
+            xi.time = x0.time + ci * dt;
+            k.push(dt * &func(&xi));
+        }
+        let higher = D.iter().map(|d| ExplicitRK::weighted_sum(d, &k)).collect();
+        DenseOutput::new(x0, x1, &k[0], &k[12], higher)
+    }
+
+    // State at time t within the last accepted step (None outside of it or
+    // without set_dense_output(true)).
+    #[allow(dead_code)]
+    pub fn dense_output(&self, t: f64) -> Option<SmpVector> {
+        let step = self.last_step.as_ref()?;
+        if !step.contains(t) {
+            return None;
+        }
+        Some(step.evaluate(t))
+    }
+
+    #[allow(dead_code)]
+    #[inline(always)]
+    pub fn get_num_of_dt_switch(&self) -> i64 {
+        self.rk.get_num_of_dt_switch()
+    }
+
+    #[allow(dead_code)]
+    #[inline(always)]
+    pub fn get_num_of_steps(&self) -> i64 {
+        self.rk.get_num_of_steps()
+    }
+
+    #[allow(dead_code)]
+    pub fn solve_to_end_time(
+        &mut self,
+        end_time: f64,
+        func: impl Fn(&SmpVector) -> SmpVector,
+        x: &SmpVector,
+    ) -> SmpVector {
+        self.integrate_to(end_time, &func, x)
+    }
+
+    #[allow(dead_code)]
+    pub fn set_delta_t(&mut self, dt: f64) {
+        self.rk.set_delta_t(dt);
+    }
+
+    #[allow(dead_code)]
+    pub fn get_delta_t(&self) -> f64 {
+        self.rk.get_delta_t()
+    }
+
+    // scaled atol/rtol error norm and PI step control instead of the scale factors
+    #[allow(dead_code)]
+    pub fn set_controller(&mut self, controller: PIController) {
+        self.rk.set_controller(controller);
+    }
+}
+
+impl Integrator for DOP853 {
+    fn step(&self, func: &dyn Fn(&SmpVector) -> SmpVector, x: &SmpVector) -> SmpVector {
+        self.rk.step(func, x)
+    }
+
+    fn adaptive_step(
+        &mut self,
+        func: &dyn Fn(&SmpVector) -> SmpVector,
+        x: &SmpVector,
+    ) -> SmpVector {
+        self.solve(func, x)
+    }
+
+    fn set_delta_t(&mut self, dt: f64) {
+        self.rk.set_delta_t(dt)
+    }
+
+    fn get_delta_t(&self) -> f64 {
+        self.rk.get_delta_t()
+    }
+
+    fn get_num_of_steps(&self) -> i64 {
+        self.rk.get_num_of_steps()
+    }
+
+    fn get_num_of_dt_switch(&self) -> i64 {
+        self.rk.get_num_of_dt_switch()
+    }
+
+    fn set_controller(&mut self, controller: PIController) {
+        self.rk.set_controller(controller)
+    }
+
+    fn statistics(&self) -> &StepStatistics {
+        self.rk.statistics()
+    }
+
+    fn set_dense_output(&mut self, dense_output: bool) -> Result<(), &'static str> {
+        DOP853::set_dense_output(self, dense_output);
+        Ok(())
+    }
+
+    fn has_dense_output(&self) -> bool {
+        self.dense_output
+    }
+
+    fn dense_output(&self, t: f64) -> Option<SmpVector> {
+        DOP853::dense_output(self, t)
+    }
+}
+
+#[cfg(test)]
+pub mod dop853_tests {
+    extern crate approx;
+    use crate::num_core::dop853::DOP853;
+    use crate::num_core::smp_vector::SmpVector;
+    use crate::num_core::test_problems::oscillator;
+
+    #[test]
+    fn dense_output() {
+        let mut sol = DOP853::new(0.5, 0.5, 1.0e-10, 1.0, 0.9);
+        assert!(sol.dense_output(0.1).is_none());
+        sol.set_dense_output(true);
+        let mut x = SmpVector {
+            time: 0.0,
+            vec: vec![1.0, 0.0],
+        };
+        while x.time < 3.0 {
+            let y = sol.solve(oscillator, &x);
+            if y.time == x.time {
+                continue;
+            }
+            // the start and 9 points inside the step, far apart from the grid
+            for i in 0..10 {
+                let t = x.time + 0.1 * i as f64 * (y.time - x.time);
+                let z = sol.dense_output(t).unwrap();
+                approx::assert_abs_diff_eq!(z.time, t);
+                approx::assert_abs_diff_eq!(z.vec[0], t.cos(), epsilon = 1.0e-9);
+                approx::assert_abs_diff_eq!(z.vec[1], -t.sin(), epsilon = 1.0e-9);
+            }
+            let z = sol.dense_output(y.time).unwrap();
+            approx::assert_abs_diff_eq!(z.vec[0], y.vec[0], epsilon = 1.0e-15);
+            assert!(sol.dense_output(y.time + 1.0e-3).is_none());
+            x = y;
+        }
+    }
+}
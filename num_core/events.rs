@@ -0,0 +1,576 @@
+//
+// Events during the integration: close encounters, pericenter and apocenter
+// passages, plane crossings and escapes (YAML: Run: Events), or any event
+// function g(x) given in code (Event::new).
+//
+// An event happens where g changes sign in its direction. After every accepted
+// step the signs of g at both ends are compared, and the root is located in the
+// dense output of the step by the Illinois method (modified regula falsi). The
+// event state is taken on the far side of the root, where g has changed sign.
+//
+use crate::num_core::integrator::Integrator;
+use crate::num_core::n_body::{Mode, NBody, DIM};
+use crate::num_core::smp_vector::SmpVector;
+use serde::{Deserialize, Serialize};
+
+// Sign change of g that makes an event.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Direction {
+    // from g < 0 to g >= 0
+    Rising,
+    // from g > 0 to g <= 0
+    Falling,
+    Both,
+}
+
+pub enum Action {
+    // only record the event state
+    Record,
+    // record it and stop the integration there
+    Terminate,
+    // record it and go on from the modified state
+    Modify(Box<dyn Fn(&SmpVector) -> SmpVector>),
+}
+
+pub struct Event {
+    pub name: String,
+    g: Box<dyn Fn(&SmpVector) -> f64>,
+    pub direction: Direction,
+    pub action: Action,
+}
+
+impl Event {
+    #[allow(dead_code)]
+    pub fn new(
+        name: &str,
+        g: impl Fn(&SmpVector) -> f64 + 'static,
+        direction: Direction,
+        action: Action,
+    ) -> Event {
+        Event {
+            name: name.to_string(),
+            g: Box::new(g),
+            direction,
+            action,
+        }
+    }
+
+    fn triggered(&self, g0: f64, g1: f64) -> bool {
+        let rising = g0 < 0.0 && g1 >= 0.0;
+        let falling = g0 > 0.0 && g1 <= 0.0;
+        match self.direction {
+            Direction::Rising => rising,
+            Direction::Falling => falling,
+            Direction::Both => rising || falling,
+        }
+    }
+}
+
+pub struct EventRecord {
+    pub name: String,
+    pub state: SmpVector,
+}
+
+pub struct Events {
+    events: Vec<Event>,
+    // g of every event at the start of the current step
+    g_last: Vec<f64>,
+    // width of the final bracket of a root in time
+    time_tol: f64,
+    records: Vec<EventRecord>,
+    terminal: Option<SmpVector>,
+}
+
+impl Events {
+    pub fn new(events: Vec<Event>, x0: &SmpVector, time_tol: f64) -> Events {
+        let g_last = events.iter().map(|e| (e.g)(x0)).collect();
+        Events {
+            events,
+            g_last,
+            time_tol,
+            records: Vec::new(),
+            terminal: None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    // every event so far, in the order of time
+    pub fn records(&self) -> &[EventRecord] {
+        &self.records
+    }
+
+    // time of the terminating event, if one has happened
+    pub fn terminated_at(&self) -> Option<f64> {
+        self.terminal.as_ref().map(|x| x.time)
+    }
+
+    // Illinois method on g(dense output) in [t0, t1]; the state at the end of
+    // the final bracket on the side of g1.
+    fn locate(
+        &self,
+        sol: &dyn Integrator,
+        event: &Event,
+        t0: f64,
+        g0: f64,
+        t1: f64,
+        g1: f64,
+    ) -> SmpVector {
+        let (mut a, mut ga, mut b, mut gb) = (t0, g0, t1, g1);
+        let mut side = 0;
+        for _ in 0..100 {
+            if b - a <= self.time_tol {
+                break;
+            }
+            let mut t = (a * gb - b * ga) / (gb - ga);
+            if !(t > a && t < b) {
+                t = 0.5 * (a + b);
+            }
+            let g = (event.g)(&sol.dense_output(t).unwrap());
+            if g == 0.0 || (g > 0.0) == (gb > 0.0) {
+                b = t;
+                gb = g;
+                if g == 0.0 {
+                    break;
+                }
+                if side == -1 {
+                    ga *= 0.5;
+                }
+                side = -1;
+            } else {
+                a = t;
+                ga = g;
+                if side == 1 {
+                    gb *= 0.5;
+                }
+                side = 1;
+            }
+        }
+        sol.dense_output(b).unwrap()
+    }
+
+    // Events in the accepted step x0 -> x1 of sol, in the order of time. Returns
+    // the state to go on from if one of them terminates or modifies the state.
+    fn check_step(
+        &mut self,
+        sol: &dyn Integrator,
+        x0: &SmpVector,
+        x1: &SmpVector,
+    ) -> Option<SmpVector> {
+        let g1: Vec<f64> = self.events.iter().map(|e| (e.g)(x1)).collect();
+        let mut found: Vec<(SmpVector, usize)> = Vec::new();
+        for (i, event) in self.events.iter().enumerate() {
+            if event.triggered(self.g_last[i], g1[i]) {
+                let z = self.locate(sol, event, x0.time, self.g_last[i], x1.time, g1[i]);
+                found.push((z, i));
+            }
+        }
+        found.sort_by(|a, b| a.0.time.partial_cmp(&b.0.time).unwrap());
+        for (z, i) in found {
+            self.records.push(EventRecord {
+                name: self.events[i].name.clone(),
+                state: z.clone(),
+            });
+            let z = match &self.events[i].action {
+                Action::Record => continue,
+                Action::Terminate => {
+                    self.terminal = Some(z.clone());
+                    z
+                }
+                Action::Modify(modify) => modify(&z),
+            };
+            self.g_last = self.events.iter().map(|e| (e.g)(&z)).collect();
+            return Some(z);
+        }
+        self.g_last = g1;
+        None
+    }
+
+    // Integrator::advance_to with the events checked after every accepted step
+    // (sol needs dense output). The state at t_out, or the terminal state once
+    // t_out has passed the terminating event.
+    pub fn advance_to(
+        &mut self,
+        sol: &mut dyn Integrator,
+        t_out: f64,
+        func: &dyn Fn(&SmpVector) -> SmpVector,
+        x: &mut SmpVector,
+    ) -> SmpVector {
+        while self.terminal.is_none() && x.time < t_out {
+            let x0 = x.clone();
+            let x1 = sol.adaptive_step(func, &x0);
+            // rejected
+            if x1.time == x0.time {
+                continue;
+            }
+            *x = match self.check_step(sol, &x0, &x1) {
+                Some(z) => z,
+                None => x1,
+            };
+        }
+        match &self.terminal {
+            Some(z) if t_out >= z.time => z.clone(),
+            _ if x.time == t_out => x.clone(),
+            // t_out lies in the last step (before any event in it)
+            _ => sol.dense_output(t_out).unwrap(),
+        }
+    }
+}
+
+// Kind of an event of the setting file.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum EventKind {
+    // |x_i - x_j| = Distance, falling (default Distance: the sum of the radii)
+    CloseEncounter,
+    // (x_i - x_j).(v_i - v_j) = 0, rising
+    Pericenter,
+    // (x_i - x_j).(v_i - v_j) = 0, falling
+    Apocenter,
+    // x_i[Axis] = Value, both directions (Poincaré sections)
+    PlaneCrossing,
+    // |x_i| = Radius from the origin, rising
+    Escape,
+}
+
+impl EventKind {
+    fn is_pair(&self) -> bool {
+        match self {
+            EventKind::CloseEncounter | EventKind::Pericenter | EventKind::Apocenter => true,
+            EventKind::PlaneCrossing | EventKind::Escape => false,
+        }
+    }
+
+    fn direction(&self) -> Direction {
+        match self {
+            EventKind::CloseEncounter | EventKind::Apocenter => Direction::Falling,
+            EventKind::Pericenter | EventKind::Escape => Direction::Rising,
+            EventKind::PlaneCrossing => Direction::Both,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub enum EventAction {
+    #[default]
+    Record,
+    Terminate,
+    // multiply the velocities of the Bodies (-1 reflects, 0 stops them)
+    ScaleVelocity(f64),
+}
+
+// One entry of Run: Events.
+// Bodies are two for CloseEncounter, Pericenter and Apocenter, one otherwise. In
+// Mode: TwoBody the relative coordinate is the only point: the pair is [0, 1]
+// and the single body [0].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct EventSetting {
+    pub kind: EventKind,
+    // label in the event output, the kind by default
+    #[serde(default)]
+    pub name: Option<String>,
+    pub bodies: Vec<usize>,
+    #[serde(default)]
+    pub distance: Option<f64>,
+    #[serde(default)]
+    pub radius: Option<f64>,
+    #[serde(default)]
+    pub axis: Option<usize>,
+    #[serde(default)]
+    pub value: f64,
+    // overrides the direction of the kind
+    #[serde(default)]
+    pub direction: Option<Direction>,
+    #[serde(default)]
+    pub action: EventAction,
+}
+
+impl EventSetting {
+    pub fn check(&self, n_body: &NBody) -> Result<&EventSetting, &'static str> {
+        let pair = self.kind.is_pair();
+        if pair && (self.bodies.len() != 2 || self.bodies[0] == self.bodies[1]) {
+            return Err("Bodies of the event must be two different bodies");
+        }
+        if !pair && self.bodies.len() != 1 {
+            return Err("Bodies of the event must be one body");
+        }
+        let num = match (n_body.mode, pair) {
+            (Mode::TwoBody, false) => 1,
+            _ => n_body.number_of_bodies,
+        };
+        if self.bodies.iter().any(|&i| i >= num) {
+            return Err("Bodies of the event out of range");
+        }
+        match self.kind {
+            EventKind::CloseEncounter => match self.close_encounter_distance(n_body) {
+                None => return Err("CloseEncounter needs Distance or Radius of the bodies"),
+                Some(d) if d <= 0.0 => return Err("CloseEncounter Distance <= 0"),
+                _ => {}
+            },
+            EventKind::PlaneCrossing => match self.axis {
+                Some(axis) if axis < DIM => {}
+                _ => return Err("PlaneCrossing needs Axis 0, 1 or 2"),
+            },
+            EventKind::Escape => match self.radius {
+                Some(r) if r > 0.0 => {}
+                _ => return Err("Escape needs Radius > 0"),
+            },
+            EventKind::Pericenter | EventKind::Apocenter => {}
+        }
+        Ok(self)
+    }
+
+    fn close_encounter_distance(&self, n_body: &NBody) -> Option<f64> {
+        self.distance.or_else(|| {
+            let radius = n_body.radius.as_ref()?;
+            Some(radius[self.bodies[0]] + radius[self.bodies[1]])
+        })
+    }
+
+    // the event function and action on the state vector of n_body (checked)
+    pub fn build(&self, n_body: &NBody) -> Event {
+        let n = DIM * n_body.num_of_points();
+        // first point in the state, and the second one of a pair in Mode: NBody
+        let (i, j) = match (n_body.mode, self.kind.is_pair()) {
+            (Mode::TwoBody, _) => (0, None),
+            (Mode::NBody, true) => (self.bodies[0], Some(self.bodies[1])),
+            (Mode::NBody, false) => (self.bodies[0], None),
+        };
+        // position and velocity of the point, relative to the second one
+        let relative = move |x: &SmpVector| {
+            let mut r = [0.0; DIM];
+            let mut v = [0.0; DIM];
+            for d in 0..DIM {
+                r[d] = x.vec[DIM * i + d];
+                v[d] = x.vec[n + DIM * i + d];
+                if let Some(j) = j {
+                    r[d] -= x.vec[DIM * j + d];
+                    v[d] -= x.vec[n + DIM * j + d];
+                }
+            }
+            (r, v)
+        };
+        let dot = |a: &[f64; DIM], b: &[f64; DIM]| -> f64 { (0..DIM).map(|d| a[d] * b[d]).sum() };
+        let g: Box<dyn Fn(&SmpVector) -> f64> = match self.kind {
+            EventKind::CloseEncounter => {
+                let distance = self.close_encounter_distance(n_body).unwrap();
+                Box::new(move |x| {
+                    let (r, _) = relative(x);
+                    dot(&r, &r).sqrt() - distance
+                })
+            }
+            EventKind::Pericenter | EventKind::Apocenter => Box::new(move |x| {
+                let (r, v) = relative(x);
+                dot(&r, &v)
+            }),
+            EventKind::PlaneCrossing => {
+                let (axis, value) = (self.axis.unwrap(), self.value);
+                Box::new(move |x| relative(x).0[axis] - value)
+            }
+            EventKind::Escape => {
+                let radius = self.radius.unwrap();
+                Box::new(move |x| {
+                    let (r, _) = relative(x);
+                    dot(&r, &r).sqrt() - radius
+                })
+            }
+        };
+        let action = match self.action {
+            EventAction::Record => Action::Record,
+            EventAction::Terminate => Action::Terminate,
+            EventAction::ScaleVelocity(factor) => {
+                let points: Vec<usize> = match n_body.mode {
+                    Mode::TwoBody => vec![0],
+                    Mode::NBody => self.bodies.clone(),
+                };
+                Action::Modify(Box::new(move |x| {
+                    let mut ret = x.clone();
+                    for p in points.iter() {
+                        for d in 0..DIM {
+                            ret.vec[n + DIM * p + d] *= factor;
+                        }
+                    }
+                    ret
+                }))
+            }
+        };
+        let name = match &self.name {
+            Some(name) => name.clone(),
+            None => format!("{:?}", self.kind),
+        };
+        Event {
+            name,
+            g,
+            direction: self.direction.unwrap_or_else(|| self.kind.direction()),
+            action,
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod events_tests {
+    extern crate approx;
+    use crate::num_core::dop54::DOP54;
+    use crate::num_core::events::{Action, Direction, Event, EventSetting, Events};
+    use crate::num_core::n_body::NBody;
+    use crate::num_core::smp_vector::SmpVector;
+    use crate::num_core::test_problems;
+    use std::f64::consts::PI;
+
+    // the eccentric Kepler orbit (e = 0.6, period 2 pi) of bodies that touch at r = 0.5
+    fn kepler() -> NBody {
+        let mut n_body = test_problems::kepler();
+        n_body.radius = Some(vec![0.3, 0.2]);
+        n_body
+    }
+
+    fn event(yaml: &str, n_body: &NBody) -> Event {
+        let setting: EventSetting = serde_yaml::from_str(yaml).unwrap();
+        setting.check(n_body).unwrap();
+        setting.build(n_body)
+    }
+
+    fn dop54() -> DOP54 {
+        let mut sol = DOP54::new(1.0e-3, 0.1, 1.0e-13, 1.05, 0.9);
+        sol.set_dense_output(true);
+        sol
+    }
+
+    #[test]
+    fn apsides() {
+        let n_body = kepler();
+        let mut x = n_body.to_smp_vector();
+        let list = vec![
+            event("{Kind: Pericenter, Bodies: [0, 1]}", &n_body),
+            event("{Kind: Apocenter, Bodies: [1, 0]}", &n_body),
+            // r = a (1 - e cos E) = 1 at E = pi / 2, 3 pi / 2
+            event("{Kind: Escape, Bodies: [1], Radius: 0.5}", &n_body),
+        ];
+        let mut events = Events::new(list, &x, 1.0e-12);
+        let mut sol = dop54();
+        let y = events.advance_to(&mut sol, 2.0 * PI + 0.1, &n_body.eom(), &mut x);
+        approx::assert_abs_diff_eq!(y.time, 2.0 * PI + 0.1);
+
+        let records = events.records();
+        let names: Vec<&str> = records.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["Escape", "Apocenter", "Pericenter"]);
+        // Kepler's equation: t = E - e sin E
+        approx::assert_abs_diff_eq!(records[0].state.time, 0.5 * PI - 0.6, epsilon = 1.0e-9);
+        approx::assert_abs_diff_eq!(records[1].state.time, PI, epsilon = 1.0e-9);
+        approx::assert_abs_diff_eq!(records[1].state.vec[0], 0.8, epsilon = 1.0e-9);
+        approx::assert_abs_diff_eq!(records[2].state.time, 2.0 * PI, epsilon = 1.0e-9);
+        approx::assert_abs_diff_eq!(records[2].state.vec[0], -0.2, epsilon = 1.0e-9);
+        assert!(events.terminated_at().is_none());
+    }
+
+    #[test]
+    fn terminate() {
+        let n_body = kepler();
+        let mut x = n_body.to_smp_vector();
+        // the bodies touch (0.3 + 0.2) at r = 1 - 0.6 cos E = 0.5
+        let collision = event(
+            "{Kind: CloseEncounter, Bodies: [0, 1], Action: Terminate}",
+            &n_body,
+        );
+        // body 0 crosses x = 0 twice before the collision (cos E = e)
+        let crossing = event(
+            "{Kind: PlaneCrossing, Bodies: [0], Axis: 0, Value: 0.0}",
+            &n_body,
+        );
+        let mut events = Events::new(vec![crossing, collision], &x, 1.0e-12);
+        let mut sol = dop54();
+        let func = n_body.eom();
+
+        // t > 0 in the second half of the orbit
+        let e_collision: f64 = 2.0 * PI - (0.5_f64 / 0.6).acos();
+        let t_collision = e_collision - 0.6 * e_collision.sin();
+        let y = events.advance_to(&mut sol, 1.0, &func, &mut x);
+        approx::assert_abs_diff_eq!(y.time, 1.0);
+        assert!(events.terminated_at().is_none());
+        let y = events.advance_to(&mut sol, 7.0, &func, &mut x);
+        approx::assert_abs_diff_eq!(y.time, t_collision, epsilon = 1.0e-9);
+        approx::assert_abs_diff_eq!(events.terminated_at().unwrap(), y.time);
+        let r = 2.0 * (y.vec[0] * y.vec[0] + y.vec[1] * y.vec[1]).sqrt();
+        approx::assert_abs_diff_eq!(r, 0.5, epsilon = 1.0e-9);
+        // the integration is over
+        let z = events.advance_to(&mut sol, 8.0, &func, &mut x);
+        assert_eq!(z.time, y.time);
+        let names: Vec<&str> = events.records().iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["PlaneCrossing", "PlaneCrossing", "CloseEncounter"]
+        );
+    }
+
+    #[test]
+    fn modify() {
+        // x' = v, v' = -1 with a perfect bounce at x = 0: |v| = 1 at the floor
+        let gravity = |x: &SmpVector| SmpVector {
+            time: x.time,
+            vec: vec![x.vec[1], -1.0],
+        };
+        let floor = Event::new(
+            "floor",
+            |x| x.vec[0],
+            Direction::Falling,
+            Action::Modify(Box::new(|x| SmpVector {
+                time: x.time,
+                vec: vec![x.vec[0], -x.vec[1]],
+            })),
+        );
+        let mut x = SmpVector {
+            time: 0.0,
+            vec: vec![0.5, 0.0],
+        };
+        let mut events = Events::new(vec![floor], &x, 1.0e-13);
+        let mut sol = dop54();
+        let y = events.advance_to(&mut sol, 4.5, &gravity, &mut x);
+        // the bounces at t = 1, 3, 5, ... and the top again at t = 2, 4, ...
+        assert_eq!(events.records().len(), 2);
+        for (k, record) in events.records().iter().enumerate() {
+            approx::assert_abs_diff_eq!(record.state.time, (2 * k + 1) as f64, epsilon = 1.0e-10);
+            approx::assert_abs_diff_eq!(record.state.vec[1], -1.0, epsilon = 1.0e-10);
+        }
+        approx::assert_abs_diff_eq!(y.vec[0], 0.375, epsilon = 1.0e-10);
+        approx::assert_abs_diff_eq!(y.vec[1], -0.5, epsilon = 1.0e-10);
+        assert!(sol.get_num_of_steps() > 0);
+    }
+
+    #[test]
+    fn setting_check() {
+        let mut n_body = kepler();
+        let check = |yaml: &str, n_body: &NBody| {
+            let setting: EventSetting = serde_yaml::from_str(yaml).unwrap();
+            setting.check(n_body).map(|_| ())
+        };
+        assert!(check("{Kind: CloseEncounter, Bodies: [0, 1]}", &n_body).is_ok());
+        assert_eq!(
+            check("{Kind: Pericenter, Bodies: [1, 1]}", &n_body),
+            Err("Bodies of the event must be two different bodies")
+        );
+        assert_eq!(
+            check("{Kind: Escape, Bodies: [2], Radius: 1.0}", &n_body),
+            Err("Bodies of the event out of range")
+        );
+        assert_eq!(
+            check("{Kind: PlaneCrossing, Bodies: [0], Axis: 3}", &n_body),
+            Err("PlaneCrossing needs Axis 0, 1 or 2")
+        );
+        assert_eq!(
+            check("{Kind: Escape, Bodies: [0, 1], Radius: 1.0}", &n_body),
+            Err("Bodies of the event must be one body")
+        );
+        n_body.radius = None;
+        assert_eq!(
+            check("{Kind: CloseEncounter, Bodies: [0, 1]}", &n_body),
+            Err("CloseEncounter needs Distance or Radius of the bodies")
+        );
+        let setting: EventSetting = serde_yaml::from_str(
+            "{Kind: Escape, Bodies: [0], Radius: 9.0, Action: {ScaleVelocity: -1.0}}",
+        )
+        .unwrap();
+        assert_eq!(setting.action, super::EventAction::ScaleVelocity(-1.0));
+    }
+}
@@ -0,0 +1,304 @@
+// Explicit Runge–Kutta method for any Butcher tableau.
+//
+// With an embedded formula the step size is controlled by
+// err = |x_p - x_p^| <= abs_tol and dt <- down * dt * (abs_tol / err)^(1 / (p^ + 1)),
+// without one every step is accepted with the given dt. A second estimate
+// x_p^^ (b_check) replaces err by err^2 / sqrt(err^2 + 0.01 |x_p - x_p^^|^2)
+// with the exponent 1 / p, as in DOP853.
+// With a PIController (set_controller) err is the scaled RMS norm with atol/rtol
+// instead and the controller proposes the next dt (still capped by dt_max).
+use crate::num_core::butcher_tableau::ButcherTableau;
+use crate::num_core::integrator::Integrator;
+use crate::num_core::step_control::{self, PIController, StepStatistics};
+use crate::SmpVector;
+
+pub struct ExplicitRK {
+    tableau: ButcherTableau,
+    // deltaT
+    dt: f64,
+    abs_tol: f64,
+    // dt scale factor
+    dt_scale_up_factor: f64,
+    dt_scale_down_factor: f64,
+    dt_max: f64,
+    // None: the fixed scale factors above with the plain 2-norm against abs_tol
+    controller: Option<PIController>,
+    stats: StepStatistics,
+}
+
+impl ExplicitRK {
+    #[allow(dead_code)]
+    pub fn new(
+        tableau: ButcherTableau,
+        dt: f64,
+        dt_max: f64,
+        abs_tol: f64,
+        dt_scale_up_factor: f64,
+        dt_scale_down_factor: f64,
+    ) -> ExplicitRK {
+        ExplicitRK {
+            tableau,
+            dt,
+            abs_tol,
+            dt_scale_up_factor,
+            dt_scale_down_factor,
+            dt_max,
+            controller: None,
+            stats: StepStatistics::new(),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn set_controller(&mut self, controller: PIController) {
+        self.controller = Some(controller);
+    }
+
+    // |e| for the error vector e of the step x -> x1
+    #[inline(always)]
+    pub fn error_norm(&self, e: &SmpVector, x: &SmpVector, x1: &SmpVector) -> f64 {
+        match &self.controller {
+            Some(pi) => step_control::error_norm(e, x, x1, &pi.atol, &pi.rtol),
+            None => e.norm(),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn get_tableau(&self) -> &ButcherTableau {
+        &self.tableau
+    }
+
+    // sum_j w_j k_j, skipping the zero weights
+    #[inline(always)]
+    pub fn weighted_sum(w: &[f64], k: &[SmpVector]) -> SmpVector {
+        let mut ret = SmpVector::set_zero_vector(k[0].vec.len());
+        for (wj, kj) in w.iter().zip(k.iter()) {
+            if *wj != 0.0 {
+                ret = &ret + &(*wj * kj);
+            }
+        }
+        ret
+    }
+
+    // k_i = dt f(t + c_i dt, x + sum_j a_ij k_j)
+    #[inline(always)]
+    fn stages(&self, func: impl Fn(&SmpVector) -> SmpVector, x: &SmpVector) -> Vec<SmpVector> {
+        let mut k: Vec<SmpVector> = Vec::with_capacity(self.tableau.num_of_stages());
+        for (ci, ai) in self.tableau.c.iter().zip(self.tableau.a.iter()) {
+            let mut xi = if k.is_empty() {
+                x.clone()
+            } else {
+                x + &ExplicitRK::weighted_sum(ai, &k)
+            };
+            xi.time = x.time + ci * self.dt;
+            k.push(self.dt * &func(&xi));
+        }
+        k
+    }
+
+    #[inline(always)]
+    fn combine(&self, b: &[f64], k: &[SmpVector], x: &SmpVector) -> SmpVector {
+        SmpVector {
+            time: self.dt + x.time,
+            vec: (x + &ExplicitRK::weighted_sum(b, k)).vec,
+        }
+    }
+
+    // one step with the propagated weights b
+    #[allow(dead_code)]
+    #[inline(always)]
+    pub fn solve_fixed(&self, func: impl Fn(&SmpVector) -> SmpVector, x: &SmpVector) -> SmpVector {
+        let k = self.stages(func, x);
+        self.combine(&self.tableau.b, &k, x)
+    }
+
+    // one step with the embedded weights b^ (b without an embedded formula)
+    #[allow(dead_code)]
+    #[inline(always)]
+    pub fn solve_embedded(
+        &self,
+        func: impl Fn(&SmpVector) -> SmpVector,
+        x: &SmpVector,
+    ) -> SmpVector {
+        let k = self.stages(func, x);
+        let b = self.tableau.b_hat.as_ref().unwrap_or(&self.tableau.b);
+        self.combine(b, &k, x)
+    }
+
+    #[allow(dead_code)]
+    #[inline(always)]
+    pub fn solve(&mut self, func: impl Fn(&SmpVector) -> SmpVector, x: &SmpVector) -> SmpVector {
+        self.solve_with_stages(func, x).0
+    }
+
+    // Adaptive step that also returns the stages k_i (for the dense output).
+    pub fn solve_with_stages(
+        &mut self,
+        func: impl Fn(&SmpVector) -> SmpVector,
+        x: &SmpVector,
+    ) -> (SmpVector, Vec<SmpVector>) {
+        let k = self.stages(func, x);
+        self.stats.evaluations += k.len() as i64;
+        let x_high = self.combine(&self.tableau.b, &k, x);
+        let b_hat = match &self.tableau.b_hat {
+            Some(b_hat) => b_hat,
+            None => {
+                self.stats.record(true, self.dt);
+                return (x_high, k);
+            }
+        };
+        let x_low = self.combine(b_hat, &k, x);
+        // adaptive dt
+        let mut err = self.error_norm(&(&x_high - &x_low), x, &x_high);
+        let mut order = (self.tableau.embedded_order + 1) as f64;
+        if let Some(b_check) = &self.tableau.b_check {
+            // err5^2 / sqrt(err5^2 + 0.01 err3^2) ~ err5^2 / err3 ~ dt^p (DOP853)
+            let x_check = self.combine(b_check, &k, x);
+            let err_check = self.error_norm(&(&x_high - &x_check), x, &x_high);
+            let den = (err * err + 0.01 * err_check * err_check).sqrt();
+            err = if den > 0.0 { err * err / den } else { 0.0 };
+            order = self.tableau.order as f64;
+        }
+        if self.accept(err, order) {
+            (x_high, k)
+        } else {
+            (x.clone(), k)
+        }
+    }
+
+    // Accept or reject a step of the current dt with the error err of an
+    // estimate of order (order - 1), and set the next dt.
+    pub fn accept(&mut self, err: f64, order: f64) -> bool {
+        let dt = self.dt;
+        let accepted = match self.controller.as_mut() {
+            Some(pi) => {
+                let (accepted, dt) = pi.propose(self.dt, err, order);
+                self.dt = dt;
+                accepted
+            }
+            None if err > self.abs_tol => {
+                self.dt =
+                    self.dt_scale_down_factor * self.dt * (self.abs_tol / err).powf(1.0 / order);
+                false
+            }
+            None => {
+                self.dt *= self.dt_scale_up_factor;
+                true
+            }
+        };
+        self.dt = step_control::limit(self.dt, self.dt_max);
+        self.stats.record(accepted, dt);
+        accepted
+    }
+
+    #[allow(dead_code)]
+    #[inline(always)]
+    pub fn get_num_of_dt_switch(&self) -> i64 {
+        self.stats.rejected
+    }
+
+    #[allow(dead_code)]
+    #[inline(always)]
+    pub fn get_num_of_steps(&self) -> i64 {
+        self.stats.accepted + self.stats.rejected
+    }
+
+    pub fn statistics(&self) -> &StepStatistics {
+        &self.stats
+    }
+
+    // right-hand side evaluations outside of the stages (FSAL, dense output)
+    pub fn add_evaluations(&mut self, n: i64) {
+        self.stats.evaluations += n;
+    }
+
+    #[allow(dead_code)]
+    pub fn solve_to_end_time(
+        &mut self,
+        end_time: f64,
+        func: impl Fn(&SmpVector) -> SmpVector,
+        x: &SmpVector,
+    ) -> SmpVector {
+        self.integrate_to(end_time, &func, x)
+    }
+
+    pub fn set_delta_t(&mut self, dt: f64) {
+        self.dt = dt;
+    }
+
+    #[allow(dead_code)]
+    pub fn get_delta_t(&self) -> f64 {
+        self.dt
+    }
+}
+
+impl Integrator for ExplicitRK {
+    fn step(&self, func: &dyn Fn(&SmpVector) -> SmpVector, x: &SmpVector) -> SmpVector {
+        self.solve_fixed(func, x)
+    }
+
+    fn adaptive_step(
+        &mut self,
+        func: &dyn Fn(&SmpVector) -> SmpVector,
+        x: &SmpVector,
+    ) -> SmpVector {
+        self.solve(func, x)
+    }
+
+    fn set_delta_t(&mut self, dt: f64) {
+        ExplicitRK::set_delta_t(self, dt)
+    }
+
+    fn get_delta_t(&self) -> f64 {
+        ExplicitRK::get_delta_t(self)
+    }
+
+    fn get_num_of_steps(&self) -> i64 {
+        ExplicitRK::get_num_of_steps(self)
+    }
+
+    fn get_num_of_dt_switch(&self) -> i64 {
+        ExplicitRK::get_num_of_dt_switch(self)
+    }
+
+    fn set_controller(&mut self, controller: PIController) {
+        ExplicitRK::set_controller(self, controller)
+    }
+
+    fn statistics(&self) -> &StepStatistics {
+        ExplicitRK::statistics(self)
+    }
+}
+
+#[cfg(test)]
+pub mod explicit_rk_tests {
+    extern crate approx;
+    use crate::num_core::butcher_tableau::ButcherTableau;
+    use crate::num_core::explicit_rk::ExplicitRK;
+    use crate::num_core::smp_vector::SmpVector;
+    use crate::num_core::test_problems::exponential;
+
+    #[test]
+    fn convergence_order() {
+        // halving dt divides the error of y' = y on [0, 1] by 2^p
+        let tableaux = vec![
+            ButcherTableau::rk4(),
+            ButcherTableau::bogacki_shampine32(),
+            ButcherTableau::cash_karp45(),
+            ButcherTableau::dormand_prince54(),
+            ButcherTableau::verner65(),
+        ];
+        for tableau in tableaux {
+            let p = tableau.order as i32;
+            let error = |n: usize| {
+                let sol = ExplicitRK::new(tableau.clone(), 1.0 / n as f64, 1.0, 1.0, 1.0, 1.0);
+                let mut y = SmpVector::set_one_fill(1);
+                for _i in 0..n {
+                    y = sol.solve_fixed(exponential, &y);
+                }
+                (y.vec[0] - std::f64::consts::E).abs()
+            };
+            let ratio = error(8) / error(16);
+            approx::assert_relative_eq!(ratio.log2(), p as f64, max_relative = 0.1);
+        }
+    }
+}
@@ -0,0 +1,374 @@
+//
+// Implicit Gauss–Legendre Runge–Kutta methods with s stages, of order 2s.
+//
+// Collocation at the zeros c_i of the shifted Legendre polynomial P_s(2c - 1):
+//   k_i = f(t + c_i dt, x + dt sum_j a_ij k_j),  x1 = x + dt sum_i b_i k_i,
+// with a_ij = int_0^c_i l_j and b_j = int_0^1 l_j for the Lagrange polynomials
+// l_j of the nodes. The method is symmetric and symplectic and keeps quadratic
+// invariants (angular momentum), so the energy error of a conservative orbit
+// stays bounded; f may depend on the velocities (post-Newtonian forces) as the
+// stage equations are solved for any f.
+// The stages come from fixed-point iteration until the change stops shrinking
+// (round-off), which converges for dt |df/dx| small enough; it starts from the
+// stage polynomial of the previous step extrapolated over the new one.
+// The step size dt is fixed, unless the iteration fails: then the step is
+// rejected and dt halved.
+//
+// ref. Hairer, Lubich, Wanner, Geometric numerical integration (2006),
+// Sec. II.1.3 and VIII.6.
+//
+use crate::num_core::explicit_rk::ExplicitRK;
+use crate::num_core::integrator::Integrator;
+use crate::num_core::smp_vector::SmpVector;
+use crate::num_core::step_control::{PIController, StepStatistics};
+use std::f64::consts::PI;
+
+// fixed-point iterations per step at most
+const MAX_ITERATIONS: usize = 50;
+
+// a change that stops shrinking within this factor of the round-off of x
+// counts as converged, above it as diverging
+const ROUND_OFF_FACTOR: f64 = 100.0;
+
+pub struct GaussLegendre {
+    c: Vec<f64>,
+    a: Vec<Vec<f64>>,
+    b: Vec<f64>,
+    // coefficients of l_j in ascending powers
+    lagrange: Vec<Vec<f64>>,
+    dt: f64,
+    // end state, dt and stages of the last step
+    last: Option<(SmpVector, f64, Vec<SmpVector>)>,
+    stats: StepStatistics,
+}
+
+// P_n(x) and P_n'(x) from the three-term recurrence (n >= 1)
+fn legendre(n: usize, x: f64) -> (f64, f64) {
+    let (mut p0, mut p1) = (1.0, x);
+    for k in 2..=n {
+        let p2 = ((2 * k - 1) as f64 * x * p1 - (k - 1) as f64 * p0) / k as f64;
+        p0 = p1;
+        p1 = p2;
+    }
+    (p1, n as f64 * (x * p1 - p0) / (x * x - 1.0))
+}
+
+// prod_{m != j} (t - c_m) / (c_j - c_m)
+fn lagrange(c: &[f64], j: usize) -> Vec<f64> {
+    let mut p = vec![1.0];
+    for (m, cm) in c.iter().enumerate() {
+        if m == j {
+            continue;
+        }
+        let d = c[j] - cm;
+        let mut q = vec![0.0; p.len() + 1];
+        for (k, pk) in p.iter().enumerate() {
+            q[k + 1] += pk / d;
+            q[k] -= pk * cm / d;
+        }
+        p = q;
+    }
+    p
+}
+
+fn value(p: &[f64], t: f64) -> f64 {
+    p.iter().rev().fold(0.0, |sum, pk| sum * t + pk)
+}
+
+// int_0^t p
+fn integral(p: &[f64], t: f64) -> f64 {
+    p.iter()
+        .enumerate()
+        .rev()
+        .fold(0.0, |sum, (k, pk)| sum * t + pk / (k + 1) as f64)
+        * t
+}
+
+impl GaussLegendre {
+    #[allow(dead_code)]
+    pub fn new(stages: usize, dt: f64) -> GaussLegendre {
+        // Newton on P_s(2c - 1) from the asymptotic estimate of the zeros
+        let c: Vec<f64> = (0..stages)
+            .map(|i| {
+                let mut x = -(PI * (i as f64 + 0.75) / (stages as f64 + 0.5)).cos();
+                for _ in 0..10 {
+                    let (p, dp) = legendre(stages, x);
+                    x -= p / dp;
+                }
+                0.5 * (x + 1.0)
+            })
+            .collect();
+        let lagrange: Vec<Vec<f64>> = (0..stages).map(|j| lagrange(&c, j)).collect();
+        let a = c
+            .iter()
+            .map(|ci| lagrange.iter().map(|l| integral(l, *ci)).collect())
+            .collect();
+        let b = lagrange.iter().map(|l| integral(l, 1.0)).collect();
+        GaussLegendre {
+            c,
+            a,
+            b,
+            lagrange,
+            dt,
+            last: None,
+            stats: StepStatistics::new(),
+        }
+    }
+
+    // k_i from the extrapolated stages of the last step if it ended at x
+    fn guess(&self, x: &SmpVector) -> Option<Vec<SmpVector>> {
+        let (end, dt, k) = self.last.as_ref()?;
+        if end.time != x.time || end.vec != x.vec {
+            return None;
+        }
+        // t + c_i dt is 1 + c_i dt / dt_last in units of the last step
+        let ratio = self.dt / dt;
+        let guess = self
+            .c
+            .iter()
+            .map(|ci| {
+                let w: Vec<f64> = self
+                    .lagrange
+                    .iter()
+                    .map(|l| value(l, 1.0 + ci * ratio))
+                    .collect();
+                ExplicitRK::weighted_sum(&w, k)
+            })
+            .collect();
+        Some(guess)
+    }
+
+    // Fixed-point iteration on the stages from k; returns the RHS evaluations
+    // and whether it converged.
+    fn iterate(
+        &self,
+        func: &dyn Fn(&SmpVector) -> SmpVector,
+        x: &SmpVector,
+        k: &mut Vec<SmpVector>,
+    ) -> (i64, bool) {
+        let scale = f64::EPSILON * x.norm();
+        let mut last_change = f64::INFINITY;
+        let mut evaluations = 0;
+        for _ in 0..MAX_ITERATIONS {
+            let next: Vec<SmpVector> = self
+                .c
+                .iter()
+                .zip(self.a.iter())
+                .map(|(ci, ai)| {
+                    let mut xi = x + &(self.dt * &ExplicitRK::weighted_sum(ai, k));
+                    xi.time = x.time + ci * self.dt;
+                    func(&xi)
+                })
+                .collect();
+            evaluations += next.len() as i64;
+            // change of the stage states dt sum_j a_ij k_j
+            let change = self.dt.abs()
+                * next
+                    .iter()
+                    .zip(k.iter())
+                    .map(|(n, o)| (n - o).norm())
+                    .fold(0.0, f64::max);
+            *k = next;
+            if change <= scale {
+                return (evaluations, true);
+            }
+            if change >= last_change {
+                return (evaluations, change <= ROUND_OFF_FACTOR * scale);
+            }
+            last_change = change;
+        }
+        (evaluations, false)
+    }
+
+    fn combine(&self, k: &[SmpVector], x: &SmpVector) -> SmpVector {
+        let mut y = x + &(self.dt * &ExplicitRK::weighted_sum(&self.b, k));
+        y.time = x.time + self.dt;
+        y
+    }
+
+    #[allow(dead_code)]
+    pub fn solve_to_end_time(
+        &mut self,
+        end_time: f64,
+        func: impl Fn(&SmpVector) -> SmpVector,
+        x: &SmpVector,
+    ) -> SmpVector {
+        self.integrate_to(end_time, &func, x)
+    }
+}
+
+impl Integrator for GaussLegendre {
+    fn step(&self, func: &dyn Fn(&SmpVector) -> SmpVector, x: &SmpVector) -> SmpVector {
+        let mut k = vec![func(x); self.c.len()];
+        self.iterate(func, x, &mut k);
+        self.combine(&k, x)
+    }
+
+    // Accepted if the stages converge. Otherwise the step is rejected (x is
+    // returned) and dt halved, and stays so for the following steps.
+    fn adaptive_step(
+        &mut self,
+        func: &dyn Fn(&SmpVector) -> SmpVector,
+        x: &SmpVector,
+    ) -> SmpVector {
+        let mut k = match self.guess(x) {
+            Some(k) => k,
+            None => {
+                self.stats.evaluations += 1;
+                vec![func(x); self.c.len()]
+            }
+        };
+        let (evaluations, converged) = self.iterate(func, x, &mut k);
+        self.stats.evaluations += evaluations;
+        self.stats.record(converged, self.dt);
+        if !converged {
+            self.dt *= 0.5;
+            return x.clone();
+        }
+        let y = self.combine(&k, x);
+        self.last = Some((y.clone(), self.dt, k));
+        y
+    }
+
+    fn set_delta_t(&mut self, dt: f64) {
+        self.dt = dt;
+    }
+
+    fn get_delta_t(&self) -> f64 {
+        self.dt
+    }
+
+    fn get_num_of_steps(&self) -> i64 {
+        self.stats.accepted + self.stats.rejected
+    }
+
+    fn get_num_of_dt_switch(&self) -> i64 {
+        self.stats.rejected
+    }
+
+    // fixed step
+    fn set_controller(&mut self, _controller: PIController) {}
+
+    fn statistics(&self) -> &StepStatistics {
+        &self.stats
+    }
+}
+
+#[cfg(test)]
+pub mod gauss_legendre_tests {
+    extern crate approx;
+    use crate::num_core::gauss_legendre::GaussLegendre;
+    use crate::num_core::integrator::{self, Integrator};
+    use crate::num_core::smp_vector::SmpVector;
+    use crate::num_core::test_problems::{self, pericenter, relative_kepler};
+    use std::cell::Cell;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn tableau() {
+        let gl4 = GaussLegendre::new(2, 1.0);
+        let r = 3.0_f64.sqrt() / 6.0;
+        approx::assert_abs_diff_eq!(gl4.c[0], 0.5 - r, epsilon = 1.0e-15);
+        approx::assert_abs_diff_eq!(gl4.a[0][1], 0.25 - r, epsilon = 1.0e-15);
+        approx::assert_abs_diff_eq!(gl4.a[1][1], 0.25, epsilon = 1.0e-15);
+        // the order conditions sum_i b_i c_i^(k - 1) = 1 / k up to k = 2s
+        for s in 1..=4 {
+            let gl = GaussLegendre::new(s, 1.0);
+            for k in 1..=2 * s {
+                let sum: f64 =
+                    gl.b.iter()
+                        .zip(gl.c.iter())
+                        .map(|(b, c)| b * c.powi(k as i32 - 1))
+                        .sum();
+                approx::assert_abs_diff_eq!(sum, 1.0 / k as f64, epsilon = 1.0e-14);
+            }
+            for (ai, ci) in gl.a.iter().zip(gl.c.iter()) {
+                approx::assert_abs_diff_eq!(ai.iter().sum::<f64>(), ci, epsilon = 1.0e-14);
+            }
+        }
+    }
+
+    #[test]
+    fn kepler_energy() {
+        // eccentric orbit (e = 0.6, period 2 pi) from the pericenter, 100 steps
+        // per period
+        let x = pericenter(0.6);
+        let dt = 2.0 * PI / 100.0;
+        let e0 = test_problems::kepler_energy(&x);
+        // largest energy error at every step of the first 10 and the next 90 periods
+        let run = |sol: &mut dyn Integrator| {
+            let mut y = x.clone();
+            let mut drift = [0.0_f64; 2];
+            for i in 0..10000 {
+                y = sol.adaptive_step(&relative_kepler, &y);
+                let de = (test_problems::kepler_energy(&y) - e0).abs();
+                let j = if i < 1000 { 0 } else { 1 };
+                drift[j] = drift[j].max(de);
+            }
+            (y, drift)
+        };
+        let mut last = f64::INFINITY;
+        for name in ["GaussLegendre4", "GaussLegendre6", "GaussLegendre8"].iter() {
+            let mut sol = integrator::from_name(name, dt, dt, 1.0e-13, 1.0, 1.0).unwrap();
+            let (y, drift) = run(sol.as_mut());
+            // bounded: no secular growth
+            assert!(drift[1] < 2.0 * drift[0]);
+            assert!(drift[1] < 0.1 * last);
+            last = drift[1];
+            // angular momentum to round-off
+            let l = y.vec[0] * y.vec[4] - y.vec[1] * y.vec[3];
+            approx::assert_abs_diff_eq!(l, 0.8, epsilon = 1.0e-12);
+        }
+        assert!(last < 1.0e-10);
+        // the explicit RK4 of the same order drifts
+        let mut sol = integrator::from_name("RK4", dt, dt, 1.0e-13, 1.0, 1.0).unwrap();
+        let (_, drift) = run(sol.as_mut());
+        assert!(drift[1] > 5.0 * drift[0]);
+    }
+
+    #[test]
+    fn velocity_dependent() {
+        // charge in a magnetic field along z: v' = v x B keeps the quadratic
+        // |v|^2, to round-off with Gauss–Legendre
+        let lorentz = test_problems::lorentz(2.0, 0.0);
+        let x = SmpVector {
+            time: 0.0,
+            vec: vec![0.0, 0.0, 0.0, 1.0, 0.5, 0.0],
+        };
+        let mut sol = GaussLegendre::new(3, 0.1);
+        let y = sol.solve_to_end_time(300.0, lorentz, &x);
+        let speed = |x: &SmpVector| x.vec[3] * x.vec[3] + x.vec[4] * x.vec[4];
+        approx::assert_abs_diff_eq!(speed(&y), 1.25, epsilon = 1.0e-13);
+        // the circle of radius |v| / 2 about the guiding center (0.25, -0.5)
+        let r = ((y.vec[0] - 0.25).powi(2) + (y.vec[1] + 0.5).powi(2)).sqrt();
+        approx::assert_abs_diff_eq!(r, 1.25_f64.sqrt() / 2.0, epsilon = 1.0e-12);
+        // the extrapolated stages save iterations over the start from f(x)
+        let count = Cell::new(0);
+        let counted = |x: &SmpVector| {
+            count.set(count.get() + 1);
+            lorentz(x)
+        };
+        let mut z = x;
+        for _i in 0..3000 {
+            z = sol.step(&counted, &z);
+        }
+        assert!(sol.statistics().evaluations < count.get());
+    }
+
+    #[test]
+    fn iteration_fails() {
+        // x' = -20 x: the fixed-point iteration diverges for dt = 1, the step
+        // is rejected and dt halved until it converges
+        let decay = |x: &SmpVector| -20.0 * x;
+        let x = SmpVector {
+            time: 0.0,
+            vec: vec![1.0],
+        };
+        let mut sol = GaussLegendre::new(3, 1.0);
+        let y = sol.solve_to_end_time(1.0, decay, &x);
+        assert!(sol.statistics().rejected > 0);
+        assert_eq!(sol.get_num_of_dt_switch(), sol.statistics().rejected);
+        assert!(sol.get_delta_t() < 0.2);
+        approx::assert_relative_eq!(y.vec[0], (-20.0_f64).exp(), max_relative = 1.0e-3);
+    }
+}
@@ -0,0 +1,300 @@
+//
+// Gragg–Bulirsch–Stoer extrapolation with adaptive order and step size.
+//
+// The modified midpoint rule (Gragg) over dt with n_j = 2, 4, 6, ... substeps
+// T_j1, and the Aitken–Neville extrapolation in (dt / n_j)^2
+//   T_j,l+1 = T_jl + (T_jl - T_j-1,l) / ((n_j / n_j-l)^2 - 1).
+// The error of column k is |T_kk - T_k,k-1| in the scaled norm of step_control;
+// the step is accepted in column k - 1, k or k + 1 of the target k, and the next
+// k and dt are those with the least work (RHS evaluations per unit time).
+//
+// ref. Hairer, Nørsett, Wanner, Solving ordinary differential equations I (1993),
+// Sec. II.9, code ODEX.
+//
+use crate::num_core::integrator::Integrator;
+use crate::num_core::smp_vector::SmpVector;
+use crate::num_core::step_control::{self, PIController, StepStatistics, Tolerance};
+
+// largest number of columns
+const K_MAX: usize = 9;
+
+#[allow(clippy::upper_case_acronyms)]
+pub struct GBS {
+    dt: f64,
+    dt_max: f64,
+    atol: Tolerance,
+    rtol: Tolerance,
+    // target column (0-based, T_k,k uses the k + 1 first n_j)
+    k: usize,
+    // n_j and the RHS evaluations a_j of the columns up to j
+    sequence: Vec<usize>,
+    cost: Vec<f64>,
+    stats: StepStatistics,
+}
+
+impl GBS {
+    #[allow(dead_code)]
+    pub fn new(dt: f64, dt_max: f64, abs_tol: f64) -> GBS {
+        let sequence: Vec<usize> = (1..=K_MAX).map(|j| 2 * j).collect();
+        // f(x) once, then n_j - 1 evaluations for each midpoint rule
+        let cost = sequence
+            .iter()
+            .scan(1.0, |sum, n| {
+                *sum += (n - 1) as f64;
+                Some(*sum)
+            })
+            .collect();
+        GBS {
+            dt,
+            dt_max,
+            atol: Tolerance::Scalar(abs_tol),
+            rtol: Tolerance::Scalar(0.0),
+            k: 4,
+            sequence,
+            cost,
+            stats: StepStatistics::new(),
+        }
+    }
+
+    // modified midpoint rule over dt with n substeps, f0 = f(x)
+    fn midpoint(
+        func: &dyn Fn(&SmpVector) -> SmpVector,
+        x: &SmpVector,
+        f0: &SmpVector,
+        dt: f64,
+        n: usize,
+    ) -> SmpVector {
+        let h = dt / n as f64;
+        let mut z0 = x.clone();
+        let mut z1 = x + &(h * f0);
+        z1.time = x.time + h;
+        for m in 1..n {
+            let f = func(&z1);
+            let mut z2 = &z0 + &((2.0 * h) * &f);
+            z2.time = x.time + (m + 1) as f64 * h;
+            z0 = z1;
+            z1 = z2;
+        }
+        z1
+    }
+
+    // the columns T_j0 .. T_jj of row j from T_j0 and the row j - 1
+    fn extrapolate(&self, row: SmpVector, last: &[SmpVector], j: usize) -> Vec<SmpVector> {
+        let mut ret = vec![row];
+        for l in 1..=j {
+            let ratio = self.sequence[j] as f64 / self.sequence[j - l] as f64;
+            let diff = &ret[l - 1] - &last[l - 1];
+            ret.push(&ret[l - 1] + &((1.0 / (ratio * ratio - 1.0)) * &diff));
+        }
+        ret
+    }
+
+    // dt for error err in column j (order 2j + 1 of T_j,j-1)
+    fn optimal_dt(dt: f64, err: f64, j: usize) -> f64 {
+        let fac = 0.94 * (0.65 / err).powf(1.0 / (2 * j + 1) as f64);
+        dt * fac.clamp(0.02, 4.0)
+    }
+
+    // Adaptive step; a rejected one returns x (see Integrator::adaptive_step).
+    #[allow(dead_code)]
+    pub fn solve(&mut self, func: &dyn Fn(&SmpVector) -> SmpVector, x: &SmpVector) -> SmpVector {
+        let dt = self.dt;
+        let k = self.k;
+        let f0 = func(x);
+        let mut evaluations = 1;
+        let mut table: Vec<SmpVector> = Vec::new();
+        let mut dt_opt = [0.0; K_MAX];
+        let mut work = [0.0; K_MAX];
+        let mut accepted = None;
+        for j in 0..=(k + 1).min(K_MAX - 1) {
+            let row = GBS::midpoint(func, x, &f0, dt, self.sequence[j]);
+            evaluations += self.sequence[j] - 1;
+            table = self.extrapolate(row, &table, j);
+            if j == 0 {
+                continue;
+            }
+            let err_vec = &table[j] - &table[j - 1];
+            let err = step_control::error_norm(&err_vec, x, &table[j], &self.atol, &self.rtol);
+            dt_opt[j] = GBS::optimal_dt(dt, err, j);
+            work[j] = self.cost[j] / dt_opt[j];
+            if j + 1 >= k && err <= 1.0 {
+                accepted = Some(j);
+                break;
+            }
+            // no hope of convergence in column k + 1 (ODEX)
+            let n = |i: usize| self.sequence[i.min(K_MAX - 1)] as f64 / self.sequence[0] as f64;
+            if (j + 1 == k && err > (n(k + 1) * n(k)).powi(2)) || (j == k && err > n(k + 1).powi(2))
+            {
+                break;
+            }
+        }
+        self.stats.evaluations += evaluations as i64;
+        let last = table.len() - 1;
+        match accepted {
+            Some(j) => {
+                self.stats.record(true, dt);
+                // order and step with the least work for the next step
+                let mut k_new = j.max(2);
+                if j >= 3 && work[j - 1] < 0.8 * work[j] {
+                    k_new = j - 1;
+                } else if j + 1 < K_MAX && (j < 2 || work[j] < 0.9 * work[j - 1]) {
+                    k_new = j + 1;
+                }
+                self.dt = if k_new > j {
+                    dt_opt[j] * self.cost[k_new] / self.cost[j]
+                } else {
+                    dt_opt[k_new.min(j)]
+                };
+                self.k = k_new.clamp(2, K_MAX - 2);
+                let mut y = table.swap_remove(j);
+                y.time = dt + x.time;
+                self.dt = step_control::limit(self.dt, self.dt_max);
+                y
+            }
+            None => {
+                self.stats.record(false, dt);
+                // the column of the least work computed
+                let best = (1..=last)
+                    .min_by(|a, b| work[*a].partial_cmp(&work[*b]).unwrap())
+                    .unwrap_or(1);
+                self.k = best.clamp(2, K_MAX - 2);
+                let dt_new = dt_opt[best].abs().min(0.5 * dt.abs()).copysign(dt);
+                self.dt = step_control::limit(dt_new, self.dt_max);
+                x.clone()
+            }
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn solve_to_end_time(
+        &mut self,
+        end_time: f64,
+        func: impl Fn(&SmpVector) -> SmpVector,
+        x: &SmpVector,
+    ) -> SmpVector {
+        self.integrate_to(end_time, &func, x)
+    }
+}
+
+impl Integrator for GBS {
+    // the target column k, without error control
+    fn step(&self, func: &dyn Fn(&SmpVector) -> SmpVector, x: &SmpVector) -> SmpVector {
+        let f0 = func(x);
+        let mut table: Vec<SmpVector> = Vec::new();
+        for j in 0..=self.k {
+            let row = GBS::midpoint(func, x, &f0, self.dt, self.sequence[j]);
+            table = self.extrapolate(row, &table, j);
+        }
+        let mut y = table.swap_remove(self.k);
+        y.time = self.dt + x.time;
+        y
+    }
+
+    fn adaptive_step(
+        &mut self,
+        func: &dyn Fn(&SmpVector) -> SmpVector,
+        x: &SmpVector,
+    ) -> SmpVector {
+        self.solve(func, x)
+    }
+
+    fn set_delta_t(&mut self, dt: f64) {
+        self.dt = dt;
+    }
+
+    fn get_delta_t(&self) -> f64 {
+        self.dt
+    }
+
+    fn get_num_of_steps(&self) -> i64 {
+        self.stats.accepted + self.stats.rejected
+    }
+
+    fn get_num_of_dt_switch(&self) -> i64 {
+        self.stats.rejected
+    }
+
+    // only the tolerances: the order and step come from the work estimates
+    fn set_controller(&mut self, controller: PIController) {
+        self.atol = controller.atol;
+        self.rtol = controller.rtol;
+    }
+
+    fn statistics(&self) -> &StepStatistics {
+        &self.stats
+    }
+}
+
+#[cfg(test)]
+pub mod gbs_tests {
+    extern crate approx;
+    use crate::num_core::dop54::DOP54;
+    use crate::num_core::gbs::GBS;
+    use crate::num_core::integrator::Integrator;
+    use crate::num_core::n_body::NBody;
+    use crate::num_core::step_control::{PIController, Tolerance};
+
+    // figure-eight choreography (Chenciner and Montgomery 2000), period T
+    const FIGURE_EIGHT: &str = "
+SettingName: \"figure-eight\"
+NumberOfBodies: 3
+Mass: [1.0, 1.0, 1.0]
+Position: [[-0.97000436, 0.24308753, 0.0], [0.97000436, -0.24308753, 0.0], [0.0, 0.0, 0.0]]
+Velocity: [[0.466203685, 0.43236573, 0.0], [0.466203685, 0.43236573, 0.0], [-0.93240737, -0.86473146, 0.0]]
+";
+    const T: f64 = 6.32591398;
+
+    // Pythagorean three-body problem (Burrau), chaotic with close encounters
+    const PYTHAGOREAN: &str = "
+SettingName: \"pythagorean\"
+NumberOfBodies: 3
+Mass: [3.0, 4.0, 5.0]
+Position: [[1.0, 3.0, 0.0], [-2.0, -1.0, 0.0], [1.0, -1.0, 0.0]]
+";
+
+    #[test]
+    fn figure_eight() {
+        let n_body: NBody = serde_yaml::from_str(FIGURE_EIGHT).unwrap();
+        let x = n_body.to_smp_vector();
+        let tolerance =
+            || PIController::new(Tolerance::Scalar(1.0e-12), Tolerance::Scalar(1.0e-12));
+        let mut gbs = GBS::new(1.0e-3, 1.0, 1.0e-12);
+        gbs.set_controller(tolerance());
+        let y = gbs.solve_to_end_time(T, n_body.eom(), &x);
+        // back at the start (to the digits of the initial condition)
+        for (a, b) in x.vec.iter().zip(y.vec.iter()) {
+            approx::assert_abs_diff_eq!(a, b, epsilon = 1.0e-6);
+        }
+        let mut dop54 = DOP54::new(1.0e-3, 1.0, 1.0e-12, 1.0, 0.9);
+        dop54.set_controller(tolerance());
+        let z = dop54.solve_to_end_time(T, n_body.eom(), &x);
+        for (a, b) in y.vec.iter().zip(z.vec.iter()) {
+            approx::assert_abs_diff_eq!(a, b, epsilon = 1.0e-9);
+        }
+        // high order: far fewer (if more expensive) steps
+        assert!(10 * gbs.get_num_of_steps() < dop54.get_num_of_steps());
+        assert!(gbs.statistics().evaluations < dop54.statistics().evaluations);
+    }
+
+    #[test]
+    fn converged_chaos() {
+        // the solution converges with the tolerance through the first close
+        // encounters (t < 10)
+        let n_body: NBody = serde_yaml::from_str(PYTHAGOREAN).unwrap();
+        let x = n_body.to_smp_vector();
+        let run = |tol: f64| {
+            let mut gbs = GBS::new(1.0e-3, 1.0, tol);
+            gbs.set_controller(PIController::new(
+                Tolerance::Scalar(tol),
+                Tolerance::Scalar(tol),
+            ));
+            gbs.solve_to_end_time(10.0, n_body.eom(), &x)
+        };
+        let difference = |a: &crate::SmpVector, b: &crate::SmpVector| (a - b).norm();
+        let reference = run(1.0e-14);
+        let coarse = difference(&run(1.0e-8), &reference);
+        let fine = difference(&run(1.0e-12), &reference);
+        assert!(fine < 1.0e-6);
+        assert!(fine < 1.0e-2 * coarse);
+    }
+}
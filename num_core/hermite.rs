@@ -0,0 +1,556 @@
+//
+// Fourth-order Hermite predictor–corrector with individual (block) time steps.
+//
+// Every body carries its own time t_i and step dt_i, with its acceleration a
+// and jerk j = da/dt at t_i (and a^(2), a^(3) from its last step). A block
+// step advances the bodies with the earliest t_i + dt_i: every body is
+// predicted there by its Taylor series, the forces on the active ones are
+// computed at the predicted state, and the corrector
+//   v1 = v0 + (a0 + a1) dt / 2 + (j0 - j1) dt^2 / 12
+//   x1 = x0 + (v0 + v1) dt / 2 + (a0 - a1) dt^2 / 12
+// gives their new state. The next dt_i follows the Aarseth criterion
+//   dt = sqrt(eta (|a| |a2| + |j|^2) / (|j| |a3| + |a2|^2)),
+// at most twice the last step, rounded down to a power of two (up to MaxDt)
+// that divides the time since the bodies were last synchronized.
+//
+// The Newtonian a and j are analytic, O(N) for each active body. The 1PN (EIH)
+// terms of an active body take the predicted Newtonian accelerations and
+// potentials of the others, and their jerk is a central difference along the
+// predicted motion.
+//
+// Backward in time the bodies run forward in t' = -t with the velocities
+// reversed, as the Newtonian and 1PN forces are invariant under t -> -t.
+//
+// ref. Makino and Aarseth, PASJ 44 (1992) 141. Aarseth, Gravitational N-body
+// simulations (2003), Ch. 2.
+//
+use crate::num_core::integrator::Integrator;
+use crate::num_core::n_body::{Mode, NBody, DIM};
+use crate::num_core::post_newtonian;
+use crate::num_core::smp_vector::SmpVector;
+use crate::num_core::step_control::{PIController, StepStatistics};
+
+// name in the run configuration (YAML: Run: Integrator)
+pub const NAME: &str = "Hermite";
+
+// accuracy parameter of the Aarseth criterion
+pub const ETA: f64 = 0.02;
+
+// Newtonian acceleration and jerk of a body, with its potential
+// phi = sum_k m_k / r_k and phi' (for the 1PN terms)
+#[derive(Debug, Clone, Copy, Default)]
+struct Newton {
+    a: [f64; DIM],
+    j: [f64; DIM],
+    phi: f64,
+    phi_dot: f64,
+}
+
+#[derive(Debug, Clone)]
+struct Body {
+    // time since Hermite::origin, and the step
+    s: f64,
+    dt: f64,
+    x: [f64; DIM],
+    v: [f64; DIM],
+    // total acceleration and its derivatives
+    a: [f64; DIM],
+    j: [f64; DIM],
+    a2: [f64; DIM],
+    a3: [f64; DIM],
+    newton: Newton,
+}
+
+// every body predicted to the same time
+struct Predicted {
+    pos: Vec<f64>,
+    vel: Vec<f64>,
+    // Newtonian
+    acc: Vec<f64>,
+    phi: Vec<f64>,
+}
+
+#[derive(Clone)]
+pub struct Hermite {
+    mass: Vec<f64>,
+    c: f64,
+    pn1: bool,
+    eta: f64,
+    // interval of step(); the adaptive steps are the block steps
+    dt: f64,
+    // largest block step, a power of two
+    dt_max: f64,
+    bodies: Vec<Body>,
+    // time of the last synchronization, and of the last block since then
+    origin: f64,
+    now: f64,
+    // the state returned last (in t' with direction -1), from which the
+    // bodies continue
+    x: SmpVector,
+    // 1 forward, -1 backward in time
+    direction: f64,
+    body_steps: i64,
+    stats: StepStatistics,
+}
+
+fn norm(a: &[f64; DIM]) -> f64 {
+    a.iter().map(|v| v * v).sum::<f64>().sqrt()
+}
+
+impl Hermite {
+    pub fn new(n_body: &NBody, dt: f64, dt_max: f64, eta: f64) -> Hermite {
+        Hermite {
+            mass: n_body.gravitational_mass(),
+            c: n_body.light_speed(),
+            pn1: n_body.post_newtonian.pn1,
+            eta,
+            dt,
+            dt_max: 2.0_f64.powi(dt_max.log2().floor() as i32),
+            bodies: Vec::new(),
+            origin: 0.0,
+            now: 0.0,
+            x: SmpVector::set_zero_vector(0),
+            direction: 1.0,
+            body_steps: 0,
+            stats: StepStatistics::new(),
+        }
+    }
+
+    // steps of single bodies (a block step of k bodies counts k)
+    #[allow(dead_code)]
+    pub fn body_steps(&self) -> i64 {
+        self.body_steps
+    }
+
+    // Newtonian force on body i
+    fn newton(&self, i: usize, pos: &[f64], vel: &[f64]) -> Newton {
+        let mut ret = Newton::default();
+        for (k, m) in self.mass.iter().enumerate() {
+            if k == i {
+                continue;
+            }
+            let mut r = [0.0; DIM];
+            let mut v = [0.0; DIM];
+            for d in 0..DIM {
+                r[d] = pos[DIM * k + d] - pos[DIM * i + d];
+                v[d] = vel[DIM * k + d] - vel[DIM * i + d];
+            }
+            let r2: f64 = r.iter().map(|x| x * x).sum();
+            let rv = r.iter().zip(v.iter()).map(|(x, y)| x * y).sum::<f64>() / r2;
+            let inv_r = 1.0 / r2.sqrt();
+            let inv_r3 = inv_r * inv_r * inv_r;
+            for d in 0..DIM {
+                ret.a[d] += m * r[d] * inv_r3;
+                ret.j[d] += m * (v[d] - 3.0 * rv * r[d]) * inv_r3;
+            }
+            ret.phi += m * inv_r;
+            ret.phi_dot -= m * rv * inv_r;
+        }
+        ret
+    }
+
+    // every body at s (time since origin)
+    fn predict(&self, s: f64) -> Predicted {
+        let n = self.bodies.len();
+        let mut ret = Predicted {
+            pos: vec![0.0; DIM * n],
+            vel: vec![0.0; DIM * n],
+            acc: vec![0.0; DIM * n],
+            phi: vec![0.0; n],
+        };
+        for (i, b) in self.bodies.iter().enumerate() {
+            let h = s - b.s;
+            for d in 0..DIM {
+                let k = DIM * i + d;
+                ret.pos[k] = b.x[d]
+                    + h * (b.v[d]
+                        + h * (b.a[d] / 2.0
+                            + h * (b.j[d] / 6.0 + h * (b.a2[d] / 24.0 + h * b.a3[d] / 120.0))));
+                ret.vel[k] = b.v[d]
+                    + h * (b.a[d] + h * (b.j[d] / 2.0 + h * (b.a2[d] / 6.0 + h * b.a3[d] / 24.0)));
+                ret.acc[k] = b.newton.a[d] + h * b.newton.j[d];
+            }
+            ret.phi[i] = b.newton.phi + h * b.newton.phi_dot;
+        }
+        ret
+    }
+
+    fn eih(&self, i: usize, p: &Predicted) -> [f64; DIM] {
+        post_newtonian::eih_body_acceleration(i, &self.mass, &p.pos, &p.vel, &p.acc, &p.phi, self.c)
+    }
+
+    // acceleration, jerk and Newtonian force of the active bodies at s;
+    // eps is the time step of the difference for the 1PN jerk
+    fn forces(&self, active: &[usize], s: f64, eps: f64) -> Vec<([f64; DIM], [f64; DIM], Newton)> {
+        let mut p = self.predict(s);
+        let newton: Vec<Newton> = active
+            .iter()
+            .map(|&i| self.newton(i, &p.pos, &p.vel))
+            .collect();
+        for (&i, f) in active.iter().zip(newton.iter()) {
+            p.acc[DIM * i..DIM * (i + 1)].copy_from_slice(&f.a);
+            p.phi[i] = f.phi;
+        }
+        // the active bodies take their new Newtonian force at s +- eps too
+        let around = |h: f64| {
+            let mut q = self.predict(s + h);
+            for (&i, f) in active.iter().zip(newton.iter()) {
+                for d in 0..DIM {
+                    q.acc[DIM * i + d] = f.a[d] + h * f.j[d];
+                }
+                q.phi[i] = f.phi + h * f.phi_dot;
+            }
+            q
+        };
+        let pn = if self.pn1 {
+            Some((around(-eps), around(eps)))
+        } else {
+            None
+        };
+        let mut ret = Vec::with_capacity(active.len());
+        for (&i, f) in active.iter().zip(newton.iter()) {
+            let (mut a, mut j) = (f.a, f.j);
+            if let Some((minus, plus)) = &pn {
+                let (a_pn, a_minus, a_plus) =
+                    (self.eih(i, &p), self.eih(i, minus), self.eih(i, plus));
+                for d in 0..DIM {
+                    a[d] += a_pn[d];
+                    j[d] += (a_plus[d] - a_minus[d]) / (2.0 * eps);
+                }
+            }
+            ret.push((a, j, *f));
+        }
+        ret
+    }
+
+    // largest power of two up to dt (and dt_max) that divides s
+    fn quantize(&self, dt: f64, s: f64) -> f64 {
+        let mut ret = self.dt_max;
+        while (ret > dt || (s / ret).fract() != 0.0) && ret > self.dt_max * f64::EPSILON {
+            ret *= 0.5;
+        }
+        ret
+    }
+
+    // every body at x (the state of NBody::to_smp_vector)
+    fn start(&mut self, x: &SmpVector) {
+        let n = self.mass.len();
+        let (pos, vel) = (&x.vec[..DIM * n], &x.vec[DIM * n..2 * DIM * n]);
+        self.origin = x.time;
+        self.now = 0.0;
+        self.bodies = (0..n)
+            .map(|i| {
+                let newton = self.newton(i, pos, vel);
+                let mut b = Body {
+                    s: 0.0,
+                    dt: 0.0,
+                    x: [0.0; DIM],
+                    v: [0.0; DIM],
+                    a: newton.a,
+                    j: newton.j,
+                    a2: [0.0; DIM],
+                    a3: [0.0; DIM],
+                    newton,
+                };
+                b.x.copy_from_slice(&pos[DIM * i..DIM * (i + 1)]);
+                b.v.copy_from_slice(&vel[DIM * i..DIM * (i + 1)]);
+                b
+            })
+            .collect();
+        // the first step (a^(2) and a^(3) are not known yet)
+        let eta = self.eta;
+        let first = |bodies: &[Body]| {
+            bodies
+                .iter()
+                .map(|b| 0.5 * eta * norm(&b.a) / norm(&b.j))
+                .collect::<Vec<f64>>()
+        };
+        if self.pn1 {
+            let all: Vec<usize> = (0..n).collect();
+            let eps = 1.0e-3
+                * first(&self.bodies)
+                    .iter()
+                    .fold(self.dt_max, |m, dt| m.min(*dt));
+            // the jerk is taken along the predicted motion, which needs the
+            // whole acceleration: once more with it
+            for _ in 0..2 {
+                let forces = self.forces(&all, 0.0, eps);
+                for (b, (a, j, _)) in self.bodies.iter_mut().zip(forces) {
+                    b.a = a;
+                    b.j = j;
+                }
+            }
+        }
+        for (i, dt) in first(&self.bodies).into_iter().enumerate() {
+            self.bodies[i].dt = self.quantize(dt, 0.0);
+        }
+        self.x = x.clone();
+    }
+
+    // x in t' = -t with the velocities reversed when going backward (and back)
+    fn reflect(&self, x: &SmpVector) -> SmpVector {
+        let mut ret = x.clone();
+        if self.direction < 0.0 {
+            let n = self.mass.len();
+            ret.time = -x.time;
+            for v in ret.vec[DIM * n..2 * DIM * n].iter_mut() {
+                *v = -*v;
+            }
+        }
+        ret
+    }
+
+    // start again unless x is the state returned last
+    fn resume(&mut self, x: &SmpVector) {
+        if self.bodies.is_empty() || x.time != self.x.time || x.vec != self.x.vec {
+            self.start(x);
+        }
+    }
+
+    // Advance the bodies due next, or every body to s_end (time since origin)
+    // if it comes first. Returns whether the bodies were synchronized.
+    fn block_step(&mut self, s_end: f64) -> bool {
+        let mut s = self
+            .bodies
+            .iter()
+            .fold(f64::INFINITY, |m, b| m.min(b.s + b.dt));
+        let sync = s >= s_end;
+        if sync {
+            s = s_end;
+        }
+        let active: Vec<usize> = (0..self.bodies.len())
+            .filter(|&i| sync || self.bodies[i].s + self.bodies[i].dt == s)
+            .collect();
+        let eps = 1.0e-3
+            * active
+                .iter()
+                .fold(f64::INFINITY, |m, &i| m.min(s - self.bodies[i].s));
+        let forces = self.forces(&active, s, eps);
+        for (&i, (a1, j1, newton)) in active.iter().zip(forces) {
+            let b = &self.bodies[i];
+            let h = s - b.s;
+            let mut next = b.clone();
+            for d in 0..DIM {
+                next.v[d] = b.v[d] + h / 2.0 * (b.a[d] + a1[d]) + h * h / 12.0 * (b.j[d] - j1[d]);
+                next.x[d] =
+                    b.x[d] + h / 2.0 * (b.v[d] + next.v[d]) + h * h / 12.0 * (b.a[d] - a1[d]);
+                next.a3[d] = (12.0 * (b.a[d] - a1[d]) + 6.0 * h * (b.j[d] + j1[d])) / (h * h * h);
+                next.a2[d] = (-6.0 * (b.a[d] - a1[d]) - h * (4.0 * b.j[d] + 2.0 * j1[d])) / (h * h)
+                    + h * next.a3[d];
+            }
+            next.a = a1;
+            next.j = j1;
+            next.newton = newton;
+            let (a, j, a2, a3) = (norm(&next.a), norm(&next.j), norm(&next.a2), norm(&next.a3));
+            let dt = (self.eta * (a * a2 + j * j) / (j * a3 + a2 * a2)).sqrt();
+            next.s = s;
+            next.dt = self.quantize(dt.min(2.0 * h), if sync { 0.0 } else { s });
+            self.bodies[i] = next;
+        }
+        self.body_steps += active.len() as i64;
+        self.stats.record(true, s - self.now);
+        // in forces on every body
+        self.stats.evaluations = self.body_steps / self.bodies.len() as i64;
+        self.now = s;
+        if sync {
+            self.origin += s_end;
+            self.now = 0.0;
+            for b in self.bodies.iter_mut() {
+                b.s = 0.0;
+            }
+        }
+        sync
+    }
+
+    // every body at the last block time (exactly the ones that stepped)
+    fn state(&self) -> SmpVector {
+        let p = self.predict(self.now);
+        let mut vec = p.pos;
+        vec.extend(p.vel);
+        SmpVector {
+            time: self.origin + self.now,
+            vec,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn solve_to_end_time(
+        &mut self,
+        end_time: f64,
+        func: impl Fn(&SmpVector) -> SmpVector,
+        x: &SmpVector,
+    ) -> SmpVector {
+        self.integrate_to(end_time, &func, x)
+    }
+}
+
+pub fn check(name: &str, n_body: &NBody) -> Result<(), &'static str> {
+    if name == NAME {
+        if n_body.mode != Mode::NBody {
+            return Err("Hermite needs Mode: NBody");
+        }
+        if n_body.spin.is_some() {
+            return Err("Hermite has no spins");
+        }
+        let pn = &n_body.post_newtonian;
+        if pn.pn2 || pn.pn25 || pn.is_spin_enabled() {
+            return Err("Hermite goes up to 1PN");
+        }
+    }
+    Ok(())
+}
+
+// The forces come from the NBody given to new(); func is not used.
+impl Integrator for Hermite {
+    // the bodies over dt, synchronized at the end
+    fn step(&self, func: &dyn Fn(&SmpVector) -> SmpVector, x: &SmpVector) -> SmpVector {
+        let mut sol = self.clone();
+        sol.bodies.clear();
+        sol.integrate_to(x.time + self.dt, func, x)
+    }
+
+    // one block step; every body is predicted to its time
+    fn adaptive_step(
+        &mut self,
+        _func: &dyn Fn(&SmpVector) -> SmpVector,
+        x: &SmpVector,
+    ) -> SmpVector {
+        self.resume(&self.reflect(x));
+        self.block_step(f64::INFINITY);
+        self.x = self.state();
+        self.reflect(&self.x)
+    }
+
+    // block steps until end_time, where the bodies are synchronized
+    fn integrate_to(
+        &mut self,
+        end_time: f64,
+        _func: &dyn Fn(&SmpVector) -> SmpVector,
+        x: &SmpVector,
+    ) -> SmpVector {
+        if x.time == end_time {
+            return x.clone();
+        }
+        self.set_direction((end_time - x.time).signum());
+        self.resume(&self.reflect(x));
+        let end_time = self.direction * end_time;
+        while !self.block_step(end_time - self.origin) {}
+        self.origin = end_time;
+        self.x = self.state();
+        self.reflect(&self.x)
+    }
+
+    // the bodies start again in the other direction
+    fn set_direction(&mut self, direction: f64) {
+        if direction * self.direction < 0.0 {
+            self.direction = -self.direction;
+            self.dt = -self.dt;
+            self.bodies.clear();
+        }
+    }
+
+    fn set_delta_t(&mut self, dt: f64) {
+        self.dt = dt;
+    }
+
+    fn get_delta_t(&self) -> f64 {
+        self.dt
+    }
+
+    fn get_num_of_steps(&self) -> i64 {
+        self.stats.accepted
+    }
+
+    fn get_num_of_dt_switch(&self) -> i64 {
+        self.stats.rejected
+    }
+
+    // the steps come from the Aarseth criterion
+    fn set_controller(&mut self, _controller: PIController) {}
+
+    fn statistics(&self) -> &StepStatistics {
+        &self.stats
+    }
+}
+
+#[cfg(test)]
+pub mod hermite_tests {
+    extern crate approx;
+    use crate::num_core::dop54::DOP54;
+    use crate::num_core::hermite::{self, Hermite, ETA};
+    use crate::num_core::integrator::Integrator;
+    use crate::num_core::n_body::NBody;
+    use crate::num_core::smp_vector::SmpVector;
+    use crate::num_core::step_control::{PIController, Tolerance};
+
+    // tight binary (separation 0.1) and a distant third body
+    const TRIPLE: &str = "
+SettingName: \"triple\"
+NumberOfBodies: 3
+Mass: [1.0, 1.0, 0.1]
+Position: [[-0.05, 0.0, 0.0], [0.05, 0.0, 0.0], [5.0, 0.0, 0.0]]
+Velocity: [[0.0, -2.2360679775, 0.0], [0.0, 2.2360679775, 0.0], [0.0, 0.632455532, 0.0]]
+";
+
+    fn reference(n_body: &NBody, end_time: f64) -> SmpVector {
+        let mut dop54 = DOP54::new(1.0e-4, 0.1, 1.0e-13, 1.0, 0.9);
+        dop54.set_controller(PIController::new(
+            Tolerance::Scalar(1.0e-13),
+            Tolerance::Scalar(1.0e-13),
+        ));
+        dop54.solve_to_end_time(end_time, n_body.eom(), &n_body.to_smp_vector())
+    }
+
+    #[test]
+    fn block_steps() {
+        let n_body: NBody = serde_yaml::from_str(TRIPLE).unwrap();
+        let x = n_body.to_smp_vector();
+        let z = reference(&n_body, 2.0);
+        let run = |eta: f64| {
+            let mut sol = Hermite::new(&n_body, 1.0, 1.0, eta);
+            let y = sol.solve_to_end_time(2.0, n_body.eom(), &x);
+            approx::assert_abs_diff_eq!(y.time, 2.0, epsilon = 1.0e-15);
+            ((&y - &z).norm(), sol)
+        };
+        // 4th order: dt ~ eta^(1/2)
+        let (coarse, _) = run(ETA);
+        let (fine, sol) = run(ETA / 16.0);
+        assert!(fine < 1.0e-6);
+        assert!(fine < 1.0e-2 * coarse);
+        // a shared step would move all three bodies in every block
+        assert!(4 * sol.body_steps() < 3 * 3 * sol.get_num_of_steps());
+    }
+
+    #[test]
+    fn eih_triple() {
+        let n_body: NBody = serde_yaml::from_str(&format!(
+            "{}SpeedOfLight: 100.0\nPostNewtonian:\n  Pn1: true\n",
+            TRIPLE
+        ))
+        .unwrap();
+        let x = n_body.to_smp_vector();
+        let mut sol = Hermite::new(&n_body, 1.0, 1.0, ETA / 16.0);
+        // through the synchronizations at the output times
+        let mut y = x.clone();
+        for k in 1..=4 {
+            y = sol.integrate_to(0.5 * k as f64, &n_body.eom(), &y);
+        }
+        let z = reference(&n_body, 2.0);
+        assert!((&y - &z).norm() < 1.0e-6);
+        // the 1PN terms do matter here
+        let newton: NBody = serde_yaml::from_str(TRIPLE).unwrap();
+        assert!((&reference(&newton, 2.0) - &z).norm() > 1.0e-3);
+    }
+
+    #[test]
+    fn check() {
+        let mut n_body: NBody = serde_yaml::from_str(TRIPLE).unwrap();
+        assert!(hermite::check("Hermite", &n_body).is_ok());
+        n_body.post_newtonian.pn2 = true;
+        assert_eq!(
+            hermite::check("Hermite", &n_body),
+            Err("Hermite goes up to 1PN")
+        );
+        assert!(hermite::check("DOP54", &n_body).is_ok());
+    }
+}
@@ -0,0 +1,375 @@
+//
+// IAS15: 15th order Gauss–Radau integrator with adaptive steps.
+//
+// The state is split as in symplectic: positions q (the first `positions`
+// components, q' = v) and the rest p (velocities, then spins), p' = a(q, p).
+// Over a step dt, with h = (t - t0) / dt,
+//   a(h) = a0 + b_0 h + b_1 h^2 + ... + b_6 h^7,
+// and q(h), p(h) are its integrals. The b_k follow from a at the 7 Gauss–Radau
+// nodes h_1 .. h_7 (through the divided differences g_k), which need q and p
+// there: the nodes are iterated (predictor–corrector) until b_6 settles, so
+// forces that depend on the velocity are handled as well.
+// The step is accepted if dt_new = dt (epsilon / err)^(1/7) >= dt / 4, with
+// err = max |b_6| / max |a| over the velocity components. Round-off keeps err
+// above ~1e-12 whatever dt is, so epsilon is not AbsTol: EPSILON already gives
+// errors at the round-off level.
+//
+// ref. Rein and Spiegel, MNRAS 446 (2015) 1424. Everhart, in Dynamics of Comets
+// (1985) 185.
+//
+use crate::num_core::integrator::Integrator;
+use crate::num_core::n_body::{NBody, DIM};
+use crate::num_core::smp_vector::SmpVector;
+use crate::num_core::step_control::{PIController, StepStatistics};
+
+// name in the run configuration (YAML: Run: Integrator)
+pub const NAME: &str = "IAS15";
+
+// Gauss–Radau nodes on [0, 1]
+#[allow(clippy::excessive_precision)]
+const H: [f64; 8] = [
+    0.0,
+    0.0562625605369221464656521910318,
+    0.180240691736892364987579942780,
+    0.352624717113169637373907769648,
+    0.547153626330555383001448554766,
+    0.734210177215410531523210605558,
+    0.885320946839095768090359771030,
+    0.977520613561287501891174488626,
+];
+
+// precision parameter of Rein and Spiegel
+pub const EPSILON: f64 = 1.0e-9;
+
+// dt_new / dt below which a step is rejected, and 1 / (its largest growth)
+const SAFETY: f64 = 0.25;
+
+#[allow(clippy::upper_case_acronyms)]
+pub struct IAS15 {
+    positions: usize,
+    dt: f64,
+    dt_max: f64,
+    epsilon: f64,
+    // a(h) - a0 = sum_k b_k h^(k+1) = sum_k g_k prod_{m <= k} (h - h_m)
+    b: Vec<Vec<f64>>,
+    g: Vec<Vec<f64>>,
+    // b_k = sum_j c[k][j] g_j
+    c: [[f64; 7]; 7],
+    stats: StepStatistics,
+}
+
+impl IAS15 {
+    pub fn new(positions: usize, dt: f64, dt_max: f64, epsilon: f64) -> IAS15 {
+        // coefficients of the Newton polynomials prod_{m <= j} (h - h_m)
+        let mut c = [[0.0; 7]; 7];
+        let mut poly = vec![0.0, 1.0];
+        for j in 0..7 {
+            if j > 0 {
+                let mut next = vec![0.0; poly.len() + 1];
+                for (i, p) in poly.iter().enumerate() {
+                    next[i + 1] += p;
+                    next[i] -= H[j] * p;
+                }
+                poly = next;
+            }
+            for (k, ck) in c.iter_mut().enumerate().take(j + 1) {
+                ck[j] = poly[k + 1];
+            }
+        }
+        IAS15 {
+            positions,
+            dt,
+            dt_max,
+            epsilon,
+            b: Vec::new(),
+            g: Vec::new(),
+            c,
+            stats: StepStatistics::new(),
+        }
+    }
+
+    // for the state of n_body (NBody::to_smp_vector)
+    pub fn for_n_body(n_body: &NBody, dt: f64, dt_max: f64, epsilon: f64) -> IAS15 {
+        IAS15::new(DIM * n_body.num_of_points(), dt, dt_max, epsilon)
+    }
+
+    // g from b (c is unit upper triangular)
+    fn g_from_b(&self, b: &[Vec<f64>]) -> Vec<Vec<f64>> {
+        let mut g = b.to_vec();
+        for j in (0..7).rev() {
+            for i in (j + 1)..7 {
+                let (lower, upper) = g.split_at_mut(i);
+                for (gj, gi) in lower[j].iter_mut().zip(upper[0].iter()) {
+                    *gj -= self.c[j][i] * gi;
+                }
+            }
+        }
+        g
+    }
+
+    // b and g of a(h) over a step of dt * ratio from the same start
+    fn rescale(&mut self, ratio: f64) {
+        let mut q = 1.0;
+        for bk in self.b.iter_mut() {
+            q *= ratio;
+            for v in bk.iter_mut() {
+                *v *= q;
+            }
+        }
+        self.g = self.g_from_b(&self.b);
+    }
+
+    // the same polynomial, expanded around the end of the step, for the next
+    // step of dt * ratio: h = 1 + ratio s
+    fn predict(&mut self, ratio: f64) {
+        let len = self.b[0].len();
+        let mut next = vec![vec![0.0; len]; 7];
+        for (k, nk) in next.iter_mut().enumerate() {
+            let q = ratio.powi(k as i32 + 1);
+            for m in k..7 {
+                let binomial = binomial(m + 1, k + 1);
+                for (v, bm) in nk.iter_mut().zip(self.b[m].iter()) {
+                    *v += q * binomial * bm;
+                }
+            }
+        }
+        self.b = next;
+        self.g = self.g_from_b(&self.b);
+    }
+
+    // state at node h of the step dt from x, with a0 = a(x)
+    fn state_at(&self, x: &SmpVector, a0: &[f64], b: &[Vec<f64>], dt: f64, h: f64) -> SmpVector {
+        let n = self.positions;
+        let mut ret = x.clone();
+        ret.time = x.time + h * dt;
+        for i in 0..a0.len() {
+            // int_0^h a and int_0^h int_0^s a, in Horner form
+            let mut dv = 0.0;
+            let mut dq = 0.0;
+            for k in (0..7).rev() {
+                dv = h * (dv + b[k][i] / (k + 2) as f64);
+                dq = h * (dq + b[k][i] / ((k + 2) * (k + 3)) as f64);
+            }
+            dv = h * (dv + a0[i]);
+            dq = h * h * (dq + 0.5 * a0[i]);
+            ret.vec[n + i] += dt * dv;
+            if i < n {
+                ret.vec[i] += dt * h * x.vec[n + i] + dt * dt * dq;
+            }
+        }
+        ret
+    }
+
+    // Predictor–corrector iterations of b and g over the step dt from x.
+    // Returns the largest |a| at the last node and the RHS evaluations.
+    fn iterate(
+        &self,
+        func: &dyn Fn(&SmpVector) -> SmpVector,
+        x: &SmpVector,
+        a0: &[f64],
+        b: &mut [Vec<f64>],
+        g: &mut [Vec<f64>],
+        dt: f64,
+    ) -> (f64, i64) {
+        let n = self.positions;
+        let len = a0.len();
+        let mut evaluations = 0;
+        let mut max_a = 0.0;
+        let mut last_change = f64::INFINITY;
+        for iteration in 0..12 {
+            let b6 = b[6].clone();
+            for s in 1..8 {
+                let a = func(&self.state_at(x, a0, b, dt, H[s]));
+                evaluations += 1;
+                for i in 0..len {
+                    // divided difference g_(s-1) at the nodes h_0 .. h_s
+                    let mut gi = (a.vec[n + i] - a0[i]) / H[s];
+                    for m in 1..s {
+                        gi = (gi - g[m - 1][i]) / (H[s] - H[m]);
+                    }
+                    let dg = gi - g[s - 1][i];
+                    g[s - 1][i] = gi;
+                    for (bk, ck) in b.iter_mut().zip(self.c.iter()).take(s) {
+                        bk[i] += ck[s - 1] * dg;
+                    }
+                }
+                if s == 7 {
+                    max_a = a.vec[n..2 * n].iter().fold(0.0_f64, |m, v| m.max(v.abs()));
+                }
+            }
+            // relative change of b_6 (velocity components)
+            let change = (0..n.min(len)).fold(0.0_f64, |m, i| m.max((b[6][i] - b6[i]).abs()));
+            let change = if max_a > 0.0 { change / max_a } else { 0.0 };
+            if change < 1.0e-16 || (iteration > 1 && change >= last_change) {
+                break;
+            }
+            last_change = change;
+        }
+        (max_a, evaluations)
+    }
+
+    // Adaptive step; a rejected one returns x (see Integrator::adaptive_step).
+    pub fn solve(&mut self, func: &dyn Fn(&SmpVector) -> SmpVector, x: &SmpVector) -> SmpVector {
+        let n = self.positions;
+        let a0 = func(x).vec[n..].to_vec();
+        if self.b.len() != 7 || self.b[0].len() != a0.len() {
+            self.b = vec![vec![0.0; a0.len()]; 7];
+            self.g = self.b.clone();
+        }
+        let dt = self.dt;
+        let mut b = std::mem::take(&mut self.b);
+        let mut g = std::mem::take(&mut self.g);
+        let (max_a, evaluations) = self.iterate(func, x, &a0, &mut b, &mut g, dt);
+        self.b = b;
+        self.g = g;
+        self.stats.evaluations += evaluations + 1;
+
+        let max_b6 = self.b[6][..n.min(a0.len())]
+            .iter()
+            .fold(0.0_f64, |m, v| m.max(v.abs()));
+        let err = if max_a > 0.0 { max_b6 / max_a } else { 0.0 };
+        let ratio = if err > 0.0 {
+            (self.epsilon / err).powf(1.0 / 7.0)
+        } else {
+            1.0 / SAFETY
+        };
+        let ratio = ratio.min(self.dt_max / dt.abs());
+        if ratio < SAFETY {
+            self.stats.record(false, dt);
+            self.dt = dt * ratio;
+            self.rescale(ratio);
+            return x.clone();
+        }
+        self.stats.record(true, dt);
+        let mut y = self.state_at(x, &a0, &self.b, dt, 1.0);
+        y.time = dt + x.time;
+        let ratio = ratio.min(1.0 / SAFETY);
+        self.dt = dt * ratio;
+        self.predict(ratio);
+        y
+    }
+
+    #[allow(dead_code)]
+    pub fn solve_to_end_time(
+        &mut self,
+        end_time: f64,
+        func: impl Fn(&SmpVector) -> SmpVector,
+        x: &SmpVector,
+    ) -> SmpVector {
+        self.integrate_to(end_time, &func, x)
+    }
+}
+
+fn binomial(n: usize, k: usize) -> f64 {
+    (0..k).fold(1.0, |acc, i| acc * (n - i) as f64 / (i + 1) as f64)
+}
+
+impl Integrator for IAS15 {
+    // one step of dt from a(h) = a0, without error control
+    fn step(&self, func: &dyn Fn(&SmpVector) -> SmpVector, x: &SmpVector) -> SmpVector {
+        let a0 = func(x).vec[self.positions..].to_vec();
+        let mut b = vec![vec![0.0; a0.len()]; 7];
+        let mut g = b.clone();
+        self.iterate(func, x, &a0, &mut b, &mut g, self.dt);
+        let mut y = self.state_at(x, &a0, &b, self.dt, 1.0);
+        y.time = self.dt + x.time;
+        y
+    }
+
+    fn adaptive_step(
+        &mut self,
+        func: &dyn Fn(&SmpVector) -> SmpVector,
+        x: &SmpVector,
+    ) -> SmpVector {
+        self.solve(func, x)
+    }
+
+    // b and g follow the new dt; far from it (a step shortened to an output
+    // time and back) the prediction is useless and starts again from a0
+    fn set_delta_t(&mut self, dt: f64) {
+        let ratio = dt / self.dt;
+        if (SAFETY..=1.0 / SAFETY).contains(&ratio) {
+            if !self.b.is_empty() {
+                self.rescale(ratio);
+            }
+        } else {
+            self.b.clear();
+            self.g.clear();
+        }
+        self.dt = dt;
+    }
+
+    fn get_delta_t(&self) -> f64 {
+        self.dt
+    }
+
+    fn get_num_of_steps(&self) -> i64 {
+        self.stats.accepted + self.stats.rejected
+    }
+
+    fn get_num_of_dt_switch(&self) -> i64 {
+        self.stats.rejected
+    }
+
+    // the step control is that of IAS15 itself
+    fn set_controller(&mut self, _controller: PIController) {}
+
+    fn statistics(&self) -> &StepStatistics {
+        &self.stats
+    }
+}
+
+#[cfg(test)]
+pub mod ias15_tests {
+    extern crate approx;
+    use crate::num_core::ias15::{EPSILON, IAS15};
+    use crate::num_core::integrator::Integrator;
+    use crate::num_core::smp_vector::SmpVector;
+    use crate::num_core::symplectic::kepler_drift;
+    use crate::num_core::test_problems::{self, pericenter, relative_kepler};
+    use std::f64::consts::PI;
+
+    #[test]
+    fn eccentric_orbits() {
+        for (e, epsilon) in [(0.6, 1.0e-11), (0.99, 1.0e-9)].iter() {
+            let x = pericenter(*e);
+            let mut sol = IAS15::new(3, 1.0e-3, 1.0, EPSILON);
+            let y = sol.solve_to_end_time(10.0 * PI, relative_kepler, &x);
+            let (mut r, mut v) = ([x.vec[0], 0.0, 0.0], [0.0, x.vec[4], 0.0]);
+            kepler_drift(1.0, &mut r, &mut v, 10.0 * PI);
+            for d in 0..3 {
+                approx::assert_abs_diff_eq!(y.vec[d], r[d], epsilon = *epsilon);
+                approx::assert_abs_diff_eq!(y.vec[3 + d], v[d], epsilon = *epsilon * 100.0);
+            }
+            // the steps shrink at the pericenter passages
+            let stats = sol.statistics();
+            if *e > 0.9 {
+                assert!(stats.min_dt < 1.0e-3 * stats.max_dt);
+            }
+        }
+    }
+
+    #[test]
+    fn velocity_dependent() {
+        // with a drag the speed and the radius shrink as exp(-k t)
+        let k = 0.1;
+        let lorentz = test_problems::lorentz(1.0, k);
+        // circles around (0, 0) for k = 0: center at the origin with v = (0, -r, 0)
+        let x = SmpVector {
+            time: 0.0,
+            vec: vec![1.0, 0.0, 0.0, 0.0, -1.0, 0.0],
+        };
+        let mut sol = IAS15::new(3, 1.0e-2, 1.0, EPSILON);
+        let y = sol.solve_to_end_time(10.0, lorentz, &x);
+        let speed = y.vec[3].hypot(y.vec[4]);
+        approx::assert_relative_eq!(speed, (-k * 10.0_f64).exp(), max_relative = 1.0e-12);
+        // step() without control, with a small dt
+        let mut z = x.clone();
+        let fixed = IAS15::new(3, 0.1, 1.0, EPSILON);
+        for _ in 0..100 {
+            z = fixed.step(&lorentz, &z);
+        }
+        approx::assert_relative_eq!(z.vec[3].hypot(z.vec[4]), speed, max_relative = 1.0e-12);
+        assert_eq!(fixed.get_num_of_steps(), 0);
+    }
+}
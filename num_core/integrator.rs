@@ -0,0 +1,363 @@
+//
+// Common interface of the ODE integrators and the driver loop written once on top of it.
+//
+use crate::num_core::butcher_tableau::ButcherTableau;
+use crate::num_core::dop54::DOP54;
+use crate::num_core::dop853::DOP853;
+use crate::num_core::explicit_rk::ExplicitRK;
+use crate::num_core::gauss_legendre::GaussLegendre;
+use crate::num_core::gbs::GBS;
+use crate::num_core::rkf78::RKF78;
+use crate::num_core::smp_vector::SmpVector;
+use crate::num_core::step_control::{PIController, StepStatistics};
+
+// names accepted by from_name (YAML: Run: Integrator)
+pub const NAMES: [&str; 11] = [
+    "DOP54",
+    "DOP853",
+    "RKF78",
+    "GBS",
+    "GaussLegendre4",
+    "GaussLegendre6",
+    "GaussLegendre8",
+    "RK4",
+    "BS32",
+    "CashKarp45",
+    "Verner65",
+];
+
+// integrators with dense output (Integrator::set_dense_output)
+pub const DENSE_OUTPUT_NAMES: [&str; 2] = ["DOP54", "DOP853"];
+
+pub trait Integrator {
+    // One step of size dt with the solution that is propagated, without error control.
+    #[allow(dead_code)]
+    fn step(&self, func: &dyn Fn(&SmpVector) -> SmpVector, x: &SmpVector) -> SmpVector;
+
+    // One step with error control. A rejected step returns x itself (same time)
+    // and shrinks dt; an accepted one returns the new state and may enlarge dt.
+    fn adaptive_step(&mut self, func: &dyn Fn(&SmpVector) -> SmpVector, x: &SmpVector)
+        -> SmpVector;
+
+    fn set_delta_t(&mut self, dt: f64);
+
+    fn get_delta_t(&self) -> f64;
+
+    // accepted + rejected steps
+    #[allow(dead_code)]
+    fn get_num_of_steps(&self) -> i64;
+
+    // rejected steps
+    #[allow(dead_code)]
+    fn get_num_of_dt_switch(&self) -> i64;
+
+    // error norm with atol/rtol and PI step-size control (step_control)
+    fn set_controller(&mut self, controller: PIController);
+
+    // accepted and rejected steps, RHS evaluations and the range of dt so far
+    fn statistics(&self) -> &StepStatistics;
+
+    // Keep the continuous extension of every accepted step (dense_output).
+    fn set_dense_output(&mut self, dense_output: bool) -> Result<(), &'static str> {
+        if dense_output {
+            return Err("DenseOutput is only available for DOP54 and DOP853");
+        }
+        Ok(())
+    }
+
+    fn has_dense_output(&self) -> bool {
+        false
+    }
+
+    // State at t within the last accepted step, None without dense output.
+    fn dense_output(&self, _t: f64) -> Option<SmpVector> {
+        None
+    }
+
+    // Turn dt towards the sign of direction (negative: backward in time).
+    fn set_direction(&mut self, direction: f64) {
+        let dt = self.get_delta_t();
+        if dt * direction < 0.0 {
+            self.set_delta_t(-dt);
+        }
+    }
+
+    // Adaptive steps up to end_time; the last step is shortened to land on it
+    // exactly, and the step size proposed before it is kept for the next call.
+    // end_time before x.time integrates backward (dt < 0).
+    fn integrate_to(
+        &mut self,
+        end_time: f64,
+        func: &dyn Fn(&SmpVector) -> SmpVector,
+        x: &SmpVector,
+    ) -> SmpVector {
+        if x.time == end_time {
+            return x.clone();
+        }
+        let mut x = x.clone();
+        let direction = (end_time - x.time).signum();
+        self.set_direction(direction);
+        while (end_time - x.time) * direction > 0.0 {
+            let dt = self.get_delta_t();
+            if (end_time - x.time - dt) * direction > 0.0 {
+                x = self.adaptive_step(func, &x);
+                continue;
+            }
+            self.set_delta_t(end_time - x.time);
+            let y = self.adaptive_step(func, &x);
+            // rejected: retry with the shorter step
+            if y.time == x.time {
+                continue;
+            }
+            x = y;
+            x.time = end_time;
+            self.set_delta_t(dt);
+        }
+        x
+    }
+
+    // State at time t_out with dense output: x takes whole adaptive steps until
+    // it reaches t_out and the state at t_out is interpolated in the last one,
+    // so no step is shortened. Without dense output x is integrated to t_out.
+    // Successive calls must have t_out moving on in the same direction.
+    fn advance_to(
+        &mut self,
+        t_out: f64,
+        func: &dyn Fn(&SmpVector) -> SmpVector,
+        x: &mut SmpVector,
+    ) -> SmpVector {
+        if !self.has_dense_output() {
+            *x = self.integrate_to(t_out, func, x);
+            return x.clone();
+        }
+        // t_out behind x lies in the last step, or reverses the direction
+        if (t_out - x.time) * self.get_delta_t() < 0.0 && self.dense_output(t_out).is_none() {
+            self.set_direction((t_out - x.time).signum());
+        }
+        let direction = self.get_delta_t().signum();
+        while (t_out - x.time) * direction > 0.0 {
+            *x = self.adaptive_step(func, x);
+        }
+        if x.time == t_out {
+            return x.clone();
+        }
+        self.dense_output(t_out).unwrap()
+    }
+}
+
+// Select an integrator by name with the adaptive-step settings shared by all of them.
+pub fn from_name(
+    name: &str,
+    dt: f64,
+    dt_max: f64,
+    abs_tol: f64,
+    dt_scale_up_factor: f64,
+    dt_scale_down_factor: f64,
+) -> Result<Box<dyn Integrator>, &'static str> {
+    let tableau = match name {
+        "DOP54" => {
+            return Ok(Box::new(DOP54::new(
+                dt,
+                dt_max,
+                abs_tol,
+                dt_scale_up_factor,
+                dt_scale_down_factor,
+            )))
+        }
+        "DOP853" => {
+            return Ok(Box::new(DOP853::new(
+                dt,
+                dt_max,
+                abs_tol,
+                dt_scale_up_factor,
+                dt_scale_down_factor,
+            )))
+        }
+        "RKF78" => {
+            return Ok(Box::new(RKF78::new(
+                dt,
+                dt_max,
+                abs_tol,
+                dt_scale_up_factor,
+                dt_scale_down_factor,
+            )))
+        }
+        // extrapolation, with its own order and step control
+        "GBS" => return Ok(Box::new(GBS::new(dt, dt_max, abs_tol))),
+        // implicit, fixed step size dt
+        "GaussLegendre4" => return Ok(Box::new(GaussLegendre::new(2, dt))),
+        "GaussLegendre6" => return Ok(Box::new(GaussLegendre::new(3, dt))),
+        "GaussLegendre8" => return Ok(Box::new(GaussLegendre::new(4, dt))),
+        // explicit Runge-Kutta from a tableau: RK4 has no embedded formula and
+        // keeps the step size dt, the others are adaptive
+        "RK4" => ButcherTableau::rk4(),
+        "BS32" => ButcherTableau::bogacki_shampine32(),
+        "CashKarp45" => ButcherTableau::cash_karp45(),
+        "Verner65" => ButcherTableau::verner65(),
+        _ => return Err("unknown Integrator"),
+    };
+    Ok(Box::new(ExplicitRK::new(
+        tableau,
+        dt,
+        dt_max,
+        abs_tol,
+        dt_scale_up_factor,
+        dt_scale_down_factor,
+    )))
+}
+
+#[cfg(test)]
+pub mod integrator_tests {
+    extern crate approx;
+    use crate::num_core::integrator::{self, Integrator};
+    use crate::num_core::smp_vector::SmpVector;
+    use crate::num_core::test_problems::{self, oscillator, KEPLER_PERIOD};
+
+    // written once against the trait
+    fn half_period(sol: &mut dyn Integrator) -> SmpVector {
+        let x = SmpVector {
+            time: 0.0,
+            vec: vec![1.0, 0.0],
+        };
+        let half = sol.integrate_to(0.5 * std::f64::consts::PI, &oscillator, &x);
+        sol.integrate_to(std::f64::consts::PI, &oscillator, &half)
+    }
+
+    #[test]
+    fn every_integrator() {
+        for name in integrator::NAMES.iter() {
+            let mut sol = integrator::from_name(name, 1.0e-3, 0.1, 1.0e-13, 1.01, 0.9).unwrap();
+            let x = half_period(sol.as_mut());
+            assert_eq!(x.time, std::f64::consts::PI);
+            approx::assert_abs_diff_eq!(x.vec[0], -1.0, epsilon = 1.0e-10);
+            approx::assert_abs_diff_eq!(x.vec[1], 0.0, epsilon = 1.0e-10);
+            assert!(sol.get_num_of_steps() > 0);
+            assert!(sol.get_delta_t() <= 0.1);
+            let stats = sol.statistics();
+            assert_eq!(stats.accepted + stats.rejected, sol.get_num_of_steps());
+            assert_eq!(stats.rejected, sol.get_num_of_dt_switch());
+            assert!(stats.evaluations >= 3 * sol.get_num_of_steps());
+            assert!(stats.min_dt <= stats.max_dt && stats.max_dt <= 0.1);
+        }
+        assert!(integrator::from_name("Euler", 1.0e-3, 0.1, 1.0e-13, 1.01, 0.9).is_err());
+    }
+
+    #[test]
+    fn calc_ode_napier() {
+        for name in ["DOP853", "RKF78", "Verner65"].iter() {
+            let mut sol = integrator::from_name(name, 1.0e-1, 0.2, 1.0e-14, 1.003, 0.9).unwrap();
+            let x = SmpVector::set_one_fill(100);
+            let y = sol.integrate_to(1.0, &test_problems::exponential, &x);
+            assert_eq!(y.time, 1.0);
+            approx::assert_abs_diff_eq!(y.vec[99], std::f64::consts::E, epsilon = 1.0e-13);
+            assert!(sol.get_num_of_dt_switch() > 0);
+        }
+    }
+
+    #[test]
+    fn fewer_evaluations_than_dop54() {
+        // one period of the eccentric Kepler orbit at a tight tolerance
+        let n_body = test_problems::kepler();
+        let func = n_body.eom();
+        let evaluations = |name: &str| {
+            let mut sol = integrator::from_name(name, 1.0e-3, 0.1, 1.0e-14, 1.05, 0.9).unwrap();
+            let y = sol.integrate_to(KEPLER_PERIOD, &func, &n_body.to_smp_vector());
+            approx::assert_abs_diff_eq!(y.vec[0], -0.2, epsilon = 1.0e-9);
+            approx::assert_abs_diff_eq!(y.vec[1], 0.0, epsilon = 1.0e-9);
+            sol.statistics().evaluations
+        };
+        let dop54 = evaluations("DOP54");
+        for name in ["DOP853", "RKF78"].iter() {
+            assert!(2 * evaluations(name) < dop54);
+        }
+    }
+
+    #[test]
+    fn fixed_step() {
+        for name in integrator::NAMES.iter() {
+            let sol = integrator::from_name(name, 1.0e-2, 0.1, 1.0e-13, 1.01, 0.9).unwrap();
+            let mut x = SmpVector {
+                time: 0.0,
+                vec: vec![1.0, 0.0],
+            };
+            for _i in 0..100 {
+                x = sol.step(&oscillator, &x);
+            }
+            approx::assert_abs_diff_eq!(x.time, 1.0, epsilon = 1.0e-13);
+            // the 3rd order BS32 is the least accurate, O(dt^3)
+            approx::assert_abs_diff_eq!(x.vec[0], 1.0_f64.cos(), epsilon = 1.0e-7);
+            assert_eq!(sol.get_num_of_steps(), 0);
+        }
+    }
+
+    #[test]
+    fn backward() {
+        // the oscillator from t = 0 back to -pi, then forward to the start
+        for name in integrator::NAMES.iter() {
+            let mut sol = integrator::from_name(name, 1.0e-3, 0.1, 1.0e-13, 1.01, 0.9).unwrap();
+            let x = SmpVector {
+                time: 0.0,
+                vec: vec![1.0, 0.0],
+            };
+            let half = sol.integrate_to(-0.5 * std::f64::consts::PI, &oscillator, &x);
+            approx::assert_abs_diff_eq!(half.vec[1], 1.0, epsilon = 1.0e-10);
+            let y = sol.integrate_to(-std::f64::consts::PI, &oscillator, &half);
+            assert_eq!(y.time, -std::f64::consts::PI);
+            approx::assert_abs_diff_eq!(y.vec[0], -1.0, epsilon = 1.0e-10);
+            assert!(sol.get_delta_t() < 0.0 && sol.get_delta_t() >= -0.1);
+            let stats = sol.statistics();
+            assert!(stats.min_dt > 0.0 && stats.max_dt <= 0.1);
+            let z = sol.integrate_to(0.0, &oscillator, &y);
+            assert!(sol.get_delta_t() > 0.0);
+            approx::assert_abs_diff_eq!(z.vec[0], 1.0, epsilon = 1.0e-10);
+            approx::assert_abs_diff_eq!(z.vec[1], 0.0, epsilon = 1.0e-10);
+        }
+        // interpolated on the way back
+        for name in integrator::DENSE_OUTPUT_NAMES.iter() {
+            let mut sol = integrator::from_name(name, 1.0e-3, 1.0, 1.0e-10, 1.2, 0.9).unwrap();
+            sol.set_dense_output(true).unwrap();
+            let mut x = SmpVector {
+                time: 0.0,
+                vec: vec![1.0, 0.0],
+            };
+            for i in 1..=100 {
+                let t = -0.03 * i as f64;
+                let y = sol.advance_to(t, &oscillator, &mut x);
+                assert_eq!(y.time, t);
+                assert!(x.time <= t);
+                approx::assert_abs_diff_eq!(y.vec[0], t.cos(), epsilon = 1.0e-8);
+                approx::assert_abs_diff_eq!(y.vec[1], -t.sin(), epsilon = 1.0e-8);
+            }
+        }
+    }
+
+    #[test]
+    fn advance_to() {
+        // output every 0.01 over 3 time units
+        let sample = |name: &str, dense_output: bool| {
+            let mut sol = integrator::from_name(name, 1.0e-3, 1.0, 1.0e-10, 1.2, 0.9).unwrap();
+            assert!(sol.set_dense_output(dense_output).is_ok());
+            assert_eq!(sol.has_dense_output(), dense_output);
+            let mut x = SmpVector {
+                time: 0.0,
+                vec: vec![1.0, 0.0],
+            };
+            for i in 1..=300 {
+                let t = 0.01 * i as f64;
+                let y = sol.advance_to(t, &oscillator, &mut x);
+                assert_eq!(y.time, t);
+                assert!(x.time >= t);
+                approx::assert_abs_diff_eq!(y.vec[0], t.cos(), epsilon = 1.0e-8);
+                approx::assert_abs_diff_eq!(y.vec[1], -t.sin(), epsilon = 1.0e-8);
+            }
+            sol.get_num_of_steps()
+        };
+        for name in integrator::DENSE_OUTPUT_NAMES.iter() {
+            // the steps are no longer cut at every output time
+            assert!(3 * sample(name, true) < 2 * sample(name, false));
+        }
+        let mut sol = integrator::from_name("RKF78", 1.0e-3, 1.0, 1.0e-12, 1.2, 0.9).unwrap();
+        assert!(sol.set_dense_output(true).is_err());
+        assert!(sol.set_dense_output(false).is_ok());
+    }
+}
@@ -1,6 +1,13 @@
 //
 // Around IO and YAML parsing
 //
+use crate::num_core::events::EventSetting;
+use crate::num_core::hermite;
+use crate::num_core::ias15;
+use crate::num_core::integrator;
+use crate::num_core::step_control::{PIController, Tolerance};
+use crate::num_core::symplectic;
+use serde::{Deserialize, Serialize};
 use std::error::Error;
 use std::fs::File;
 use std::io::Read;
@@ -37,3 +44,218 @@ pub fn read_setting_yaml(config: Config) -> Result<String, Box<dyn Error>> {
 
     Ok(contents)
 }
+
+// Step-size control of the adaptive integrators (YAML: Run: StepControl).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum StepControl {
+    // scaled RMS error norm with AbsTol/RelTol and the PI controller (step_control)
+    PI,
+    // 2-norm of the error against a scalar AbsTol with ScaleUpFactor/ScaleDownFactor
+    ScaleFactor,
+}
+
+// Way back of the reversibility test (YAML: Run: Reversibility).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Reversibility {
+    // from EndTime back to the start with negative dt
+    NegativeDt,
+    // reverse the velocities (and spins) at EndTime and integrate forward as
+    // long again
+    ReverseVelocities,
+}
+
+// How to run the simulation (YAML: Run).
+// It sits at the top level of the setting file, next to the NBody keys.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct Run {
+    // one of integrator::NAMES, symplectic::NAMES (fixed step InitialDt),
+    // ias15::NAME or hermite::NAME
+    pub integrator: String,
+    pub initial_dt: f64,
+    pub max_dt: f64,
+    // a number or one value per component of the state vector
+    pub abs_tol: Tolerance,
+    pub rel_tol: Tolerance,
+    pub step_control: StepControl,
+    // StepControl: PI
+    pub safety: f64,
+    pub min_scale_factor: f64,
+    pub max_scale_factor: f64,
+    pub pi_beta: f64,
+    // StepControl: ScaleFactor
+    pub scale_up_factor: f64,
+    pub scale_down_factor: f64,
+    // precision parameter of IAS15, which has its own step control instead of
+    // AbsTol and RelTol
+    pub ias15_epsilon: f64,
+    // accuracy parameter of the Aarseth criterion of Hermite, which does not
+    // use AbsTol and RelTol either
+    pub hermite_eta: f64,
+    // negative: backward in time from the initial state
+    pub end_time: f64,
+    // time between two output lines
+    pub output_interval: f64,
+    // interpolate the output times instead of shortening the steps to hit them
+    pub dense_output: bool,
+    // shorthand for the PostNewtonian switches: 0, 1, 2, 2.5, 3 or 3.5
+    pub pn_order: Option<f64>,
+    // file for the drift of the conserved quantities, written every output
+    pub diagnostics_file: Option<String>,
+    // recorded, terminating or state-modifying events (events), need DenseOutput
+    pub events: Vec<EventSetting>,
+    // width of the time bracket that locates an event
+    pub event_time_tol: f64,
+    // file for the event states, written when they happen
+    pub events_file: Option<String>,
+    // integrate back to the start after EndTime and report the distance from
+    // the initial state
+    pub reversibility: Option<Reversibility>,
+    // KS regularization of the pairs that come closer than this
+    pub regularization_distance: Option<f64>,
+}
+
+impl Default for Run {
+    fn default() -> Self {
+        Run {
+            integrator: "DOP54".to_string(),
+            initial_dt: 1.0e-3,
+            max_dt: 1.0e-1,
+            abs_tol: Tolerance::Scalar(1.0e-12),
+            rel_tol: Tolerance::Scalar(0.0),
+            step_control: StepControl::PI,
+            safety: 0.9,
+            min_scale_factor: 0.2,
+            max_scale_factor: 10.0,
+            pi_beta: 0.04,
+            scale_up_factor: 1.01,
+            scale_down_factor: 0.9,
+            ias15_epsilon: ias15::EPSILON,
+            hermite_eta: hermite::ETA,
+            end_time: 1.0,
+            output_interval: 1.0e-1,
+            dense_output: false,
+            pn_order: None,
+            diagnostics_file: None,
+            events: Vec::new(),
+            event_time_tol: 1.0e-12,
+            events_file: None,
+            reversibility: None,
+            regularization_distance: None,
+        }
+    }
+}
+
+impl Run {
+    pub fn check(&self) -> Result<&Run, &'static str> {
+        let name = self.integrator.as_str();
+        if !integrator::NAMES.contains(&name)
+            && !symplectic::NAMES.contains(&name)
+            && name != ias15::NAME
+            && name != hermite::NAME
+        {
+            return Err("unknown Integrator");
+        }
+        if self.initial_dt <= 0.0 {
+            return Err("InitialDt <= 0");
+        }
+        if self.max_dt < self.initial_dt {
+            return Err("MaxDt < InitialDt");
+        }
+        if self.abs_tol.min() <= 0.0 {
+            return Err("AbsTol <= 0");
+        }
+        if self.rel_tol.min() < 0.0 {
+            return Err("RelTol < 0");
+        }
+        match self.step_control {
+            StepControl::PI => {
+                if self.safety <= 0.0 || self.safety > 1.0 {
+                    return Err("Safety must be in (0, 1]");
+                }
+                if self.min_scale_factor <= 0.0 || self.min_scale_factor > 1.0 {
+                    return Err("MinScaleFactor must be in (0, 1]");
+                }
+                if self.max_scale_factor < 1.0 {
+                    return Err("MaxScaleFactor < 1");
+                }
+                if self.pi_beta < 0.0 {
+                    return Err("PiBeta < 0");
+                }
+            }
+            StepControl::ScaleFactor => {
+                if let Tolerance::PerComponent(_) = self.abs_tol {
+                    return Err("StepControl ScaleFactor needs a scalar AbsTol");
+                }
+                if self.scale_up_factor < 1.0 {
+                    return Err("ScaleUpFactor < 1");
+                }
+                if self.scale_down_factor <= 0.0 || self.scale_down_factor > 1.0 {
+                    return Err("ScaleDownFactor must be in (0, 1]");
+                }
+            }
+        }
+        if self.ias15_epsilon <= 0.0 {
+            return Err("Ias15Epsilon <= 0");
+        }
+        if self.hermite_eta <= 0.0 {
+            return Err("HermiteEta <= 0");
+        }
+        if self.end_time == 0.0 {
+            return Err("EndTime = 0");
+        }
+        if self.output_interval <= 0.0 {
+            return Err("OutputInterval <= 0");
+        }
+        if self.dense_output && !integrator::DENSE_OUTPUT_NAMES.contains(&self.integrator.as_str())
+        {
+            return Err("DenseOutput is only available for DOP54 and DOP853");
+        }
+        if !self.events.is_empty() && !self.dense_output {
+            return Err("Events need DenseOutput");
+        }
+        if !self.events.is_empty() && (self.end_time < 0.0 || self.reversibility.is_some()) {
+            return Err("Events need forward time");
+        }
+        if self.event_time_tol <= 0.0 {
+            return Err("EventTimeTol <= 0");
+        }
+        Ok(self)
+    }
+
+    // AbsTol and RelTol against a state vector of len components
+    pub fn check_tolerance(&self, len: usize) -> Result<&Run, &'static str> {
+        if !self.abs_tol.fits(len) {
+            return Err("AbsTol needs one value per state component");
+        }
+        if !self.rel_tol.fits(len) {
+            return Err("RelTol needs one value per state component");
+        }
+        Ok(self)
+    }
+
+    // None for StepControl: ScaleFactor
+    pub fn controller(&self) -> Option<PIController> {
+        if self.step_control == StepControl::ScaleFactor {
+            return None;
+        }
+        let mut pi = PIController::new(self.abs_tol.clone(), self.rel_tol.clone());
+        pi.safety = self.safety;
+        pi.min_factor = self.min_scale_factor;
+        pi.max_factor = self.max_scale_factor;
+        pi.beta = self.pi_beta;
+        Some(pi)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RunSection {
+    run: Option<Run>,
+}
+
+// Extract the Run section of the setting file, None if there is none.
+pub fn read_run(yaml: &str) -> Result<Option<Run>, serde_yaml::Error> {
+    let section: RunSection = serde_yaml::from_str(yaml)?;
+    Ok(section.run)
+}
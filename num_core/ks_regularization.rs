@@ -0,0 +1,595 @@
+//
+// Kustaanheimo–Stiefel regularization of the closest pair of bodies.
+//
+// The relative coordinate x = x_i - x_j (r = |x|) of the pair is written as
+// x = L(u) u with u in R^4, and the time as dt = r ds. With u' = du/ds and the
+// energy h = v^2 / 2 - mu / r of the relative motion (mu = G (m_i + m_j)),
+//   u'' = h / 2 u + r / 2 L(u)^T P,  h' = 2 u' . L(u)^T P,  t' = r,
+// where P is the perturbing acceleration of the relative motion: the other
+// bodies and every post-Newtonian (and spin) term of the pair. Without P this
+// is a harmonic oscillator, regular at r = 0. The center of mass of the pair
+// and the other bodies follow their physical equations times r.
+//
+// A pair is regularized once it comes closer than the regularization distance
+// and released beyond twice that; any integrator of integrator::NAMES takes
+// the steps, in s while the pair is regularized and in t otherwise. The steps
+// are shortened to land on the output times by Newton's method on t(s).
+//
+// ref. Stiefel and Scheifele, Linear and regular celestial mechanics (1971).
+// Aarseth, Gravitational N-body simulations (2003), Ch. 4 and 5.
+//
+use crate::num_core::integrator::{self, Integrator};
+use crate::num_core::io_config::Run;
+use crate::num_core::n_body::{newton_acceleration, Mode, NBody, DIM};
+use crate::num_core::smp_vector::SmpVector;
+use crate::num_core::step_control::{PIController, StepStatistics, Tolerance};
+
+// u, u', h and t after the state of the bodies
+const KS_LEN: usize = 10;
+
+// release a pair beyond this many times the regularization distance
+const RELEASE_FACTOR: f64 = 2.0;
+
+// iterations to land on an output time
+const MAX_LANDING_ITERATIONS: usize = 20;
+
+pub struct KSRegularization {
+    n_body: NBody,
+    inner: Box<dyn Integrator>,
+    distance: f64,
+    // the regularized pair (i < j), its state in s and the physical state
+    // returned last from it
+    pair: Option<(usize, usize)>,
+    z: SmpVector,
+    x: SmpVector,
+    switches: i64,
+}
+
+// the KS matrix L(u)
+fn ks_matrix(u: &[f64]) -> [[f64; 4]; 4] {
+    [
+        [u[0], -u[1], -u[2], u[3]],
+        [u[1], u[0], -u[3], -u[2]],
+        [u[2], u[3], u[0], u[1]],
+        [u[3], -u[2], u[1], -u[0]],
+    ]
+}
+
+// L(u) w, or L(u)^T w
+fn ks_product(u: &[f64], w: &[f64], transpose: bool) -> [f64; 4] {
+    let l = ks_matrix(u);
+    let mut ret = [0.0; 4];
+    for (a, ra) in ret.iter_mut().enumerate() {
+        for (b, wb) in w.iter().enumerate() {
+            *ra += if transpose { l[b][a] } else { l[a][b] } * wb;
+        }
+    }
+    ret
+}
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b.iter()).map(|(p, q)| p * q).sum()
+}
+
+// u with L(u) u = x, taken with the larger of u_1 and u_2 for accuracy
+fn ks_coordinates(x: &[f64]) -> [f64; 4] {
+    let r = dot(x, x).sqrt();
+    if x[0] >= 0.0 {
+        let u1 = (0.5 * (r + x[0])).sqrt();
+        [u1, 0.5 * x[1] / u1, 0.5 * x[2] / u1, 0.0]
+    } else {
+        let u2 = (0.5 * (r - x[0])).sqrt();
+        [0.5 * x[1] / u2, u2, 0.0, 0.5 * x[2] / u2]
+    }
+}
+
+// positions and velocities of the bodies of the state vector
+fn slots(n_body: &NBody, k: usize) -> (usize, usize) {
+    (DIM * k, DIM * (n_body.number_of_bodies + k))
+}
+
+// The regularized state of the pair in x: the center of mass of the pair in
+// the slots of body i, zero in those of body j, and u, u', h, t at the end.
+fn regularize(n_body: &NBody, pair: (usize, usize), x: &SmpVector) -> SmpVector {
+    let mass = n_body.gravitational_mass();
+    let (i, j) = pair;
+    let (mi, mj) = (mass[i] / (mass[i] + mass[j]), mass[j] / (mass[i] + mass[j]));
+    let ((pi, vi), (pj, vj)) = (slots(n_body, i), slots(n_body, j));
+    let mut z = x.clone();
+    let mut rel = [0.0; 2 * DIM];
+    for d in 0..DIM {
+        rel[d] = x.vec[pi + d] - x.vec[pj + d];
+        rel[DIM + d] = x.vec[vi + d] - x.vec[vj + d];
+        z.vec[pi + d] = mi * x.vec[pi + d] + mj * x.vec[pj + d];
+        z.vec[vi + d] = mi * x.vec[vi + d] + mj * x.vec[vj + d];
+        z.vec[pj + d] = 0.0;
+        z.vec[vj + d] = 0.0;
+    }
+    let (pos, vel) = rel.split_at(DIM);
+    let u = ks_coordinates(pos);
+    let u_dot = ks_product(&u, vel, true).map(|w| 0.5 * w);
+    let h = 0.5 * dot(vel, vel) - (mass[i] + mass[j]) / dot(pos, pos).sqrt();
+    z.vec.extend_from_slice(&u);
+    z.vec.extend_from_slice(&u_dot);
+    z.vec.push(h);
+    z.vec.push(x.time);
+    z.time = 0.0;
+    z
+}
+
+// The physical state of the regularized state z.
+fn physical(n_body: &NBody, pair: (usize, usize), z: &SmpVector) -> SmpVector {
+    let mass = n_body.gravitational_mass();
+    let (i, j) = pair;
+    let (mi, mj) = (mass[i] / (mass[i] + mass[j]), mass[j] / (mass[i] + mass[j]));
+    let ((pi, vi), (pj, vj)) = (slots(n_body, i), slots(n_body, j));
+    let len = z.vec.len() - KS_LEN;
+    let (u, u_dot) = (&z.vec[len..len + 4], &z.vec[len + 4..len + 8]);
+    let r = dot(u, u);
+    let pos = ks_product(u, u, false);
+    let vel = ks_product(u, u_dot, false).map(|w| 2.0 * w / r);
+    let mut x = SmpVector {
+        time: z.vec[len + 9],
+        vec: z.vec[..len].to_vec(),
+    };
+    for d in 0..DIM {
+        x.vec[pj + d] = z.vec[pi + d] - mi * pos[d];
+        x.vec[vj + d] = z.vec[vi + d] - mi * vel[d];
+        x.vec[pi + d] = z.vec[pi + d] + mj * pos[d];
+        x.vec[vi + d] = z.vec[vi + d] + mj * vel[d];
+    }
+    x
+}
+
+// Accelerations of the bodies without the Newtonian attraction within the
+// pair, and dS/dt of the spins.
+fn perturbed_forces(n_body: &NBody, pair: (usize, usize), x: &SmpVector) -> (Vec<f64>, Vec<f64>) {
+    let mass = n_body.gravitational_mass();
+    let n = DIM * n_body.number_of_bodies;
+    let pos = &x.vec[..n];
+    let mut acc = vec![0.0; n];
+    for k in 0..mass.len() {
+        for l in (k + 1)..mass.len() {
+            if (k, l) == pair {
+                continue;
+            }
+            let mut r = [0.0; DIM];
+            for d in 0..DIM {
+                r[d] = pos[DIM * l + d] - pos[DIM * k + d];
+            }
+            let r2 = dot(&r, &r);
+            let inv_r3 = 1.0 / (r2 * r2.sqrt());
+            for d in 0..DIM {
+                acc[DIM * k + d] += mass[l] * r[d] * inv_r3;
+                acc[DIM * l + d] -= mass[k] * r[d] * inv_r3;
+            }
+        }
+    }
+    let mut spin_dot = vec![0.0; x.vec.len() - 2 * n];
+    if n_body.post_newtonian.is_enabled() {
+        // the terms beyond Newton, from the whole equations of motion
+        let f = n_body.eom()(x);
+        let newton = newton_acceleration(&mass, pos);
+        for k in 0..n {
+            acc[k] += f.vec[n + k] - newton[k];
+        }
+        spin_dot.copy_from_slice(&f.vec[2 * n..]);
+    }
+    (acc, spin_dot)
+}
+
+// d/ds of the regularized state z
+fn regularized_rhs(n_body: &NBody, pair: (usize, usize), z: &SmpVector) -> SmpVector {
+    let mass = n_body.gravitational_mass();
+    let (i, j) = pair;
+    let (mi, mj) = (mass[i] / (mass[i] + mass[j]), mass[j] / (mass[i] + mass[j]));
+    let ((pi, vi), (pj, vj)) = (slots(n_body, i), slots(n_body, j));
+    let n = DIM * n_body.number_of_bodies;
+    let len = z.vec.len() - KS_LEN;
+    let (u, u_dot, h) = (
+        &z.vec[len..len + 4],
+        &z.vec[len + 4..len + 8],
+        z.vec[len + 8],
+    );
+    let r = dot(u, u);
+    let x = physical(n_body, pair, z);
+    let (acc, spin_dot) = perturbed_forces(n_body, pair, &x);
+    let mut ret = SmpVector::set_zero_vector(z.vec.len());
+    ret.time = z.time;
+    for (k, a) in acc.iter().enumerate().take(n) {
+        ret.vec[k] = r * x.vec[n + k];
+        ret.vec[n + k] = r * a;
+    }
+    for (k, s) in spin_dot.iter().enumerate() {
+        ret.vec[2 * n + k] = r * s;
+    }
+    let mut p = [0.0; DIM];
+    for d in 0..DIM {
+        p[d] = acc[vi - n + d] - acc[vj - n + d];
+        ret.vec[pi + d] = r * z.vec[vi + d];
+        ret.vec[vi + d] = r * (mi * acc[vi - n + d] + mj * acc[vj - n + d]);
+        ret.vec[pj + d] = 0.0;
+        ret.vec[vj + d] = 0.0;
+    }
+    let q = ks_product(u, &p, true);
+    for a in 0..4 {
+        ret.vec[len + a] = u_dot[a];
+        ret.vec[len + 4 + a] = 0.5 * h * u[a] + 0.5 * r * q[a];
+    }
+    ret.vec[len + 8] = 2.0 * dot(u_dot, &q);
+    ret.vec[len + 9] = r;
+    ret
+}
+
+impl KSRegularization {
+    // inner takes the steps; pairs closer than distance are regularized
+    pub fn new(n_body: &NBody, inner: Box<dyn Integrator>, distance: f64) -> KSRegularization {
+        KSRegularization {
+            n_body: n_body.clone(),
+            inner,
+            distance,
+            pair: None,
+            z: SmpVector::set_zero_vector(0),
+            x: SmpVector::set_zero_vector(0),
+            switches: 0,
+        }
+    }
+
+    // number of regularizations so far
+    #[allow(dead_code)]
+    pub fn switches(&self) -> i64 {
+        self.switches
+    }
+
+    // the closest pair and its separation
+    fn closest_pair(&self, x: &SmpVector) -> ((usize, usize), f64) {
+        let mut ret = ((0, 1), f64::INFINITY);
+        for k in 0..self.n_body.number_of_bodies {
+            for l in (k + 1)..self.n_body.number_of_bodies {
+                let r2: f64 = (0..DIM)
+                    .map(|d| (x.vec[DIM * k + d] - x.vec[DIM * l + d]).powi(2))
+                    .sum();
+                if r2.sqrt() < ret.1 {
+                    ret = ((k, l), r2.sqrt());
+                }
+            }
+        }
+        ret
+    }
+
+    fn separation(&self) -> f64 {
+        let len = self.z.vec.len() - KS_LEN;
+        dot(&self.z.vec[len..len + 4], &self.z.vec[len..len + 4])
+    }
+
+    // the step in t for the one in s
+    fn release(&mut self) {
+        let r = self.separation();
+        self.inner.set_delta_t(r * self.inner.get_delta_t());
+        self.pair = None;
+    }
+
+    // Go on from z if x is the state returned last and the pair is still
+    // close; regularize the closest pair of x if it comes within distance.
+    fn resume(&mut self, x: &SmpVector) {
+        if self.pair.is_some()
+            && (x.time != self.x.time
+                || x.vec != self.x.vec
+                || self.separation() > RELEASE_FACTOR * self.distance)
+        {
+            self.release();
+        }
+        if self.pair.is_some() {
+            return;
+        }
+        let (pair, r) = self.closest_pair(x);
+        if r < self.distance {
+            self.pair = Some(pair);
+            self.z = regularize(&self.n_body, pair, x);
+            self.x = x.clone();
+            self.inner.set_delta_t(self.inner.get_delta_t() / r);
+            self.switches += 1;
+        }
+    }
+
+    // one adaptive step in s of the regularized pair
+    fn regularized_step(&mut self, pair: (usize, usize)) -> SmpVector {
+        let n_body = &self.n_body;
+        let rhs = |z: &SmpVector| regularized_rhs(n_body, pair, z);
+        let z = self.inner.adaptive_step(&rhs, &self.z);
+        if z.time != self.z.time {
+            self.z = z;
+            self.x = physical(n_body, pair, &self.z);
+        }
+        self.x.clone()
+    }
+
+    // From z0 at t0 the step in s to end_time (t0 < end_time < t0 + the step
+    // just taken), by Newton's method with dt/ds = r. If Newton's method does
+    // not converge, the step is halved until it stops short of end_time and
+    // the state is returned at its own time; the caller lands from there.
+    fn land(&mut self, pair: (usize, usize), end_time: f64, z0: &SmpVector) -> SmpVector {
+        let n_body = &self.n_body;
+        let rhs = |z: &SmpVector| regularized_rhs(n_body, pair, z);
+        let len = z0.vec.len() - KS_LEN;
+        let r = |z: &SmpVector| dot(&z.vec[len..len + 4], &z.vec[len..len + 4]);
+        let ds_next = self.inner.get_delta_t();
+        let t0 = z0.vec[len + 9];
+        let direction = (end_time - t0).signum();
+        let tol = 4.0 * f64::EPSILON * (end_time.abs() + (end_time - t0).abs());
+        let mut ds = (end_time - t0) / r(z0);
+        let mut z = z0.clone();
+        let mut landed = false;
+        for _ in 0..MAX_LANDING_ITERATIONS {
+            self.inner.set_delta_t(ds);
+            z = self.inner.step(&rhs, z0);
+            let dt = end_time - z.vec[len + 9];
+            if dt.abs() <= tol {
+                landed = true;
+                break;
+            }
+            ds += dt / r(&z);
+        }
+        if !landed {
+            ds = (end_time - t0) / r(z0);
+            loop {
+                self.inner.set_delta_t(ds);
+                z = self.inner.step(&rhs, z0);
+                if (end_time - z.vec[len + 9]) * direction >= 0.0 {
+                    break;
+                }
+                ds *= 0.5;
+            }
+        }
+        self.inner.set_delta_t(ds_next);
+        self.z = z;
+        self.x = physical(n_body, pair, &self.z);
+        if landed {
+            self.x.time = end_time;
+        }
+        self.x.clone()
+    }
+
+    #[allow(dead_code)]
+    pub fn solve_to_end_time(
+        &mut self,
+        end_time: f64,
+        func: impl Fn(&SmpVector) -> SmpVector,
+        x: &SmpVector,
+    ) -> SmpVector {
+        self.integrate_to(end_time, &func, x)
+    }
+}
+
+pub fn check(run: &Run, n_body: &NBody) -> Result<(), &'static str> {
+    let distance = match run.regularization_distance {
+        Some(distance) => distance,
+        None => return Ok(()),
+    };
+    if distance <= 0.0 {
+        return Err("RegularizationDistance <= 0");
+    }
+    if !integrator::NAMES.contains(&run.integrator.as_str()) {
+        return Err("KS regularization needs an Integrator of the Runge-Kutta or GBS family");
+    }
+    if n_body.mode != Mode::NBody {
+        return Err("KS regularization needs Mode: NBody");
+    }
+    if run.dense_output {
+        return Err("KS regularization has no DenseOutput");
+    }
+    if let (Tolerance::Scalar(_), Tolerance::Scalar(_)) = (&run.abs_tol, &run.rel_tol) {
+        return Ok(());
+    }
+    Err("KS regularization needs a scalar AbsTol and RelTol")
+}
+
+// The regularized pair takes its forces from the NBody given to new(); func
+// moves the bodies otherwise.
+impl Integrator for KSRegularization {
+    // one step of dt without control; a close pair takes it in s scaled by r
+    fn step(&self, func: &dyn Fn(&SmpVector) -> SmpVector, x: &SmpVector) -> SmpVector {
+        let (pair, r) = self.closest_pair(x);
+        if r >= self.distance {
+            return self.inner.step(func, x);
+        }
+        let n_body = &self.n_body;
+        let rhs = |z: &SmpVector| (1.0 / r) * &regularized_rhs(n_body, pair, z);
+        let z = self.inner.step(&rhs, &regularize(n_body, pair, x));
+        physical(n_body, pair, &z)
+    }
+
+    fn adaptive_step(
+        &mut self,
+        func: &dyn Fn(&SmpVector) -> SmpVector,
+        x: &SmpVector,
+    ) -> SmpVector {
+        self.resume(x);
+        match self.pair {
+            Some(pair) => self.regularized_step(pair),
+            None => self.inner.adaptive_step(func, x),
+        }
+    }
+
+    // Adaptive steps up to end_time, landing on it exactly in t or in s.
+    fn integrate_to(
+        &mut self,
+        end_time: f64,
+        func: &dyn Fn(&SmpVector) -> SmpVector,
+        x: &SmpVector,
+    ) -> SmpVector {
+        if x.time == end_time {
+            return x.clone();
+        }
+        let mut x = x.clone();
+        let direction = (end_time - x.time).signum();
+        self.inner.set_direction(direction);
+        while (end_time - x.time) * direction > 0.0 {
+            self.resume(&x);
+            if let Some(pair) = self.pair {
+                let z0 = self.z.clone();
+                let y = self.regularized_step(pair);
+                x = if (end_time - y.time) * direction < 0.0 {
+                    self.land(pair, end_time, &z0)
+                } else {
+                    y
+                };
+                continue;
+            }
+            let dt = self.inner.get_delta_t();
+            if (end_time - x.time - dt) * direction > 0.0 {
+                x = self.inner.adaptive_step(func, &x);
+                continue;
+            }
+            self.inner.set_delta_t(end_time - x.time);
+            let y = self.inner.adaptive_step(func, &x);
+            // rejected: retry with the shorter step
+            if y.time == x.time {
+                continue;
+            }
+            x = y;
+            x.time = end_time;
+            self.inner.set_delta_t(dt);
+        }
+        x
+    }
+
+    // dt in t, r ds while a pair is regularized
+    fn set_delta_t(&mut self, dt: f64) {
+        match self.pair {
+            Some(_) => {
+                let r = self.separation();
+                self.inner.set_delta_t(dt / r)
+            }
+            None => self.inner.set_delta_t(dt),
+        }
+    }
+
+    fn get_delta_t(&self) -> f64 {
+        match self.pair {
+            Some(_) => self.separation() * self.inner.get_delta_t(),
+            None => self.inner.get_delta_t(),
+        }
+    }
+
+    fn get_num_of_steps(&self) -> i64 {
+        self.inner.get_num_of_steps()
+    }
+
+    fn get_num_of_dt_switch(&self) -> i64 {
+        self.inner.get_num_of_dt_switch()
+    }
+
+    fn set_controller(&mut self, controller: PIController) {
+        self.inner.set_controller(controller)
+    }
+
+    fn statistics(&self) -> &StepStatistics {
+        self.inner.statistics()
+    }
+}
+
+#[cfg(test)]
+pub mod ks_regularization_tests {
+    extern crate approx;
+    use crate::num_core::integrator::{self, Integrator};
+    use crate::num_core::ks_regularization::{self, KSRegularization};
+    use crate::num_core::n_body::NBody;
+    use crate::num_core::smp_vector::SmpVector;
+    use crate::num_core::step_control::{PIController, Tolerance};
+    use std::f64::consts::PI;
+
+    // binary with e = 0.999 (a = 1, period 2 pi) from the apocenter
+    const BINARY: &str = "
+SettingName: \"eccentric\"
+NumberOfBodies: 2
+Mass: [0.5, 0.5]
+Position: [[0.9995, 0.0, 0.0], [-0.9995, 0.0, 0.0]]
+Velocity: [[0.0, 0.011183136021064615, 0.0], [0.0, -0.011183136021064615, 0.0]]
+";
+
+    // the binary with e = 0.99 and a third body on a wide orbit
+    const TRIPLE: &str = "
+SettingName: \"triple\"
+NumberOfBodies: 3
+Mass: [0.5, 0.5, 0.2]
+Position: [[0.995, 0.0, 0.0], [-0.995, 0.0, 0.0], [0.0, 8.0, 1.0]]
+Velocity: [[0.0, 0.03544406025041681, 0.0], [0.0, -0.03544406025041681, 0.0], [-0.35, 0.0, 0.0]]
+";
+
+    fn solver(name: &str, tol: f64) -> Box<dyn Integrator> {
+        let mut sol = integrator::from_name(name, 1.0e-3, 1.0, tol, 1.01, 0.9).unwrap();
+        sol.set_controller(PIController::new(
+            Tolerance::Scalar(tol),
+            Tolerance::Scalar(tol),
+        ));
+        sol
+    }
+
+    #[test]
+    fn transformation() {
+        let n_body: NBody = serde_yaml::from_str(TRIPLE).unwrap();
+        let x = SmpVector {
+            time: 1.5,
+            vec: vec![
+                0.3, -0.2, 0.1, -0.4, 0.5, 0.2, 0.0, 8.0, 1.0, 0.1, 0.2, -0.3, -0.2, 0.4, 0.1,
+                -0.35, 0.0, 0.0,
+            ],
+        };
+        for pair in [(0, 1), (1, 2)].iter() {
+            let z = ks_regularization::regularize(&n_body, *pair, &x);
+            let y = ks_regularization::physical(&n_body, *pair, &z);
+            assert_eq!(y.time, x.time);
+            for (a, b) in x.vec.iter().zip(y.vec.iter()) {
+                approx::assert_abs_diff_eq!(a, b, epsilon = 1.0e-15);
+            }
+            // the bilinear relation u1 u4' - u2 u3' + u3 u2' - u4 u1' = 0
+            let w = &z.vec[18..];
+            let bilinear = w[0] * w[7] - w[1] * w[6] + w[2] * w[5] - w[3] * w[4];
+            approx::assert_abs_diff_eq!(bilinear, 0.0, epsilon = 1.0e-16);
+        }
+        // unperturbed, h is constant and t' = r
+        let n_body: NBody = serde_yaml::from_str(BINARY).unwrap();
+        let z = ks_regularization::regularize(&n_body, (0, 1), &n_body.to_smp_vector());
+        approx::assert_abs_diff_eq!(z.vec[20], -0.5, epsilon = 1.0e-15);
+        let f = ks_regularization::regularized_rhs(&n_body, (0, 1), &z);
+        approx::assert_abs_diff_eq!(f.vec[20], 0.0);
+        approx::assert_abs_diff_eq!(f.vec[21], 1.999, epsilon = 1.0e-15);
+    }
+
+    #[test]
+    fn eccentric_binary() {
+        // three periods, back at the apocenter
+        let n_body: NBody = serde_yaml::from_str(BINARY).unwrap();
+        let x = n_body.to_smp_vector();
+        let mut ks = KSRegularization::new(&n_body, solver("DOP54", 1.0e-12), 0.5);
+        let y = ks.solve_to_end_time(6.0 * PI, n_body.eom(), &x);
+        assert_eq!(y.time, 6.0 * PI);
+        for (a, b) in x.vec.iter().zip(y.vec.iter()) {
+            approx::assert_abs_diff_eq!(a, b, epsilon = 1.0e-9);
+        }
+        assert_eq!(ks.switches(), 3);
+        let mut plain = solver("DOP54", 1.0e-12);
+        let z = plain.integrate_to(6.0 * PI, &n_body.eom(), &x);
+        // fewer steps, and more accurate through the pericenters
+        assert!(4 * ks.get_num_of_steps() < plain.get_num_of_steps());
+        assert!((&y - &x).norm() < 0.1 * (&z - &x).norm());
+    }
+
+    #[test]
+    fn perturbed_binary() {
+        // the perturber and 1PN terms (c = 30) as perturbations of the pair,
+        // against the unregularized DOP853
+        let mut n_body: NBody = serde_yaml::from_str(TRIPLE).unwrap();
+        n_body.speed_of_light = Some(30.0);
+        n_body.post_newtonian.pn1 = true;
+        let x = n_body.to_smp_vector();
+        let reference = solver("DOP853", 1.0e-15).integrate_to(10.0, &n_body.eom(), &x);
+        let mut ks = KSRegularization::new(&n_body, solver("DOP853", 1.0e-13), 0.3);
+        let y = ks.solve_to_end_time(10.0, n_body.eom(), &x);
+        assert!(ks.switches() >= 1);
+        for (a, b) in y.vec.iter().zip(reference.vec.iter()) {
+            approx::assert_abs_diff_eq!(a, b, epsilon = 1.0e-8);
+        }
+    }
+}
@@ -1,4 +1,24 @@
+pub mod butcher_tableau;
+pub mod dense_output;
+pub mod diagnostics;
 pub mod dop54;
+pub mod dop853;
+pub mod events;
+pub mod explicit_rk;
+pub mod gauss_legendre;
+pub mod gbs;
+pub mod hermite;
+pub mod ias15;
+pub mod integrator;
 pub mod io_config;
-pub mod smp_vector;
+pub mod ks_regularization;
 pub mod n_body;
+pub mod post_newtonian;
+pub mod rkf78;
+pub mod simulation;
+pub mod smp_vector;
+pub mod step_control;
+pub mod symplectic;
+#[cfg(test)]
+pub mod test_problems;
+pub mod units;
@@ -1,5 +1,21 @@
+use crate::num_core::post_newtonian::{self, PostNewtonian};
+use crate::num_core::smp_vector::SmpVector;
+use crate::num_core::units::Units;
 use serde::{Deserialize, Serialize};
 
+// spatial dimension of each body
+pub const DIM: usize = 3;
+
+// How the system is integrated (YAML: Mode).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+pub enum Mode {
+    // positions and velocities of every body
+    #[default]
+    NBody,
+    // reduced problem in the center-of-mass frame: x = x_1 - x_2, v = v_1 - v_2
+    TwoBody,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
 #[serde(rename_all = "PascalCase")]
 pub struct NBody {
@@ -7,6 +23,26 @@ pub struct NBody {
     pub number_of_bodies: usize,
     pub mass: Vec<f64>,
     pub position: Vec<Vec<f64>>,
+    // empty: every body starts at rest
+    #[serde(default)]
+    pub velocity: Vec<Vec<f64>>,
+    #[serde(default)]
+    pub spin: Option<Vec<Vec<f64>>>,
+    #[serde(default)]
+    pub name: Option<Vec<String>>,
+    #[serde(default)]
+    pub radius: Option<Vec<f64>>,
+    #[serde(default)]
+    pub speed_of_light: Option<f64>,
+    #[serde(default)]
+    pub post_newtonian: PostNewtonian,
+    #[serde(default)]
+    pub mode: Mode,
+    // units of the input, and of the output when OutputUnits is not given
+    #[serde(default)]
+    pub units: Units,
+    #[serde(default)]
+    pub output_units: Option<Units>,
 }
 
 impl NBody {
@@ -17,7 +53,615 @@ impl NBody {
         if self.position.len() != self.number_of_bodies {
             return Err("position.len() != number_of_bodies");
         }
+        if self.position.iter().any(|p| p.len() != DIM) {
+            return Err("position[i].len() != 3");
+        }
+        if !self.velocity.is_empty() {
+            if self.velocity.len() != self.number_of_bodies {
+                return Err("velocity.len() != number_of_bodies");
+            }
+            if self.velocity.iter().any(|v| v.len() != DIM) {
+                return Err("velocity[i].len() != 3");
+            }
+        }
+        if let Some(spin) = &self.spin {
+            if spin.len() != self.number_of_bodies {
+                return Err("spin.len() != number_of_bodies");
+            }
+            if spin.iter().any(|s| s.len() != DIM) {
+                return Err("spin[i].len() != 3");
+            }
+        }
+        if let Some(name) = &self.name {
+            if name.len() != self.number_of_bodies {
+                return Err("name.len() != number_of_bodies");
+            }
+        }
+        if let Some(radius) = &self.radius {
+            if radius.len() != self.number_of_bodies {
+                return Err("radius.len() != number_of_bodies");
+            }
+            if radius.iter().any(|&r| r < 0.0) {
+                return Err("radius < 0");
+            }
+        }
+        if self.mass.iter().any(|&m| m <= 0.0) {
+            return Err("Mass must be > 0");
+        }
+        if self.post_newtonian.is_enabled() {
+            match self.speed_of_light.or_else(|| self.units.speed_of_light()) {
+                None => return Err("SpeedOfLight is required for post-Newtonian terms"),
+                Some(c) if c <= 0.0 => return Err("SpeedOfLight <= 0"),
+                _ => {}
+            }
+        }
+        if self.post_newtonian.is_spin_enabled() && self.spin.is_none() {
+            return Err("SpinOrbit and SpinSpin require Spin");
+        }
+        if self.mode == Mode::TwoBody && self.number_of_bodies != 2 {
+            return Err("Mode: TwoBody requires number_of_bodies == 2");
+        }
+        if self.mode == Mode::NBody && self.post_newtonian.is_two_body_only() {
+            return Err("3PN and 3.5PN terms require Mode: TwoBody");
+        }
+        if let Some(output_units) = self.output_units {
+            self.units.conversion(output_units)?;
+        }
 
         Ok(self)
     }
-}
\ No newline at end of file
+
+    // number of position vectors in the state
+    pub fn num_of_points(&self) -> usize {
+        match self.mode {
+            Mode::NBody => self.number_of_bodies,
+            Mode::TwoBody => 1,
+        }
+    }
+
+    // length of the state vector
+    pub fn state_len(&self) -> usize {
+        let spin = match self.spin {
+            Some(_) => DIM * self.number_of_bodies,
+            None => 0,
+        };
+        2 * DIM * self.num_of_points() + spin
+    }
+
+    // Pack the system into the state vector integrated by DOP54.
+    // NBody:   (x_1, y_1, z_1, ..., z_N, vx_1, vy_1, vz_1, ..., vz_N)
+    // TwoBody: (x, y, z, vx, vy, vz) of the relative coordinate
+    // followed by (Sx_1, Sy_1, Sz_1, ..., Sz_N) when Spin is given.
+    #[allow(dead_code)]
+    pub fn to_smp_vector(&self) -> SmpVector {
+        let n = self.num_of_points();
+        let mut ret = SmpVector::set_zero_vector(self.state_len());
+        let velocity = |i: usize, d: usize| {
+            if self.velocity.is_empty() {
+                0.0
+            } else {
+                self.velocity[i][d]
+            }
+        };
+        match self.mode {
+            Mode::NBody => {
+                for i in 0..n {
+                    for d in 0..DIM {
+                        ret.vec[DIM * i + d] = self.position[i][d];
+                        ret.vec[DIM * (n + i) + d] = velocity(i, d);
+                    }
+                }
+            }
+            Mode::TwoBody => {
+                for d in 0..DIM {
+                    ret.vec[d] = self.position[0][d] - self.position[1][d];
+                    ret.vec[DIM + d] = velocity(0, d) - velocity(1, d);
+                }
+            }
+        }
+        if let Some(spin) = &self.spin {
+            for (i, s) in spin.iter().enumerate() {
+                ret.vec[DIM * (2 * n + i)..DIM * (2 * n + i + 1)].copy_from_slice(s);
+            }
+        }
+        ret
+    }
+
+    // The state under time reversal t -> -t: the velocities and spins change sign.
+    pub fn time_reversed(&self, x: &SmpVector) -> SmpVector {
+        let mut ret = x.clone();
+        ret.time = -x.time;
+        for v in ret.vec[DIM * self.num_of_points()..].iter_mut() {
+            *v = -*v;
+        }
+        ret
+    }
+
+    // Column names of the state vector, in the order of to_smp_vector.
+    #[allow(dead_code)]
+    pub fn column_labels(&self) -> Vec<String> {
+        let mut ret = vec![];
+        let axes = ["x", "y", "z"];
+        match self.mode {
+            Mode::NBody => {
+                for prefix in ["", "v"].iter() {
+                    for i in 0..self.number_of_bodies {
+                        for axis in axes.iter() {
+                            ret.push(format!("{}{}_{}", prefix, axis, self.label(i)));
+                        }
+                    }
+                }
+            }
+            Mode::TwoBody => {
+                for prefix in ["", "v"].iter() {
+                    for axis in axes.iter() {
+                        ret.push(format!("{}{}", prefix, axis));
+                    }
+                }
+            }
+        }
+        if self.spin.is_some() {
+            for i in 0..self.number_of_bodies {
+                for axis in axes.iter() {
+                    ret.push(format!("s{}_{}", axis, self.label(i)));
+                }
+            }
+        }
+        ret
+    }
+
+    // label of the i-th body, "body_i" unless given in the config
+    #[allow(dead_code)]
+    pub fn label(&self, i: usize) -> String {
+        match &self.name {
+            Some(name) => name[i].clone(),
+            None => format!("body_{}", i),
+        }
+    }
+
+    // Speed of light in the input units: SpeedOfLight if given, otherwise the
+    // physical value of the unit system (infinite, i.e. Newtonian, in N-body units).
+    pub fn light_speed(&self) -> f64 {
+        self.speed_of_light
+            .or_else(|| self.units.speed_of_light())
+            .unwrap_or(f64::INFINITY)
+    }
+
+    // G m of every body, the masses seen by the G = 1 equations of motion
+    pub fn gravitational_mass(&self) -> Vec<f64> {
+        let g = self.units.gravitational_constant();
+        self.mass.iter().map(|m| g * m).collect()
+    }
+
+    // Convert a state vector (and its time) from Units to OutputUnits.
+    #[allow(dead_code)]
+    pub fn to_output_units(&self, x: &SmpVector) -> SmpVector {
+        let conv = match self.output_units {
+            Some(output_units) => self.units.conversion(output_units).unwrap(),
+            None => return x.clone(),
+        };
+        let n = DIM * self.num_of_points();
+        let mut ret = x.clone();
+        ret.time *= conv.time;
+        for (k, v) in ret.vec.iter_mut().enumerate() {
+            *v *= if k < n {
+                conv.length
+            } else if k < 2 * n {
+                conv.velocity()
+            } else {
+                conv.angular_momentum()
+            };
+        }
+        ret
+    }
+
+    // Right-hand side of the Newtonian equations of motion, d/dt (x, v) = (v, a).
+    // The spins, if any, stay constant.
+    #[allow(dead_code)]
+    pub fn newton_eom(&self) -> impl Fn(&SmpVector) -> SmpVector + Copy + '_ {
+        move |x: &SmpVector| {
+            let n = DIM * self.num_of_points();
+            let mass = self.gravitational_mass();
+            let pos = &x.vec[..n];
+            let acc = match self.mode {
+                Mode::NBody => newton_acceleration(&mass, pos),
+                Mode::TwoBody => {
+                    let m = mass[0] + mass[1];
+                    let r = pos.iter().map(|p| p * p).sum::<f64>().sqrt();
+                    pos.iter().map(|p| -m * p / (r * r * r)).collect()
+                }
+            };
+            let mut ret = SmpVector::set_zero_vector(x.vec.len());
+            ret.vec[..n].copy_from_slice(&x.vec[n..2 * n]);
+            ret.vec[n..2 * n].copy_from_slice(&acc);
+            ret.time = x.time;
+            ret
+        }
+    }
+
+    // Accelerations of all bodies: Newton + the enabled post-Newtonian terms.
+    // In Mode: TwoBody, pos and vel are the relative coordinate and velocity.
+    #[allow(dead_code)]
+    pub fn acceleration(&self, pos: &[f64], vel: &[f64]) -> Vec<f64> {
+        let c = self.light_speed();
+        let mass = self.gravitational_mass();
+        if self.mode == Mode::TwoBody {
+            let m = mass[0] + mass[1];
+            let nu = mass[0] * mass[1] / (m * m);
+            let acc =
+                post_newtonian::relative_acceleration(m, nu, pos, vel, c, &self.post_newtonian);
+            return acc.to_vec();
+        }
+        let mut acc = newton_acceleration(&mass, pos);
+        if self.post_newtonian.pn1 {
+            let acc_1pn = post_newtonian::eih_acceleration(&mass, pos, vel, &acc, c);
+            for k in 0..acc.len() {
+                acc[k] += acc_1pn[k];
+            }
+        }
+        if self.post_newtonian.pn2 {
+            let acc_2pn = post_newtonian::pairwise_2pn_acceleration(&mass, pos, vel, c);
+            for k in 0..acc.len() {
+                acc[k] += acc_2pn[k];
+            }
+        }
+        if self.post_newtonian.pn25 {
+            let acc_25pn = post_newtonian::pairwise_25pn_acceleration(&mass, pos, vel, c);
+            for k in 0..acc.len() {
+                acc[k] += acc_25pn[k];
+            }
+        }
+        acc
+    }
+
+    // Spin-orbit and spin-spin accelerations and the spin precession dS/dt.
+    // In Mode: TwoBody, pos and vel are the relative coordinate and velocity.
+    // The spins are evaluated as G S, like the masses.
+    #[allow(dead_code)]
+    pub fn spin_terms(&self, pos: &[f64], vel: &[f64], spin: &[f64]) -> (Vec<f64>, Vec<f64>) {
+        let c = self.light_speed();
+        let g = self.units.gravitational_constant();
+        let mass = self.gravitational_mass();
+        let spin: Vec<f64> = spin.iter().map(|s| g * s).collect();
+        let pn = &self.post_newtonian;
+        let (acc, mut spin_dot) = if self.mode == Mode::TwoBody {
+            let (m1, m2) = (mass[0], mass[1]);
+            let (s1, s2) = (&spin[..DIM], &spin[DIM..]);
+            let acc = post_newtonian::relative_spin_acceleration(m1, m2, pos, vel, (s1, s2), c, pn);
+            let mut x_21 = [0.0; DIM];
+            let mut v_21 = [0.0; DIM];
+            for d in 0..DIM {
+                x_21[d] = -pos[d];
+                v_21[d] = -vel[d];
+            }
+            let ds1 = post_newtonian::spin_precession(m1, m2, pos, vel, (s1, s2), c, pn);
+            let ds2 = post_newtonian::spin_precession(m2, m1, &x_21, &v_21, (s2, s1), c, pn);
+            (acc.to_vec(), [ds1, ds2].concat())
+        } else {
+            post_newtonian::pairwise_spin_terms(&mass, pos, vel, &spin, c, pn)
+        };
+        for s in spin_dot.iter_mut() {
+            *s /= g;
+        }
+        (acc, spin_dot)
+    }
+
+    // Right-hand side of the equations of motion selected in the config.
+    #[allow(dead_code)]
+    pub fn eom(&self) -> impl Fn(&SmpVector) -> SmpVector + Copy + '_ {
+        move |x: &SmpVector| {
+            let n = DIM * self.num_of_points();
+            let (pos, vel) = (&x.vec[..n], &x.vec[n..2 * n]);
+            let mut acc = self.acceleration(pos, vel);
+            let mut ret = SmpVector::set_zero_vector(x.vec.len());
+            if self.spin.is_some() && self.post_newtonian.is_spin_enabled() {
+                let (acc_spin, spin_dot) = self.spin_terms(pos, vel, &x.vec[2 * n..]);
+                for k in 0..n {
+                    acc[k] += acc_spin[k];
+                }
+                ret.vec[2 * n..].copy_from_slice(&spin_dot);
+            }
+            ret.vec[..n].copy_from_slice(vel);
+            ret.vec[n..2 * n].copy_from_slice(&acc);
+            ret.time = x.time;
+            ret
+        }
+    }
+}
+
+// Pairwise Newtonian accelerations (G = 1).
+// pos: (x_1, y_1, z_1, ..., z_N)
+pub fn newton_acceleration(mass: &[f64], pos: &[f64]) -> Vec<f64> {
+    let n = mass.len();
+    let mut acc = vec![0.0; DIM * n];
+    for i in 0..n {
+        for j in (i + 1)..n {
+            let mut r = [0.0; DIM];
+            let mut r2 = 0.0;
+            for d in 0..DIM {
+                r[d] = pos[DIM * j + d] - pos[DIM * i + d];
+                r2 += r[d] * r[d];
+            }
+            let inv_r3 = 1.0 / (r2 * r2.sqrt());
+            for d in 0..DIM {
+                acc[DIM * i + d] += mass[j] * r[d] * inv_r3;
+                acc[DIM * j + d] -= mass[i] * r[d] * inv_r3;
+            }
+        }
+    }
+    acc
+}
+
+#[cfg(test)]
+pub mod n_body_tests {
+    extern crate approx;
+    use crate::num_core::dop54::DOP54;
+    use crate::num_core::n_body::{newton_acceleration, Mode, NBody, DIM};
+    use crate::num_core::units::{Units, ASTRONOMICAL_UNIT_SI};
+
+    // equal-mass binary with separation 1 (G = 1)
+    fn circular_binary() -> NBody {
+        NBody {
+            setting_name: "binary".to_string(),
+            number_of_bodies: 2,
+            mass: vec![0.5, 0.5],
+            position: vec![vec![-0.5, 0.0, 0.0], vec![0.5, 0.0, 0.0]],
+            // v = sqrt(m_total / r) / 2 for each body
+            velocity: vec![vec![0.0, -0.5, 0.0], vec![0.0, 0.5, 0.0]],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn momentum_balance() {
+        let mass = [1.0, 2.0, 3.0];
+        let pos = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0];
+        let acc = newton_acceleration(&mass, &pos);
+        for d in 0..DIM {
+            let mut f = 0.0;
+            for i in 0..mass.len() {
+                f += mass[i] * acc[DIM * i + d];
+            }
+            approx::assert_abs_diff_eq!(f, 0.0, epsilon = 1.0e-14);
+        }
+        // x component of the first body: 2 / 1^2 + 3 * cos(pi / 4) / 2
+        approx::assert_abs_diff_eq!(acc[0], 2.0 + 3.0 / 8.0_f64.sqrt(), epsilon = 1.0e-14);
+    }
+
+    #[test]
+    fn circular_orbit_period() {
+        let n_body = circular_binary();
+        let mut x = n_body.to_smp_vector();
+        let period = 2.0 * std::f64::consts::PI;
+
+        let mut sol = DOP54::new(1.0e-3, 1.0e-2, 1.0e-13, 1.01, 0.9);
+        let func = n_body.newton_eom();
+        loop {
+            x = sol.solve(func, &x);
+            if x.time + sol.get_delta_t() > period {
+                break;
+            }
+        }
+        sol.set_delta_t(period - x.time);
+        x = sol.solve_5th_order(func, &x);
+
+        approx::assert_abs_diff_eq!(x.time, period, epsilon = 1.0e-12);
+        approx::assert_abs_diff_eq!(x.vec[0], -0.5, epsilon = 1.0e-9);
+        approx::assert_abs_diff_eq!(x.vec[1], 0.0, epsilon = 1.0e-9);
+        approx::assert_abs_diff_eq!(x.vec[7], -0.5, epsilon = 1.0e-9);
+    }
+
+    #[test]
+    fn post_newtonian_config() {
+        let yaml = "
+SettingName: \"1pn\"
+NumberOfBodies: 2
+Mass: [0.5, 0.5]
+Position: [[-0.5, 0.0, 0.0], [0.5, 0.0, 0.0]]
+Velocity: [[0.0, -0.5, 0.0], [0.0, 0.5, 0.0]]
+SpeedOfLight: 10.0
+PostNewtonian:
+  Pn1: true
+";
+        let mut n_body: NBody = serde_yaml::from_str(yaml).unwrap();
+        assert!(n_body.check().is_ok());
+        assert!(n_body.post_newtonian.pn1);
+
+        let x = n_body.to_smp_vector();
+        // 1PN circular orbit (harmonic coordinates): omega^2 r^3 / m = 1 + (nu - 3) m / (r c^2)
+        let dx = n_body.eom()(&x);
+        approx::assert_abs_diff_eq!(
+            dx.vec[6],
+            0.5 * (1.0 + (0.25 - 3.0) / 100.0),
+            epsilon = 1.0e-14
+        );
+
+        n_body.speed_of_light = None;
+        assert!(n_body.check().is_err());
+    }
+
+    #[test]
+    fn two_body_mode() {
+        let yaml = "
+SettingName: \"inspiral\"
+NumberOfBodies: 2
+Mass: [0.8, 0.2]
+Position: [[0.8, 0.0, 0.0], [-0.2, 0.0, 0.0]]
+SpeedOfLight: 10.0
+Mode: TwoBody
+PostNewtonian:
+  Pn3: true
+  Pn35: true
+";
+        let mut n_body: NBody = serde_yaml::from_str(yaml).unwrap();
+        assert!(n_body.check().is_ok());
+        let x = n_body.to_smp_vector();
+        assert_eq!(x.vec.len(), 2 * DIM);
+        approx::assert_abs_diff_eq!(x.vec[0], 1.0, epsilon = 1.0e-15);
+        // at rest only the static 3PN term survives (m = r = 1, c = 10)
+        let nu: f64 = 0.16;
+        let pi2 = std::f64::consts::PI * std::f64::consts::PI;
+        let a3 = -16.0 - 437.0 / 4.0 * nu - 35.5 * nu * nu + 41.0 / 16.0 * nu * pi2;
+        let dx = n_body.eom()(&x);
+        approx::assert_abs_diff_eq!(dx.vec[3], -(1.0 + a3 * 1.0e-6), epsilon = 1.0e-14);
+
+        n_body.mode = Mode::NBody;
+        assert!(n_body.check().is_err());
+    }
+
+    #[test]
+    fn input_schema() {
+        let yaml = "
+SettingName: \"schema\"
+NumberOfBodies: 2
+Mass: [1.0, 1.0e-3]
+Position: [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0]]
+Velocity: [[0.0, 0.0, 0.0], [0.0, 1.0, 0.0]]
+Spin: [[0.0, 0.0, 0.5], [0.0, 0.0, 0.0]]
+Name: [\"sun\", \"planet\"]
+Radius: [4.65e-3, 4.8e-4]
+";
+        let mut n_body: NBody = serde_yaml::from_str(yaml).unwrap();
+        assert!(n_body.check().is_ok());
+        assert_eq!(n_body.label(1), "planet");
+        let x = n_body.to_smp_vector();
+        approx::assert_abs_diff_eq!(x.vec[10], 1.0, epsilon = 1.0e-15);
+
+        n_body.velocity[1].pop();
+        assert_eq!(n_body.check(), Err("velocity[i].len() != 3"));
+        n_body.velocity.pop();
+        assert_eq!(n_body.check(), Err("velocity.len() != number_of_bodies"));
+        n_body.velocity.clear();
+        assert!(n_body.check().is_ok());
+        n_body.spin = Some(vec![vec![0.0; 3]]);
+        assert_eq!(n_body.check(), Err("spin.len() != number_of_bodies"));
+        n_body.spin = None;
+        n_body.name = Some(vec!["sun".to_string()]);
+        assert_eq!(n_body.check(), Err("name.len() != number_of_bodies"));
+        n_body.name = None;
+        n_body.radius = Some(vec![1.0, -1.0]);
+        assert_eq!(n_body.check(), Err("radius < 0"));
+        n_body.radius = None;
+        n_body.mass[1] = 0.0;
+        assert_eq!(n_body.check(), Err("Mass must be > 0"));
+    }
+
+    #[test]
+    fn spin_precession_config() {
+        let yaml = "
+SettingName: \"spinning\"
+NumberOfBodies: 2
+Mass: [0.6, 0.4]
+Position: [[0.4, 0.0, 0.0], [-0.6, 0.0, 0.0]]
+Velocity: [[0.0, 0.4, 0.0], [0.0, -0.6, 0.0]]
+Spin: [[0.0, 0.1, 0.2], [0.05, 0.0, 0.1]]
+SpeedOfLight: 10.0
+PostNewtonian:
+  SpinOrbit: true
+  SpinSpin: true
+";
+        let mut n_body: NBody = serde_yaml::from_str(yaml).unwrap();
+        assert!(n_body.check().is_ok());
+        let x = n_body.to_smp_vector();
+        assert_eq!(x.vec.len(), 3 * DIM * 2);
+        approx::assert_abs_diff_eq!(x.vec[13], 0.1, epsilon = 1.0e-15);
+
+        // both modes give the same relative acceleration and spin precession
+        let dx = n_body.eom()(&x);
+        n_body.mode = Mode::TwoBody;
+        let x_rel = n_body.to_smp_vector();
+        assert_eq!(x_rel.vec.len(), 2 * DIM + DIM * 2);
+        let dx_rel = n_body.eom()(&x_rel);
+        for d in 0..DIM {
+            approx::assert_abs_diff_eq!(
+                dx.vec[DIM * 2 + d] - dx.vec[DIM * 3 + d],
+                dx_rel.vec[DIM + d],
+                epsilon = 1.0e-14
+            );
+        }
+        for k in 0..DIM * 2 {
+            approx::assert_abs_diff_eq!(dx.vec[12 + k], dx_rel.vec[6 + k], epsilon = 1.0e-15);
+        }
+        // L_N along z, S_1 precesses about it at (2 + 3 m_2 / (2 m_1)) L_N / r^3 / c^2
+        n_body.post_newtonian.spin_spin = false;
+        let dx_rel = n_body.eom()(&x_rel);
+        let omega = (2.0 + 1.5 * 0.4 / 0.6) * 0.24 / 100.0;
+        approx::assert_abs_diff_eq!(dx_rel.vec[6], -omega * 0.1, epsilon = 1.0e-15);
+
+        n_body.spin = None;
+        assert_eq!(n_body.check(), Err("SpinOrbit and SpinSpin require Spin"));
+    }
+
+    #[test]
+    fn newton_eom_layout() {
+        let yaml = "
+SettingName: \"spinning\"
+NumberOfBodies: 2
+Mass: [0.6, 0.4]
+Position: [[0.4, 0.0, 0.0], [-0.6, 0.0, 0.0]]
+Velocity: [[0.0, 0.4, 0.0], [0.0, -0.6, 0.0]]
+Spin: [[0.0, 0.1, 0.2], [0.05, 0.0, 0.1]]
+";
+        let mut n_body: NBody = serde_yaml::from_str(yaml).unwrap();
+        assert!(n_body.check().is_ok());
+        let x = n_body.to_smp_vector();
+        let dx = n_body.newton_eom()(&x);
+        assert_eq!(dx.vec.len(), 3 * DIM * 2);
+        approx::assert_abs_diff_eq!(dx.vec[1], 0.4, epsilon = 1.0e-15);
+        approx::assert_abs_diff_eq!(dx.vec[6], -0.4, epsilon = 1.0e-15);
+        approx::assert_abs_diff_eq!(dx.vec[9], 0.6, epsilon = 1.0e-15);
+        assert!(dx.vec[12..].iter().all(|s| *s == 0.0));
+
+        // the relative coordinate: a = -m x / r^3
+        n_body.mode = Mode::TwoBody;
+        let x = n_body.to_smp_vector();
+        let dx = n_body.newton_eom()(&x);
+        assert_eq!(dx.vec.len(), 2 * DIM + DIM * 2);
+        approx::assert_abs_diff_eq!(dx.vec[1], 1.0, epsilon = 1.0e-15);
+        approx::assert_abs_diff_eq!(dx.vec[3], -1.0, epsilon = 1.0e-15);
+        assert!(dx.vec[6..].iter().all(|s| *s == 0.0));
+    }
+
+    #[test]
+    fn physical_units() {
+        let yaml = "
+SettingName: \"sun-earth\"
+NumberOfBodies: 2
+Mass: [1.0, 3.0e-6]
+Position: [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0]]
+Units: Astronomical
+OutputUnits: SI
+PostNewtonian:
+  Pn1: true
+";
+        let mut n_body: NBody = serde_yaml::from_str(yaml).unwrap();
+        // c defaults to its physical value in AU / yr
+        assert!(n_body.check().is_ok());
+        approx::assert_relative_eq!(
+            n_body.light_speed(),
+            63_241.077_084_266,
+            max_relative = 1.0e-12
+        );
+
+        let x = n_body.to_smp_vector();
+        let dx = n_body.eom()(&x);
+        // test body at rest: a = -(G M / r^2) (1 - 4 G M / (c^2 r))
+        let g = Units::Astronomical.gravitational_constant();
+        let c2 = n_body.light_speed().powi(2);
+        approx::assert_relative_eq!(dx.vec[9], -g * (1.0 - 4.0 * g / c2), max_relative = 1.0e-10);
+
+        let out = n_body.to_output_units(&x);
+        approx::assert_relative_eq!(out.vec[3], ASTRONOMICAL_UNIT_SI, max_relative = 1.0e-15);
+
+        n_body.units = Units::NBody;
+        assert_eq!(
+            n_body.check(),
+            Err("SpeedOfLight is required for post-Newtonian terms")
+        );
+        n_body.speed_of_light = Some(10.0);
+        assert_eq!(
+            n_body.check(),
+            Err("N-body units cannot be converted to physical units")
+        );
+    }
+}
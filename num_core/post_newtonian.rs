@@ -0,0 +1,1175 @@
+// Post-Newtonian corrections to the N-body accelerations.
+//
+// Every N-body function returns only the correction of its own order, so that
+// the equations of motion are assembled as Newton + 1PN + ... in NBody.
+// The two-body (relative coordinate) problem goes up to 3.5PN.
+// N-body units (G = 1), harmonic coordinates.
+//
+use crate::num_core::n_body::DIM;
+use serde::{Deserialize, Serialize};
+
+// Switches of the post-Newtonian terms (YAML: PostNewtonian).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct PostNewtonian {
+    // 1PN: Einstein–Infeld–Hoffmann (full N-body)
+    pub pn1: bool,
+    // 2PN: conservative, pairwise sum of the two-body terms
+    pub pn2: bool,
+    // 2.5PN: radiation reaction, pairwise sum of the two-body terms
+    pub pn25: bool,
+    // 3PN: conservative, two-body only
+    pub pn3: bool,
+    // 3.5PN: radiation reaction, two-body only
+    pub pn35: bool,
+    // r'_0 of the logarithm in the 3PN harmonic-gauge terms
+    pub gauge_radius: f64,
+    // 1.5PN: leading-order spin-orbit coupling and precession
+    pub spin_orbit: bool,
+    // 2PN: leading-order spin-spin coupling and precession
+    pub spin_spin: bool,
+}
+
+impl Default for PostNewtonian {
+    fn default() -> Self {
+        PostNewtonian {
+            pn1: false,
+            pn2: false,
+            pn25: false,
+            pn3: false,
+            pn35: false,
+            gauge_radius: 1.0,
+            spin_orbit: false,
+            spin_spin: false,
+        }
+    }
+}
+
+impl PostNewtonian {
+    pub fn is_enabled(&self) -> bool {
+        self.pn1 || self.pn2 || self.pn25 || self.pn3 || self.pn35 || self.is_spin_enabled()
+    }
+
+    pub fn is_spin_enabled(&self) -> bool {
+        self.spin_orbit || self.spin_spin
+    }
+
+    // terms that exist only for the relative two-body problem
+    pub fn is_two_body_only(&self) -> bool {
+        self.pn3 || self.pn35
+    }
+
+    // Enable every orbital term up to the given order (0, 1, 2, 2.5, 3 or 3.5).
+    // The spin switches and the gauge radius are left as they are.
+    pub fn set_order(&mut self, order: f64) -> Result<(), &'static str> {
+        if ![0.0, 1.0, 2.0, 2.5, 3.0, 3.5].contains(&order) {
+            return Err("PnOrder must be one of 0, 1, 2, 2.5, 3, 3.5");
+        }
+        self.pn1 = order >= 1.0;
+        self.pn2 = order >= 2.0;
+        self.pn25 = order >= 2.5;
+        self.pn3 = order >= 3.0;
+        self.pn35 = order >= 3.5;
+        Ok(())
+    }
+}
+
+#[inline(always)]
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    let mut ret = 0.0;
+    for d in 0..DIM {
+        ret += a[d] * b[d];
+    }
+    ret
+}
+
+#[inline(always)]
+fn cross(a: &[f64], b: &[f64]) -> [f64; DIM] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+#[inline(always)]
+fn body(x: &[f64], i: usize) -> &[f64] {
+    &x[DIM * i..DIM * (i + 1)]
+}
+
+// 1PN Einstein–Infeld–Hoffmann acceleration.
+// Eq. (4) of Portegies Zwart et al. (arXiv:2109.11012), the 1/c^2 part only.
+// acc_newton is the Newtonian acceleration of every body, used for the
+// acceleration-dependent terms.
+pub fn eih_acceleration(
+    mass: &[f64],
+    pos: &[f64],
+    vel: &[f64],
+    acc_newton: &[f64],
+    c: f64,
+) -> Vec<f64> {
+    let phi = newton_potential(mass, pos);
+    let mut acc = Vec::with_capacity(DIM * mass.len());
+    for i in 0..mass.len() {
+        acc.extend_from_slice(&eih_body_acceleration(
+            i, mass, pos, vel, acc_newton, &phi, c,
+        ));
+    }
+    acc
+}
+
+// Newtonian potential: phi_i = sum_{k != i} m_k / r_ik
+pub fn newton_potential(mass: &[f64], pos: &[f64]) -> Vec<f64> {
+    let n = mass.len();
+    let mut phi = vec![0.0; n];
+    for i in 0..n {
+        for j in (i + 1)..n {
+            let mut r2 = 0.0;
+            for d in 0..DIM {
+                let dx = pos[DIM * i + d] - pos[DIM * j + d];
+                r2 += dx * dx;
+            }
+            let r = r2.sqrt();
+            phi[i] += mass[j] / r;
+            phi[j] += mass[i] / r;
+        }
+    }
+    phi
+}
+
+// 1PN EIH acceleration of body i alone, with the Newtonian accelerations and
+// potentials phi of every body given (O(N), for individual time steps).
+pub fn eih_body_acceleration(
+    i: usize,
+    mass: &[f64],
+    pos: &[f64],
+    vel: &[f64],
+    acc_newton: &[f64],
+    phi: &[f64],
+    c: f64,
+) -> [f64; DIM] {
+    let inv_c2 = 1.0 / (c * c);
+    let mut acc = [0.0; DIM];
+    let vi = body(vel, i);
+    let vi2 = dot(vi, vi);
+    for j in 0..mass.len() {
+        if i == j {
+            continue;
+        }
+        let vj = body(vel, j);
+        let aj = body(acc_newton, j);
+        // x_ij = x_i - x_j
+        let mut x_ij = [0.0; DIM];
+        for d in 0..DIM {
+            x_ij[d] = pos[DIM * i + d] - pos[DIM * j + d];
+        }
+        let r = dot(&x_ij, &x_ij).sqrt();
+        let r3 = r * r * r;
+        let n_ij_vj = dot(&x_ij, vj) / r;
+        let coef = -4.0 * phi[i] - phi[j] + vi2 + 2.0 * dot(vj, vj)
+            - 4.0 * dot(vi, vj)
+            - 1.5 * n_ij_vj * n_ij_vj
+            - 0.5 * dot(&x_ij, aj);
+        let mut x_ij_v = 0.0;
+        for d in 0..DIM {
+            x_ij_v += x_ij[d] * (4.0 * vi[d] - 3.0 * vj[d]);
+        }
+        for d in 0..DIM {
+            acc[d] += mass[j]
+                * (-x_ij[d] * coef / r3 + x_ij_v * (vi[d] - vj[d]) / r3 + 3.5 * aj[d] / r)
+                * inv_c2;
+        }
+    }
+    acc
+}
+
+// Sum of the pairwise two-body terms: pair(m_i, m_j, n_ij, r_ij, v_i, v_j)
+// is the acceleration of body i caused by body j.
+fn pairwise_sum(
+    mass: &[f64],
+    pos: &[f64],
+    vel: &[f64],
+    pair: impl Fn(f64, f64, &[f64], f64, &[f64], &[f64]) -> [f64; DIM],
+) -> Vec<f64> {
+    let n = mass.len();
+    let mut acc = vec![0.0; DIM * n];
+    for i in 0..n {
+        for j in 0..n {
+            if i == j {
+                continue;
+            }
+            let mut n_ij = [0.0; DIM];
+            for d in 0..DIM {
+                n_ij[d] = pos[DIM * i + d] - pos[DIM * j + d];
+            }
+            let r = dot(&n_ij, &n_ij).sqrt();
+            for x in n_ij.iter_mut() {
+                *x /= r;
+            }
+            let a = pair(mass[i], mass[j], &n_ij, r, body(vel, i), body(vel, j));
+            for d in 0..DIM {
+                acc[DIM * i + d] += a[d];
+            }
+        }
+    }
+    acc
+}
+
+// 2PN acceleration of body 1 due to body 2 in a general frame.
+// Blanchet, Living Rev. Relativ. 17, 2 (2014), Eq. (203).
+fn pair_2pn(m1: f64, m2: f64, n: &[f64], r: f64, v1: &[f64], v2: &[f64]) -> [f64; DIM] {
+    let v1v1 = dot(v1, v1);
+    let v2v2 = dot(v2, v2);
+    let v1v2 = dot(v1, v2);
+    let nv1 = dot(n, v1);
+    let nv2 = dot(n, v2);
+    let coef_n = -2.0 * v2v2 * v2v2 + 4.0 * v2v2 * v1v2 - 2.0 * v1v2 * v1v2
+        + 1.5 * v1v1 * nv2 * nv2
+        + 4.5 * v2v2 * nv2 * nv2
+        - 6.0 * v1v2 * nv2 * nv2
+        - 15.0 / 8.0 * nv2.powi(4)
+        + m1 / r
+            * (-15.0 / 4.0 * v1v1 + 5.0 / 4.0 * v2v2 - 2.5 * v1v2 + 19.5 * nv1 * nv1
+                - 39.0 * nv1 * nv2
+                + 8.5 * nv2 * nv2)
+        + m2 / r * (4.0 * v2v2 - 8.0 * v1v2 + 2.0 * nv1 * nv1 - 4.0 * nv1 * nv2 - 6.0 * nv2 * nv2)
+        + (-57.0 / 4.0 * m1 * m1 - 9.0 * m2 * m2 - 34.5 * m1 * m2) / (r * r);
+    let coef_v = v1v1 * nv2 + 4.0 * v2v2 * nv1 - 5.0 * v2v2 * nv2 - 4.0 * v1v2 * nv1
+        + 4.0 * v1v2 * nv2
+        - 6.0 * nv1 * nv2 * nv2
+        + 4.5 * nv2.powi(3)
+        + m1 / r * (-63.0 / 4.0 * nv1 + 55.0 / 4.0 * nv2)
+        + m2 / r * (-2.0 * nv1 - 2.0 * nv2);
+    let mut ret = [0.0; DIM];
+    for d in 0..DIM {
+        ret[d] = m2 / (r * r) * (coef_n * n[d] + coef_v * (v1[d] - v2[d]));
+    }
+    ret
+}
+
+// 2.5PN radiation-reaction acceleration of body 1 due to body 2 in a general frame.
+// Blanchet, Living Rev. Relativ. 17, 2 (2014), Eq. (203).
+fn pair_25pn(m1: f64, m2: f64, n: &[f64], r: f64, v1: &[f64], v2: &[f64]) -> [f64; DIM] {
+    let mut v12 = [0.0; DIM];
+    for d in 0..DIM {
+        v12[d] = v1[d] - v2[d];
+    }
+    let v12v12 = dot(&v12, &v12);
+    let nv12 = dot(n, &v12);
+    let coef_v = -v12v12 + 2.0 * m1 / r - 8.0 * m2 / r;
+    let coef_n = nv12 * (3.0 * v12v12 - 6.0 * m1 / r + 52.0 / 3.0 * m2 / r);
+    let mut ret = [0.0; DIM];
+    for d in 0..DIM {
+        ret[d] = 0.8 * m1 * m2 / (r * r * r) * (coef_v * v12[d] + coef_n * n[d]);
+    }
+    ret
+}
+
+// 2PN acceleration, pairwise sum of the two-body terms (1/c^4 part only).
+pub fn pairwise_2pn_acceleration(mass: &[f64], pos: &[f64], vel: &[f64], c: f64) -> Vec<f64> {
+    let inv_c4 = 1.0 / c.powi(4);
+    let mut acc = pairwise_sum(mass, pos, vel, pair_2pn);
+    for a in acc.iter_mut() {
+        *a *= inv_c4;
+    }
+    acc
+}
+
+// 2.5PN radiation reaction, pairwise sum of the two-body terms (1/c^5 part only).
+pub fn pairwise_25pn_acceleration(mass: &[f64], pos: &[f64], vel: &[f64], c: f64) -> Vec<f64> {
+    let inv_c5 = 1.0 / c.powi(5);
+    let mut acc = pairwise_sum(mass, pos, vel, pair_25pn);
+    for a in acc.iter_mut() {
+        *a *= inv_c5;
+    }
+    acc
+}
+
+//
+// Two-body problem in the center-of-mass frame.
+// a = -(m / r^2) [(1 + A) n + B v], x = x_1 - x_2, v = v_1 - v_2.
+// Blanchet, Living Rev. Relativ. 17, 2 (2014), Eqs. (219)-(220).
+//
+
+// Scalars of the relative motion entering A and B.
+struct Relative {
+    nu: f64,
+    // G m / r
+    gm: f64,
+    // n.v
+    rd: f64,
+    // v.v
+    v2: f64,
+}
+
+fn relative_1pn(q: &Relative) -> (f64, f64) {
+    let Relative { nu, gm, rd, v2 } = *q;
+    let a = -1.5 * rd * rd * nu + v2 + 3.0 * nu * v2 - gm * (4.0 + 2.0 * nu);
+    let b = -4.0 * rd + 2.0 * rd * nu;
+    (a, b)
+}
+
+fn relative_2pn(q: &Relative) -> (f64, f64) {
+    let Relative { nu, gm, rd, v2 } = *q;
+    let nu2 = nu * nu;
+    let rd2 = rd * rd;
+    let a = 15.0 / 8.0 * rd2 * rd2 * nu - 45.0 / 8.0 * rd2 * rd2 * nu2 - 4.5 * rd2 * nu * v2
+        + 6.0 * rd2 * nu2 * v2
+        + 3.0 * nu * v2 * v2
+        - 4.0 * nu2 * v2 * v2
+        + gm * (-2.0 * rd2 - 25.0 * rd2 * nu - 2.0 * rd2 * nu2 - 6.5 * nu * v2 + 2.0 * nu2 * v2)
+        + gm * gm * (9.0 + 87.0 / 4.0 * nu);
+    let b = 4.5 * rd2 * rd * nu + 3.0 * rd2 * rd * nu2 - 7.5 * rd * nu * v2 - 2.0 * rd * nu2 * v2
+        + gm * (2.0 * rd + 20.5 * rd * nu + 4.0 * rd * nu2);
+    (a, b)
+}
+
+fn relative_25pn(q: &Relative) -> (f64, f64) {
+    let Relative { nu, gm, rd, v2 } = *q;
+    let a = -24.0 / 5.0 * rd * nu * gm * v2 - 136.0 / 15.0 * rd * nu * gm * gm;
+    let b = 8.0 / 5.0 * nu * gm * v2 + 24.0 / 5.0 * nu * gm * gm;
+    (a, b)
+}
+
+// ln_r = ln(r / r'_0)
+fn relative_3pn(q: &Relative, ln_r: f64) -> (f64, f64) {
+    let Relative { nu, gm, rd, v2 } = *q;
+    let pi2 = std::f64::consts::PI * std::f64::consts::PI;
+    let nu2 = nu * nu;
+    let nu3 = nu2 * nu;
+    let rd2 = rd * rd;
+    let rd4 = rd2 * rd2;
+    let v4 = v2 * v2;
+    let a = -35.0 / 16.0 * rd4 * rd2 * nu + 175.0 / 16.0 * rd4 * rd2 * nu2
+        - 175.0 / 16.0 * rd4 * rd2 * nu3
+        + 7.5 * rd4 * nu * v2
+        - 135.0 / 4.0 * rd4 * nu2 * v2
+        + 255.0 / 8.0 * rd4 * nu3 * v2
+        - 7.5 * rd2 * nu * v4
+        + 237.0 / 8.0 * rd2 * nu2 * v4
+        - 22.5 * rd2 * nu3 * v4
+        + 11.0 / 4.0 * nu * v4 * v2
+        - 49.0 / 4.0 * nu2 * v4 * v2
+        + 13.0 * nu3 * v4 * v2
+        + gm * (79.0 * rd4 * nu - 34.5 * rd4 * nu2 - 30.0 * rd4 * nu3 - 121.0 * rd2 * nu * v2
+            + 16.0 * rd2 * nu2 * v2
+            + 20.0 * rd2 * nu3 * v2
+            + 75.0 / 4.0 * nu * v4
+            + 8.0 * nu2 * v4
+            - 10.0 * nu3 * v4)
+        + gm * gm
+            * (rd2 + 32573.0 / 168.0 * rd2 * nu + 11.0 / 8.0 * rd2 * nu2 - 7.0 * rd2 * nu3
+                + 615.0 / 64.0 * rd2 * nu * pi2
+                - 26987.0 / 840.0 * nu * v2
+                + nu3 * v2
+                - 123.0 / 64.0 * nu * pi2 * v2
+                - 110.0 * rd2 * nu * ln_r
+                + 22.0 * nu * v2 * ln_r)
+        + gm * gm * gm * (-16.0 - 437.0 / 4.0 * nu - 35.5 * nu2 + 41.0 / 16.0 * nu * pi2);
+    let b = -45.0 / 8.0 * rd4 * rd * nu
+        + 15.0 * rd4 * rd * nu2
+        + 15.0 / 4.0 * rd4 * rd * nu3
+        + 12.0 * rd2 * rd * nu * v2
+        - 111.0 / 4.0 * rd2 * rd * nu2 * v2
+        - 12.0 * rd2 * rd * nu3 * v2
+        - 65.0 / 8.0 * rd * nu * v4
+        + 19.0 * rd * nu2 * v4
+        + 6.0 * rd * nu3 * v4
+        + gm * (329.0 / 6.0 * rd2 * rd * nu + 29.5 * rd2 * rd * nu2 + 18.0 * rd2 * rd * nu3
+            - 15.0 * rd * nu * v2
+            - 27.0 * rd * nu2 * v2
+            - 10.0 * rd * nu3 * v2)
+        + gm * gm
+            * (-4.0 * rd - 5849.0 / 840.0 * rd * nu + 25.0 * rd * nu2 + 8.0 * rd * nu3
+                - 123.0 / 32.0 * rd * nu * pi2
+                - 44.0 * rd * nu * ln_r);
+    (a, b)
+}
+
+fn relative_35pn(q: &Relative) -> (f64, f64) {
+    let Relative { nu, gm, rd, v2 } = *q;
+    let nu2 = nu * nu;
+    let rd2 = rd * rd;
+    let v4 = v2 * v2;
+    let a = gm
+        * rd
+        * (366.0 / 35.0 * nu * v4 + 12.0 * nu2 * v4
+            - 114.0 * nu * v2 * rd2
+            - 12.0 * nu2 * v2 * rd2
+            + 112.0 * nu * rd2 * rd2)
+        + gm * gm
+            * rd
+            * (692.0 / 35.0 * nu * v2 - 724.0 / 15.0 * nu2 * v2
+                + 294.0 / 5.0 * nu * rd2
+                + 376.0 / 5.0 * nu2 * rd2)
+        + gm * gm * gm * rd * (3956.0 / 35.0 * nu + 184.0 / 5.0 * nu2);
+    let b = gm
+        * (-626.0 / 35.0 * nu * v4 - 12.0 / 5.0 * nu2 * v4
+            + 678.0 / 5.0 * nu * v2 * rd2
+            + 12.0 / 5.0 * nu2 * v2 * rd2
+            - 120.0 * nu * rd2 * rd2)
+        + gm * gm
+            * (164.0 / 21.0 * nu * v2 + 148.0 / 5.0 * nu2 * v2
+                - 82.0 / 3.0 * nu * rd2
+                - 848.0 / 15.0 * nu2 * rd2)
+        + gm * gm * gm * (-1060.0 / 21.0 * nu - 104.0 / 5.0 * nu2);
+    (a, b)
+}
+
+// Relative acceleration of a binary with total mass m and symmetric mass ratio nu,
+// Newton + the enabled post-Newtonian terms.
+pub fn relative_acceleration(
+    m: f64,
+    nu: f64,
+    x: &[f64],
+    v: &[f64],
+    c: f64,
+    pn: &PostNewtonian,
+) -> [f64; DIM] {
+    let r = dot(x, x).sqrt();
+    let q = Relative {
+        nu,
+        gm: m / r,
+        rd: dot(x, v) / r,
+        v2: dot(v, v),
+    };
+    let inv_c = 1.0 / c;
+    let (mut a, mut b) = (1.0, 0.0);
+    // (A_k, B_k) / c^order
+    let mut add = |(da, db): (f64, f64), order: i32| {
+        a += da * inv_c.powi(order);
+        b += db * inv_c.powi(order);
+    };
+    if pn.pn1 {
+        add(relative_1pn(&q), 2);
+    }
+    if pn.pn2 {
+        add(relative_2pn(&q), 4);
+    }
+    if pn.pn25 {
+        add(relative_25pn(&q), 5);
+    }
+    if pn.pn3 {
+        add(relative_3pn(&q, (r / pn.gauge_radius).ln()), 6);
+    }
+    if pn.pn35 {
+        add(relative_35pn(&q), 7);
+    }
+    let mut ret = [0.0; DIM];
+    for d in 0..DIM {
+        ret[d] = -m / (r * r) * (a * x[d] / r + b * v[d]);
+    }
+    ret
+}
+
+//
+// Spin effects at leading order, S_i being the spin angular momentum of body i.
+// Kidder, Phys. Rev. D 52, 821 (1995), Eqs. (2.2c), (2.2d) and (2.4).
+// x = x_1 - x_2, v = v_1 - v_2, covariant spin supplementary condition.
+//
+
+// Relative spin-orbit (1.5PN) and spin-spin (2PN) acceleration.
+pub fn relative_spin_acceleration(
+    m1: f64,
+    m2: f64,
+    x: &[f64],
+    v: &[f64],
+    (s1, s2): (&[f64], &[f64]),
+    c: f64,
+    pn: &PostNewtonian,
+) -> [f64; DIM] {
+    let m = m1 + m2;
+    let mu = m1 * m2 / m;
+    let r = dot(x, x).sqrt();
+    let mut n = [0.0; DIM];
+    for d in 0..DIM {
+        n[d] = x[d] / r;
+    }
+    let inv_c2 = 1.0 / (c * c);
+    let mut ret = [0.0; DIM];
+    if pn.spin_orbit {
+        // S = S_1 + S_2, (dm / m) Delta = (m_1 - m_2) (S_2 / m_2 - S_1 / m_1)
+        let mut spin = [0.0; DIM];
+        let mut delta = [0.0; DIM];
+        for d in 0..DIM {
+            spin[d] = s1[d] + s2[d];
+            delta[d] = (m1 - m2) * (s2[d] / m2 - s1[d] / m1);
+        }
+        let rd = dot(&n, v);
+        let mut s_a = [0.0; DIM];
+        let mut s_b = [0.0; DIM];
+        let mut s_c = [0.0; DIM];
+        for d in 0..DIM {
+            s_a[d] = 2.0 * spin[d] + delta[d];
+            s_b[d] = 7.0 * spin[d] + 3.0 * delta[d];
+            s_c[d] = 3.0 * spin[d] + delta[d];
+        }
+        let nv_s = dot(&cross(&n, v), &s_a);
+        let v_s = cross(v, &s_b);
+        let n_s = cross(&n, &s_c);
+        for d in 0..DIM {
+            ret[d] += (6.0 * n[d] * nv_s - v_s[d] + 3.0 * rd * n_s[d]) / (r * r * r) * inv_c2;
+        }
+    }
+    if pn.spin_spin {
+        let ns1 = dot(&n, s1);
+        let ns2 = dot(&n, s2);
+        let s1s2 = dot(s1, s2);
+        for d in 0..DIM {
+            ret[d] -= 3.0 / (mu * r.powi(4))
+                * (n[d] * s1s2 + s1[d] * ns2 + s2[d] * ns1 - 5.0 * n[d] * ns1 * ns2)
+                * inv_c2;
+        }
+    }
+    ret
+}
+
+// Precession dS_1/dt of the spin of body 1 in a binary; swap the bodies
+// (x -> -x, v -> -v) for dS_2/dt.
+pub fn spin_precession(
+    m1: f64,
+    m2: f64,
+    x: &[f64],
+    v: &[f64],
+    (s1, s2): (&[f64], &[f64]),
+    c: f64,
+    pn: &PostNewtonian,
+) -> [f64; DIM] {
+    let mu = m1 * m2 / (m1 + m2);
+    let r = dot(x, x).sqrt();
+    let inv_r3_c2 = 1.0 / (r * r * r * c * c);
+    let mut ret = [0.0; DIM];
+    if pn.spin_orbit {
+        // Newtonian orbital angular momentum L_N = mu x * v
+        let mut l_n = cross(x, v);
+        for l in l_n.iter_mut() {
+            *l *= mu;
+        }
+        let l_s = cross(&l_n, s1);
+        for d in 0..DIM {
+            ret[d] += (2.0 + 1.5 * m2 / m1) * l_s[d] * inv_r3_c2;
+        }
+    }
+    if pn.spin_spin {
+        let mut n = [0.0; DIM];
+        for d in 0..DIM {
+            n[d] = x[d] / r;
+        }
+        let s2_s1 = cross(s2, s1);
+        let n_s1 = cross(&n, s1);
+        let ns2 = dot(&n, s2);
+        for d in 0..DIM {
+            ret[d] += (-s2_s1[d] + 3.0 * ns2 * n_s1[d]) * inv_r3_c2;
+        }
+    }
+    ret
+}
+
+// Spin terms of the N-body problem as a sum over pairs. The relative
+// acceleration of every pair is shared as m_j / m and -m_i / m, which keeps
+// the Newtonian center of mass of the pair.
+// Returns (acceleration, dS/dt).
+pub fn pairwise_spin_terms(
+    mass: &[f64],
+    pos: &[f64],
+    vel: &[f64],
+    spin: &[f64],
+    c: f64,
+    pn: &PostNewtonian,
+) -> (Vec<f64>, Vec<f64>) {
+    let n = mass.len();
+    let mut acc = vec![0.0; DIM * n];
+    let mut spin_dot = vec![0.0; DIM * n];
+    for i in 0..n {
+        for j in (i + 1)..n {
+            let (mi, mj) = (mass[i], mass[j]);
+            let mut x = [0.0; DIM];
+            let mut v = [0.0; DIM];
+            for d in 0..DIM {
+                x[d] = pos[DIM * i + d] - pos[DIM * j + d];
+                v[d] = vel[DIM * i + d] - vel[DIM * j + d];
+            }
+            let (si, sj) = (body(spin, i), body(spin, j));
+            let a = relative_spin_acceleration(mi, mj, &x, &v, (si, sj), c, pn);
+            let dsi = spin_precession(mi, mj, &x, &v, (si, sj), c, pn);
+            let mut x_ji = [0.0; DIM];
+            let mut v_ji = [0.0; DIM];
+            for d in 0..DIM {
+                x_ji[d] = -x[d];
+                v_ji[d] = -v[d];
+            }
+            let dsj = spin_precession(mj, mi, &x_ji, &v_ji, (sj, si), c, pn);
+            for d in 0..DIM {
+                acc[DIM * i + d] += mj / (mi + mj) * a[d];
+                acc[DIM * j + d] -= mi / (mi + mj) * a[d];
+                spin_dot[DIM * i + d] += dsi[d];
+                spin_dot[DIM * j + d] += dsj[d];
+            }
+        }
+    }
+    (acc, spin_dot)
+}
+
+#[cfg(test)]
+pub mod post_newtonian_tests {
+    extern crate approx;
+    use crate::num_core::integrator;
+    use crate::num_core::n_body::{newton_acceleration, DIM};
+    use crate::num_core::post_newtonian::*;
+    use crate::num_core::smp_vector::SmpVector;
+    use crate::num_core::step_control::{PIController, Tolerance};
+
+    // post-Newtonian part of the relative acceleration in the center-of-mass frame
+    fn relative_correction(
+        m1: f64,
+        m2: f64,
+        x: &[f64],
+        v: &[f64],
+        c: f64,
+        pn: &PostNewtonian,
+    ) -> Vec<f64> {
+        let m = m1 + m2;
+        let nu = m1 * m2 / (m * m);
+        let a = relative_acceleration(m, nu, x, v, c, pn);
+        let a_newton = relative_acceleration(m, nu, x, v, c, &PostNewtonian::default());
+        (0..DIM).map(|d| a[d] - a_newton[d]).collect()
+    }
+
+    // omega^2 r^3 / m of a circular orbit, solved by fixed-point iteration
+    fn circular_omega2(m: f64, nu: f64, r: f64, c: f64, pn: &PostNewtonian) -> f64 {
+        let x = [r, 0.0, 0.0];
+        let mut omega2 = m / (r * r * r);
+        for _ in 0..100 {
+            let v = [0.0, omega2.sqrt() * r, 0.0];
+            omega2 = -relative_acceleration(m, nu, &x, &v, c, pn)[0] / r;
+        }
+        omega2 * r * r * r / m
+    }
+
+    #[test]
+    fn pairwise_two_body_limit() {
+        // equal masses: the center-of-mass relations carry no PN corrections
+        let (m1, m2) = (0.5, 0.5);
+        let x = [0.8, -0.3, 0.2];
+        let v = [0.1, 0.9, -0.2];
+        let mut pos = vec![0.0; 2 * DIM];
+        let mut vel = vec![0.0; 2 * DIM];
+        for d in 0..DIM {
+            pos[d] = 0.5 * x[d];
+            pos[DIM + d] = -0.5 * x[d];
+            vel[d] = 0.5 * v[d];
+            vel[DIM + d] = -0.5 * v[d];
+        }
+        let mass = [m1, m2];
+        let acc2 = pairwise_2pn_acceleration(&mass, &pos, &vel, 1.0);
+        let acc25 = pairwise_25pn_acceleration(&mass, &pos, &vel, 1.0);
+        let pn2 = PostNewtonian {
+            pn2: true,
+            ..Default::default()
+        };
+        let pn25 = PostNewtonian {
+            pn25: true,
+            ..Default::default()
+        };
+        let expected2 = relative_correction(m1, m2, &x, &v, 1.0, &pn2);
+        let expected25 = relative_correction(m1, m2, &x, &v, 1.0, &pn25);
+        for d in 0..DIM {
+            approx::assert_abs_diff_eq!(acc2[d] - acc2[DIM + d], expected2[d], epsilon = 1.0e-13);
+            approx::assert_abs_diff_eq!(
+                acc25[d] - acc25[DIM + d],
+                expected25[d],
+                epsilon = 1.0e-13
+            );
+        }
+
+        // unequal masses in the 1PN center-of-mass frame,
+        // y_1 = [X_2 + nu (X_1 - X_2) P] x, P = (v^2 - m / r) / (2 c^2);
+        // the shift of the velocities makes up the 2PN part of EIH
+        let (m1, m2) = (0.8, 0.2);
+        let m = m1 + m2;
+        let nu = m1 * m2 / (m * m);
+        let c = 30.0;
+        let r = dot(&x, &x).sqrt();
+        let rd = dot(&x, &v) / r;
+        let p = (dot(&v, &v) - m / r) / (2.0 * c * c);
+        let p_dot = -m * rd / (2.0 * r * r * c * c);
+        let shift = nu * (m1 - m2) / m;
+        for d in 0..DIM {
+            pos[d] = (m2 / m + shift * p) * x[d];
+            pos[DIM + d] = pos[d] - x[d];
+            vel[d] = (m2 / m + shift * p) * v[d] + shift * p_dot * x[d];
+            vel[DIM + d] = vel[d] - v[d];
+        }
+        let mass = [m1, m2];
+        let acc_newton = newton_acceleration(&mass, &pos);
+        let acc1 = eih_acceleration(&mass, &pos, &vel, &acc_newton, c);
+        let acc2 = pairwise_2pn_acceleration(&mass, &pos, &vel, c);
+        let acc25 = pairwise_25pn_acceleration(&mass, &pos, &vel, c);
+        let pn12 = PostNewtonian {
+            pn1: true,
+            pn2: true,
+            ..Default::default()
+        };
+        let expected12 = relative_correction(m1, m2, &x, &v, c, &pn12);
+        let expected25 = relative_correction(m1, m2, &x, &v, c, &pn25);
+        for d in 0..DIM {
+            let a = acc1[d] + acc2[d] - acc1[DIM + d] - acc2[DIM + d];
+            // in units of the 2PN and 2.5PN orders
+            approx::assert_abs_diff_eq!((a - expected12[d]) * c.powi(4), 0.0, epsilon = 1.0e-3);
+            approx::assert_abs_diff_eq!(
+                (acc25[d] - acc25[DIM + d] - expected25[d]) * c.powi(5),
+                0.0,
+                epsilon = 1.0e-3
+            );
+        }
+    }
+
+    #[test]
+    fn radiation_reaction_circular_energy_loss() {
+        // quadrupole formula: dE/dt = -(32 / 5) nu^2 m^5 / r^5 (c = 1)
+        let (m1, m2) = (0.8, 0.2);
+        let m = m1 + m2;
+        let nu = m1 * m2 / (m * m);
+        let r: f64 = 10.0;
+        let v = (m / r).sqrt();
+        let pos = [m2 / m * r, 0.0, 0.0, -m1 / m * r, 0.0, 0.0];
+        let vel = [0.0, m2 / m * v, 0.0, 0.0, -m1 / m * v, 0.0];
+        let mass = [m1, m2];
+        let acc = pairwise_25pn_acceleration(&mass, &pos, &vel, 1.0);
+        let mut de_dt = 0.0;
+        for i in 0..2 {
+            de_dt += mass[i] * dot(&acc[DIM * i..DIM * (i + 1)], &vel[DIM * i..DIM * (i + 1)]);
+        }
+        approx::assert_relative_eq!(
+            de_dt,
+            -32.0 / 5.0 * nu * nu * m.powi(5) / r.powi(5),
+            max_relative = 1.0e-12
+        );
+    }
+
+    #[test]
+    fn eih_two_body_limit() {
+        let (m1, m2) = (0.7, 0.3);
+        let m = m1 + m2;
+        let x = [0.8, -0.3, 0.2];
+        let v = [0.1, 0.9, -0.2];
+        // Newtonian center-of-mass frame
+        let mut pos = vec![0.0; 2 * DIM];
+        let mut vel = vec![0.0; 2 * DIM];
+        for d in 0..DIM {
+            pos[d] = m2 / m * x[d];
+            pos[DIM + d] = -m1 / m * x[d];
+            vel[d] = m2 / m * v[d];
+            vel[DIM + d] = -m1 / m * v[d];
+        }
+        let c = 3.0;
+        let mass = [m1, m2];
+        let acc_newton = newton_acceleration(&mass, &pos);
+        let acc = eih_acceleration(&mass, &pos, &vel, &acc_newton, c);
+        let pn1 = PostNewtonian {
+            pn1: true,
+            ..Default::default()
+        };
+        let expected = relative_correction(m1, m2, &x, &v, c, &pn1);
+        for d in 0..DIM {
+            approx::assert_abs_diff_eq!(acc[d] - acc[DIM + d], expected[d], epsilon = 1.0e-13);
+        }
+    }
+
+    #[test]
+    fn eih_scales_as_inverse_c2() {
+        let mass = [1.0, 2.0, 3.0];
+        let pos = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0];
+        let vel = [0.1, 0.0, 0.0, 0.0, 0.2, 0.0, -0.1, 0.0, 0.3];
+        let acc_newton = newton_acceleration(&mass, &pos);
+        let acc1 = eih_acceleration(&mass, &pos, &vel, &acc_newton, 1.0e3);
+        let acc2 = eih_acceleration(&mass, &pos, &vel, &acc_newton, 2.0e3);
+        // 1 / c^2 scaling
+        for k in 0..acc1.len() {
+            approx::assert_abs_diff_eq!(acc1[k], 4.0 * acc2[k], epsilon = 1.0e-15);
+        }
+    }
+
+    #[test]
+    fn three_pn_circular_orbit() {
+        // omega^2 r^3 / m = 1 + (nu - 3) g + (6 + 41 nu / 4 + nu^2) g^2 + a_3 g^3, g = m / (r c^2)
+        let (m, nu) = (1.0, 0.16);
+        let (r, c) = (1.0, 100.0);
+        let pn = PostNewtonian {
+            pn1: true,
+            pn2: true,
+            pn3: true,
+            gauge_radius: 0.5,
+            ..Default::default()
+        };
+        let g: f64 = m / (r * c * c);
+        let pi2 = std::f64::consts::PI * std::f64::consts::PI;
+        let a1 = nu - 3.0;
+        let a2 = 6.0 + 41.0 / 4.0 * nu + nu * nu;
+        let a3 = -10.0
+            + (-75707.0 / 840.0 + 41.0 / 64.0 * pi2 + 22.0 * 2.0_f64.ln()) * nu
+            + 9.5 * nu * nu
+            + nu * nu * nu;
+        let omega2 = circular_omega2(m, nu, r, c, &pn);
+        approx::assert_abs_diff_eq!(
+            (omega2 - 1.0 - a1 * g - a2 * g * g) / g.powi(3),
+            a3,
+            epsilon = 1.0e-2
+        );
+    }
+
+    #[test]
+    fn three_pn_eccentric_energy() {
+        // The 1PN, 2PN and 3PN terms conserve the 3PN energy order by order: the
+        // coefficients of 1 / c^2, 1 / c^4 and 1 / c^6 in dE/dt vanish. Checked
+        // along an eccentric orbit, where the r-dot terms of A and B take part.
+        let pi2 = std::f64::consts::PI * std::f64::consts::PI;
+        let gauge_radius = 0.5;
+        // energy per reduced mass in harmonic coordinates (G = m = 1), Blanchet,
+        // Living Rev. Relativ. 17, 2 (2014), Eq. (233), with the ln(r / r'_0) of
+        // the acceleration: terms (order in 1 / c^2, powers of v^2, r-dot, m / r
+        // and ln(r / r'_0), coefficients of 1, nu, nu^2, nu^3)
+        let energy = [
+            (0, [1, 0, 0, 0], [1.0 / 2.0, 0.0, 0.0, 0.0]),
+            (0, [0, 0, 1, 0], [-1.0, 0.0, 0.0, 0.0]),
+            (1, [2, 0, 0, 0], [3.0 / 8.0, -9.0 / 8.0, 0.0, 0.0]),
+            (1, [1, 0, 1, 0], [3.0 / 2.0, 1.0 / 2.0, 0.0, 0.0]),
+            (1, [0, 2, 1, 0], [0.0, 1.0 / 2.0, 0.0, 0.0]),
+            (1, [0, 0, 2, 0], [1.0 / 2.0, 0.0, 0.0, 0.0]),
+            (
+                2,
+                [3, 0, 0, 0],
+                [5.0 / 16.0, -35.0 / 16.0, 65.0 / 16.0, 0.0],
+            ),
+            (2, [2, 0, 1, 0], [21.0 / 8.0, -23.0 / 8.0, -27.0 / 8.0, 0.0]),
+            (2, [1, 2, 1, 0], [0.0, 1.0 / 4.0, -15.0 / 4.0, 0.0]),
+            (2, [0, 4, 1, 0], [0.0, -3.0 / 8.0, 9.0 / 8.0, 0.0]),
+            (2, [1, 0, 2, 0], [7.0 / 4.0, -55.0 / 8.0, 1.0 / 2.0, 0.0]),
+            (2, [0, 2, 2, 0], [1.0 / 2.0, 69.0 / 8.0, 3.0 / 2.0, 0.0]),
+            (2, [0, 0, 3, 0], [-1.0 / 2.0, -15.0 / 4.0, 0.0, 0.0]),
+            (
+                3,
+                [4, 0, 0, 0],
+                [35.0 / 128.0, -413.0 / 128.0, 833.0 / 64.0, -2261.0 / 128.0],
+            ),
+            (
+                3,
+                [3, 0, 1, 0],
+                [55.0 / 16.0, -215.0 / 16.0, 29.0 / 4.0, 325.0 / 16.0],
+            ),
+            (
+                3,
+                [2, 2, 1, 0],
+                [0.0, -21.0 / 16.0, -75.0 / 16.0, 375.0 / 16.0],
+            ),
+            (
+                3,
+                [1, 4, 1, 0],
+                [0.0, -9.0 / 16.0, 21.0 / 4.0, -165.0 / 16.0],
+            ),
+            (
+                3,
+                [0, 6, 1, 0],
+                [0.0, 5.0 / 16.0, -25.0 / 16.0, 25.0 / 16.0],
+            ),
+            (
+                3,
+                [2, 0, 2, 0],
+                [135.0 / 16.0, -97.0 / 8.0, 203.0 / 8.0, -27.0 / 4.0],
+            ),
+            (
+                3,
+                [1, 2, 2, 0],
+                [3.0 / 4.0, 31.0 / 2.0, -815.0 / 16.0, -81.0 / 4.0],
+            ),
+            (3, [0, 4, 2, 0], [0.0, -731.0 / 48.0, 41.0 / 4.0, 6.0]),
+            (
+                3,
+                [1, 0, 3, 0],
+                [5.0 / 4.0, -48403.0 / 2520.0, -21.0 / 4.0, 1.0 / 2.0],
+            ),
+            (3, [1, 0, 3, 0], [0.0, 41.0 / 64.0 * pi2, 0.0, 0.0]),
+            (3, [1, 0, 3, 1], [0.0, 22.0, 0.0, 0.0]),
+            (
+                3,
+                [0, 2, 3, 0],
+                [3.0 / 2.0, 803.0 / 840.0, 51.0 / 4.0, 7.0 / 2.0],
+            ),
+            (3, [0, 2, 3, 0], [0.0, -123.0 / 64.0 * pi2, 0.0, 0.0]),
+            (3, [0, 2, 3, 1], [0.0, 22.0, 0.0, 0.0]),
+            (3, [0, 0, 4, 0], [3.0 / 8.0, 40007.0 / 2520.0, 0.0, 0.0]),
+            (3, [0, 0, 4, 1], [0.0, -22.0, 0.0, 0.0]),
+        ];
+        // power x^n and its derivative
+        let pow = |x: f64, n: i32| x.powi(n);
+        let dpow = |x: f64, n: i32| {
+            if n == 0 {
+                0.0
+            } else {
+                n as f64 * x.powi(n - 1)
+            }
+        };
+        for &nu in [0.16, 0.25].iter() {
+            // Newtonian orbit with a = 1, e = 0.5 at a few true anomalies
+            for i in 0..8 {
+                let phi = 0.4 + 0.8 * i as f64;
+                let r = 0.75 / (1.0 + 0.5 * phi.cos());
+                let q = Relative {
+                    nu,
+                    gm: 1.0 / r,
+                    rd: 0.5 * phi.sin() / 0.75_f64.sqrt(),
+                    v2: 2.0 / r - 1.0,
+                };
+                let ln_r = (r / gauge_radius).ln();
+                // d(r-dot)/dt and d(v^2)/dt of Newton and of the kPN terms
+                let mut rates = vec![((q.v2 - q.rd * q.rd) / r - q.gm / r, -2.0 * q.gm / r * q.rd)];
+                for (a, b) in [relative_1pn(&q), relative_2pn(&q), relative_3pn(&q, ln_r)].iter() {
+                    rates.push((
+                        -q.gm / r * (a + b * q.rd),
+                        -2.0 * q.gm / r * (a * q.rd + b * q.v2),
+                    ));
+                }
+                // coefficients of dE/dt and the size of their terms
+                let mut de_dt = [0.0; 7];
+                let mut size = [0.0; 7];
+                for &(k, [nv, nr, ng, nl], c) in energy.iter() {
+                    let c = c[0] + nu * (c[1] + nu * (c[2] + nu * c[3]));
+                    let (fv, fr, fg, fl) =
+                        (pow(q.v2, nv), pow(q.rd, nr), pow(q.gm, ng), pow(ln_r, nl));
+                    // d/dr through m / r and ln(r / r'_0) at fixed r-dot and v^2
+                    let de_dr =
+                        c * fv * fr * (-dpow(q.gm, ng) * q.gm * fl + fg * dpow(ln_r, nl)) / r;
+                    let de_drd = c * fv * dpow(q.rd, nr) * fg * fl;
+                    let de_dv2 = c * dpow(q.v2, nv) * fr * fg * fl;
+                    for (order, &(rd_dot, v2_dot)) in rates.iter().enumerate() {
+                        let mut term = de_drd * rd_dot + de_dv2 * v2_dot;
+                        if order == 0 {
+                            term += de_dr * q.rd;
+                        }
+                        de_dt[k + order] += term;
+                        size[k + order] += term.abs();
+                    }
+                }
+                for k in 1..=3 {
+                    approx::assert_abs_diff_eq!(de_dt[k], 0.0, epsilon = 1.0e-14 * size[k]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn three_half_pn_circular_flux() {
+        // energy balance with the 1PN quadrupole flux
+        // F = (32 / 5) nu^2 x^5 [1 + (-1247 / 336 - 35 nu / 12) x] (G = c = 1)
+        // m_1 = 0.8 m, m_2 = 0.2 m
+        let (m, nu) = (1.0, 0.16);
+        let (r, c) = (1.0, 100.0);
+        let pn1 = PostNewtonian {
+            pn1: true,
+            ..Default::default()
+        };
+        let radiation = PostNewtonian {
+            pn25: true,
+            pn35: true,
+            ..Default::default()
+        };
+        let omega = (circular_omega2(m, nu, r, c, &pn1) * m / (r * r * r)).sqrt();
+        let xx = [r, 0.0, 0.0];
+        let v = [0.0, omega * r, 0.0];
+        let a = relative_correction(0.8 * m, 0.2 * m, &xx, &v, c, &radiation);
+        // dE/dt = mu (dE_1PN / dv) . a, with E_1PN the 1PN energy per reduced mass
+        let v2 = omega * omega * r * r;
+        let de_dv = 1.0 + (1.5 * (1.0 - 3.0 * nu) * v2 + (3.0 + nu) * m / r) / (c * c);
+        let de_dt = nu * m * de_dv * dot(&v, &a);
+        let x = (m * omega).powf(2.0 / 3.0) / (c * c);
+        let flux_newton = 32.0 / 5.0 * nu * nu * x.powi(5) * c.powi(5);
+        approx::assert_abs_diff_eq!(
+            (-de_dt / flux_newton - 1.0) / x,
+            -1247.0 / 336.0 - 35.0 / 12.0 * nu,
+            epsilon = 1.0e-2
+        );
+    }
+
+    #[test]
+    fn three_half_pn_eccentric_flux() {
+        // orbit-averaged energy balance with the 1PN quadrupole flux of an eccentric binary
+        // F = (32 / 5) nu^2 x^5 / (1 - e^2)^(7/2) [f_0(e) + x f_1(e) / (1 - e^2)],
+        // x = (m omega)^(2/3), omega = (1 + k) n, e = e_t of the 1PN quasi-Keplerian orbit.
+        // Arun, Blanchet, Iyer, Qusailah, Phys. Rev. D 77, 064035 (2008).
+        // Unlike the circular orbit, the r-dot terms of A and B take part.
+        let (m1, m2) = (0.8, 0.2);
+        let m = m1 + m2;
+        let nu = m1 * m2 / (m * m);
+        let c: f64 = 300.0;
+        let pn1 = PostNewtonian {
+            pn1: true,
+            ..Default::default()
+        };
+        // 1PN relative orbit and the energy radiated by the 2.5PN and 3.5PN terms
+        // (in units of c^-5)
+        let func = |y: &SmpVector| {
+            let (x, v) = (&y.vec[..DIM], &y.vec[DIM..2 * DIM]);
+            let r = dot(x, x).sqrt();
+            let q = Relative {
+                nu,
+                gm: m / r,
+                rd: dot(x, v) / r,
+                v2: dot(v, v),
+            };
+            let (a25, b25) = relative_25pn(&q);
+            let (a35, b35) = relative_35pn(&q);
+            let (a_rr, b_rr) = (a25 + a35 / (c * c), b25 + b35 / (c * c));
+            // mu dE_1PN / dv . a_rr
+            let de_dv = 1.0 + (1.5 * (1.0 - 3.0 * nu) * q.v2 + (3.0 + nu) * q.gm) / (c * c);
+            let de_dx = nu * q.gm * q.rd / (r * c * c);
+            let mut ret = SmpVector::set_zero_vector(2 * DIM + 1);
+            ret.time = y.time;
+            ret.vec[..DIM].copy_from_slice(v);
+            ret.vec[DIM..2 * DIM].copy_from_slice(&relative_acceleration(m, nu, x, v, c, &pn1));
+            for d in 0..DIM {
+                let rr = -m / (r * r) * (a_rr * x[d] / r + b_rr * v[d]);
+                ret.vec[2 * DIM] += nu * m * (de_dv * v[d] + de_dx * x[d]) * rr;
+            }
+            ret
+        };
+        // from the pericenter of a Newtonian orbit with a = 1, e = 0.5
+        let y0 = SmpVector {
+            time: 0.0,
+            vec: vec![0.5, 0.0, 0.0, 0.0, 3.0_f64.sqrt(), 0.0, 0.0],
+        };
+        let at = |t: f64| {
+            let mut sol = integrator::from_name("DOP853", 1.0e-3, 0.1, 1.0e-14, 1.01, 0.9).unwrap();
+            sol.set_controller(PIController::new(
+                Tolerance::Scalar(1.0e-14),
+                Tolerance::Scalar(1.0e-14),
+            ));
+            sol.integrate_to(t, &func, &y0)
+        };
+        let r = |y: &SmpVector| dot(&y.vec[..DIM], &y.vec[..DIM]).sqrt();
+        let secant = |g: &dyn Fn(f64) -> f64, mut t0: f64, mut t1: f64| {
+            let mut g0 = g(t0);
+            for _ in 0..50 {
+                let g1 = g(t1);
+                if g1 == g0 {
+                    break;
+                }
+                let t2 = t1 - g1 * (t1 - t0) / (g1 - g0);
+                t0 = t1;
+                g0 = g1;
+                t1 = t2;
+                if (t1 - t0).abs() < 1.0e-13 {
+                    break;
+                }
+            }
+            t1
+        };
+        // radial period, from pericenter to pericenter
+        let period = secant(
+            &|t| {
+                let y = at(t);
+                dot(&y.vec[..DIM], &y.vec[DIM..2 * DIM])
+            },
+            6.2,
+            6.3,
+        );
+        let n = 2.0 * std::f64::consts::PI / period;
+        // r = a_r at u = pi / 2, where l = n t = pi / 2 - e_t
+        let a_r = 0.5 * (r(&y0) + r(&at(0.5 * period)));
+        let t_a = secant(&|t| r(&at(t)) - a_r, 1.0, 1.1);
+        let e = 0.5 * std::f64::consts::PI - n * t_a;
+        let y = at(period);
+        let omega = (y.vec[1].atan2(y.vec[0]) + 2.0 * std::f64::consts::PI) / period;
+        let x = (m * omega).powf(2.0 / 3.0) / (c * c);
+
+        let e2 = e * e;
+        let f0 = 1.0 + 73.0 / 24.0 * e2 + 37.0 / 96.0 * e2 * e2;
+        let f1 = -1247.0 / 336.0 - 35.0 / 12.0 * nu
+            + e2 * (10475.0 / 672.0 - 1081.0 / 36.0 * nu)
+            + e2 * e2 * (10043.0 / 384.0 - 311.0 / 12.0 * nu)
+            + e2 * e2 * e2 * (2179.0 / 1792.0 - 851.0 / 576.0 * nu);
+        let flux = -y.vec[2 * DIM] / period;
+        let flux_newton = 32.0 / 5.0 * nu * nu * (x * c * c).powi(5) / (1.0 - e2).powf(3.5);
+        approx::assert_abs_diff_eq!(
+            (flux / flux_newton - f0) / x,
+            f1 / (1.0 - e2),
+            epsilon = 1.0e-2
+        );
+    }
+
+    #[test]
+    fn lense_thirring_limit() {
+        // test mass around a spinning body: a = (2 / r^3) [3 (n * v) (n.S) + v * S] / c^2
+        let (m1, m2) = (1.0, 1.0e-12);
+        let x = [0.6, -0.8, 0.3];
+        let v = [0.2, 0.1, -0.4];
+        let s1 = [0.1, 0.3, 0.7];
+        let s2 = [0.0; DIM];
+        let pn = PostNewtonian {
+            spin_orbit: true,
+            ..Default::default()
+        };
+        let a = relative_spin_acceleration(m1, m2, &x, &v, (&s1, &s2), 1.0, &pn);
+        let r = dot(&x, &x).sqrt();
+        let n: Vec<f64> = x.iter().map(|x| x / r).collect();
+        let n_v = cross(&n, &v);
+        let v_s = cross(&v, &s1);
+        for d in 0..DIM {
+            let expected = 2.0 / r.powi(3) * (3.0 * n_v[d] * dot(&n, &s1) + v_s[d]);
+            approx::assert_abs_diff_eq!(a[d], expected, epsilon = 1.0e-10);
+        }
+    }
+
+    #[test]
+    fn spin_spin_potential_force() {
+        // mu a = -grad H with the spin-spin Hamiltonian H = [3 (n.S_1)(n.S_2) - S_1.S_2] / r^3
+        let (m1, m2) = (0.6, 0.4);
+        let mu = m1 * m2 / (m1 + m2);
+        let x = [0.6, -0.8, 0.3];
+        let s1 = [0.1, 0.3, 0.7];
+        let s2 = [-0.2, 0.5, 0.1];
+        let pn = PostNewtonian {
+            spin_spin: true,
+            ..Default::default()
+        };
+        let a = relative_spin_acceleration(m1, m2, &x, &[0.0; DIM], (&s1, &s2), 1.0, &pn);
+        let h = |x: &[f64]| {
+            let r = dot(x, x).sqrt();
+            (3.0 * dot(x, &s1) * dot(x, &s2) / (r * r) - dot(&s1, &s2)) / r.powi(3)
+        };
+        let eps = 1.0e-6;
+        for d in 0..DIM {
+            let mut xp = x;
+            let mut xm = x;
+            xp[d] += eps;
+            xm[d] -= eps;
+            let force = -(h(&xp) - h(&xm)) / (2.0 * eps);
+            approx::assert_abs_diff_eq!(mu * a[d], force, epsilon = 1.0e-8);
+        }
+    }
+
+    #[test]
+    fn spin_precession_conserves_magnitude() {
+        let mass = [0.6, 0.4, 0.1];
+        let pos = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 2.0, 0.5];
+        let vel = [0.0, -0.3, 0.1, 0.0, 0.6, 0.0, 0.4, 0.0, 0.0];
+        let spin = [0.1, 0.0, 0.3, 0.0, 0.2, 0.1, 0.0, 0.0, 0.01];
+        let pn = PostNewtonian {
+            spin_orbit: true,
+            spin_spin: true,
+            ..Default::default()
+        };
+        let (acc, spin_dot) = pairwise_spin_terms(&mass, &pos, &vel, &spin, 1.0, &pn);
+        for i in 0..mass.len() {
+            approx::assert_abs_diff_eq!(
+                dot(body(&spin, i), body(&spin_dot, i)),
+                0.0,
+                epsilon = 1.0e-15
+            );
+        }
+        // the pair forces cancel
+        for d in 0..DIM {
+            let f: f64 = (0..mass.len()).map(|i| mass[i] * acc[DIM * i + d]).sum();
+            approx::assert_abs_diff_eq!(f, 0.0, epsilon = 1.0e-15);
+        }
+    }
+}
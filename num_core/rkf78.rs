@@ -0,0 +1,165 @@
+// Runge–Kutta–Fehlberg method 7(8)
+//
+// E. Fehlberg, Classical fifth-, sixth-, seventh-, and eighth-order Runge-Kutta
+// formulas with stepsize control, NASA TR R-287 (1968), Table X.
+// The step is advanced with the 8th order solution (local extrapolation).
+// Tableau: ButcherTableau::fehlberg78, stepper: ExplicitRK.
+use crate::num_core::butcher_tableau::ButcherTableau;
+use crate::num_core::explicit_rk::ExplicitRK;
+use crate::num_core::integrator::Integrator;
+use crate::num_core::step_control::{PIController, StepStatistics};
+use crate::SmpVector;
+
+pub struct RKF78 {
+    rk: ExplicitRK,
+}
+
+impl RKF78 {
+    #[allow(dead_code)]
+    pub fn new(
+        dt1: f64,
+        dt_max1: f64,
+        abs_tol1: f64,
+        dt_scale_up_factor1: f64,
+        dt_scale_down_factor1: f64,
+    ) -> RKF78 {
+        RKF78 {
+            rk: ExplicitRK::new(
+                ButcherTableau::fehlberg78(),
+                dt1,
+                dt_max1,
+                abs_tol1,
+                dt_scale_up_factor1,
+                dt_scale_down_factor1,
+            ),
+        }
+    }
+
+    // 8th order solution (propagated)
+    #[allow(dead_code)]
+    #[inline(always)]
+    pub fn solve_8th_order(
+        &self,
+        func: impl Fn(&SmpVector) -> SmpVector,
+        x: &SmpVector,
+    ) -> SmpVector {
+        self.rk.solve_fixed(func, x)
+    }
+
+    // 7th order solution (error estimate)
+    #[allow(dead_code)]
+    #[inline(always)]
+    pub fn solve_7th_order(
+        &self,
+        func: impl Fn(&SmpVector) -> SmpVector,
+        x: &SmpVector,
+    ) -> SmpVector {
+        self.rk.solve_embedded(func, x)
+    }
+
+    #[allow(dead_code)]
+    #[inline(always)]
+    pub fn solve(&mut self, func: impl Fn(&SmpVector) -> SmpVector, x: &SmpVector) -> SmpVector {
+        self.rk.solve(func, x)
+    }
+
+    #[allow(dead_code)]
+    #[inline(always)]
+    pub fn get_num_of_dt_switch(&self) -> i64 {
+        self.rk.get_num_of_dt_switch()
+    }
+
+    #[allow(dead_code)]
+    #[inline(always)]
+    pub fn get_num_of_steps(&self) -> i64 {
+        self.rk.get_num_of_steps()
+    }
+
+    #[allow(dead_code)]
+    pub fn solve_to_end_time(
+        &mut self,
+        end_time: f64,
+        func: impl Fn(&SmpVector) -> SmpVector,
+        x: &SmpVector,
+    ) -> SmpVector {
+        self.rk.solve_to_end_time(end_time, func, x)
+    }
+
+    #[allow(dead_code)]
+    pub fn set_delta_t(&mut self, dt: f64) {
+        self.rk.set_delta_t(dt);
+    }
+
+    #[allow(dead_code)]
+    pub fn get_delta_t(&self) -> f64 {
+        self.rk.get_delta_t()
+    }
+
+    // scaled atol/rtol error norm and PI step control instead of the scale factors
+    #[allow(dead_code)]
+    pub fn set_controller(&mut self, controller: PIController) {
+        self.rk.set_controller(controller);
+    }
+}
+
+impl Integrator for RKF78 {
+    fn step(&self, func: &dyn Fn(&SmpVector) -> SmpVector, x: &SmpVector) -> SmpVector {
+        self.rk.step(func, x)
+    }
+
+    fn adaptive_step(
+        &mut self,
+        func: &dyn Fn(&SmpVector) -> SmpVector,
+        x: &SmpVector,
+    ) -> SmpVector {
+        self.rk.adaptive_step(func, x)
+    }
+
+    fn set_delta_t(&mut self, dt: f64) {
+        self.rk.set_delta_t(dt)
+    }
+
+    fn get_delta_t(&self) -> f64 {
+        self.rk.get_delta_t()
+    }
+
+    fn get_num_of_steps(&self) -> i64 {
+        self.rk.get_num_of_steps()
+    }
+
+    fn get_num_of_dt_switch(&self) -> i64 {
+        self.rk.get_num_of_dt_switch()
+    }
+
+    fn set_controller(&mut self, controller: PIController) {
+        self.rk.set_controller(controller)
+    }
+
+    fn statistics(&self) -> &StepStatistics {
+        self.rk.statistics()
+    }
+}
+
+#[cfg(test)]
+pub mod rkf78_tests {
+    extern crate approx;
+    use crate::num_core::rkf78::RKF78;
+    use crate::num_core::smp_vector::SmpVector;
+    use crate::num_core::test_problems::exponential;
+
+    #[test]
+    fn seventh_and_eighth_order() {
+        let s = RKF78::new(1.0e-1, 0.2, 1.0e-12, 1.001, 0.2);
+        let mut y7 = SmpVector::set_one_fill(1);
+        let mut y8 = SmpVector::set_one_fill(1);
+        for _i in 0..10 {
+            y7 = s.solve_7th_order(exponential, &y7);
+            y8 = s.solve_8th_order(exponential, &y8);
+        }
+        // global errors e h^7 / 8! and e h^8 / 9! (Taylor remainders of exp)
+        let e = std::f64::consts::E;
+        approx::assert_abs_diff_eq!(y7.vec[0], e, epsilon = 1.0e-11);
+        approx::assert_abs_diff_eq!(y8.vec[0], e, epsilon = 1.0e-13);
+        assert!((y8.vec[0] - e).abs() < (y7.vec[0] - e).abs());
+    }
+}
@@ -0,0 +1,559 @@
+//
+// Drive a simulation from the setting file (NBody + Run).
+//
+use crate::num_core::diagnostics::Diagnostics;
+use crate::num_core::events::Events;
+use crate::num_core::hermite::{self, Hermite};
+use crate::num_core::ias15::{self, IAS15};
+use crate::num_core::integrator::{self, Integrator};
+use crate::num_core::io_config::{Reversibility, Run};
+use crate::num_core::ks_regularization::{self, KSRegularization};
+use crate::num_core::n_body::{NBody, DIM};
+use crate::num_core::smp_vector::SmpVector;
+use crate::num_core::symplectic::{self, Symplectic};
+use std::io::{self, Write};
+
+// Apply the Run section to the bodies (PnOrder) and check both.
+pub fn prepare(n_body: &mut NBody, run: &Run) -> Result<(), &'static str> {
+    run.check()?;
+    if let Some(order) = run.pn_order {
+        n_body.post_newtonian.set_order(order)?;
+    }
+    n_body.check()?;
+    run.check_tolerance(n_body.state_len())?;
+    symplectic::check(&run.integrator, n_body)?;
+    hermite::check(&run.integrator, n_body)?;
+    ks_regularization::check(run, n_body)?;
+    for event in run.events.iter() {
+        event.check(n_body)?;
+    }
+    Ok(())
+}
+
+// one output line: time and the whole state vector
+fn write_state(out: &mut impl Write, x: &SmpVector) -> io::Result<()> {
+    write!(out, "{:.14}", x.time)?;
+    for v in x.vec.iter() {
+        write!(out, " {:.14}", v)?;
+    }
+    writeln!(out)
+}
+
+// Integrate the equations of motion from t = 0 to Run.end_time and write the
+// state every Run.output_interval (and at the end time) to out, in OutputUnits.
+// The drift of the conserved quantities goes to diagnostics at the same times.
+// The step is shortened to land exactly on every output time (Integrator::integrate_to),
+// or with Run.dense_output the state is interpolated there (Integrator::advance_to).
+// Run.events are written to events_out as they happen (name, time and state);
+// a terminating one ends the output with the state at the event.
+// With Run.reversibility the run goes on from the end time back to t = 0,
+// with the output on the way, and the distance of the state from the initial
+// one goes to stderr; the state back at the start is returned.
+pub fn simulate(
+    n_body: &NBody,
+    run: &Run,
+    out: &mut impl Write,
+    mut diagnostics: Option<&mut dyn Write>,
+    mut events_out: Option<&mut dyn Write>,
+) -> io::Result<SmpVector> {
+    let mut sol: Box<dyn Integrator> = if symplectic::NAMES.contains(&run.integrator.as_str()) {
+        Box::new(
+            Symplectic::from_name(&run.integrator, n_body, run.initial_dt)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?,
+        )
+    } else if run.integrator == hermite::NAME {
+        Box::new(Hermite::new(
+            n_body,
+            run.initial_dt,
+            run.max_dt,
+            run.hermite_eta,
+        ))
+    } else if run.integrator == ias15::NAME {
+        Box::new(IAS15::for_n_body(
+            n_body,
+            run.initial_dt,
+            run.max_dt,
+            run.ias15_epsilon,
+        ))
+    } else {
+        let sol = integrator::from_name(
+            &run.integrator,
+            run.initial_dt,
+            run.max_dt,
+            run.abs_tol.get(0),
+            run.scale_up_factor,
+            run.scale_down_factor,
+        )
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        match run.regularization_distance {
+            Some(distance) => Box::new(KSRegularization::new(n_body, sol, distance)),
+            None => sol,
+        }
+    };
+    if let Some(controller) = run.controller() {
+        sol.set_controller(controller);
+    }
+    sol.set_dense_output(run.dense_output)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    let func = n_body.eom();
+    // x is the integrated state, y the one at the output time (the same
+    // unless the output is interpolated with DenseOutput) and z the written
+    // one (y reversed back on the way back of ReverseVelocities)
+    let mut x = n_body.to_smp_vector();
+    let mut y;
+    let list = run.events.iter().map(|e| e.build(n_body)).collect();
+    let mut events = Events::new(list, &x, run.event_time_tol);
+    let mut num_events = 0;
+
+    let conserved = Diagnostics::new(n_body, &x);
+    if let Some(diag) = diagnostics.as_mut() {
+        conserved.write_header(*diag)?;
+        conserved.write(*diag, n_body, &x)?;
+    }
+    writeln!(out, "# time {}", n_body.column_labels().join(" "))?;
+    write_state(out, &n_body.to_output_units(&x))?;
+    if let Some(ev) = events_out.as_mut() {
+        writeln!(*ev, "# event time {}", n_body.column_labels().join(" "))?;
+    }
+    // (start, end) of the integrated time, and back with Reversibility:
+    // ReverseVelocities integrates the reversed state in t' = -t
+    let mut legs = vec![(0.0, run.end_time)];
+    match run.reversibility {
+        Some(Reversibility::NegativeDt) => legs.push((run.end_time, 0.0)),
+        Some(Reversibility::ReverseVelocities) => legs.push((-run.end_time, 0.0)),
+        None => (),
+    }
+    let x0 = x.clone();
+    let mut z = x.clone();
+    for (leg, (start, end)) in legs.into_iter().enumerate() {
+        let reversed = leg > 0 && run.reversibility == Some(Reversibility::ReverseVelocities);
+        if leg > 0 {
+            // from the state at the end time, not from a step beyond it
+            x = if reversed {
+                n_body.time_reversed(&z)
+            } else {
+                z.clone()
+            };
+            // the interpolant of the last step is left behind
+            sol.set_dense_output(run.dense_output)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        }
+        let (direction, length) = ((end - start).signum(), (end - start).abs());
+        let mut num_output = 1;
+        loop {
+            let t_out = start + direction * (num_output as f64 * run.output_interval).min(length);
+            y = if events.is_empty() {
+                sol.advance_to(t_out, &func, &mut x)
+            } else {
+                events.advance_to(sol.as_mut(), t_out, &func, &mut x)
+            };
+            z = if reversed {
+                n_body.time_reversed(&y)
+            } else {
+                y.clone()
+            };
+            write_state(out, &n_body.to_output_units(&z))?;
+            if let Some(diag) = diagnostics.as_mut() {
+                conserved.write(*diag, n_body, &z)?;
+            }
+            if let Some(ev) = events_out.as_mut() {
+                for record in events.records()[num_events..].iter() {
+                    write!(*ev, "{} ", record.name)?;
+                    write_state(ev, &n_body.to_output_units(&record.state))?;
+                }
+            }
+            num_events = events.records().len();
+            let terminated = events.terminated_at().is_some_and(|t| y.time >= t);
+            if num_output as f64 * run.output_interval >= length || terminated {
+                break;
+            }
+            num_output += 1;
+        }
+    }
+    eprintln!(
+        "{}: {}, {} events",
+        run.integrator,
+        sol.statistics(),
+        num_events
+    );
+    if run.reversibility.is_some() {
+        let n = DIM * n_body.num_of_points();
+        let diff = &z - &x0;
+        let distance = |v: &[f64]| v.iter().map(|d| d * d).sum::<f64>().sqrt();
+        eprintln!(
+            "round trip: |x - x0| = {:.3e} (positions {:.3e}, velocities {:.3e})",
+            diff.norm(),
+            distance(&diff.vec[..n]),
+            distance(&diff.vec[n..2 * n])
+        );
+    }
+    Ok(z)
+}
+
+#[cfg(test)]
+pub mod simulation_tests {
+    extern crate approx;
+    use crate::num_core::events::EventAction;
+    use crate::num_core::io_config::{self, Reversibility, Run, StepControl};
+    use crate::num_core::n_body::NBody;
+    use crate::num_core::simulation::{prepare, simulate};
+    use crate::num_core::step_control::Tolerance;
+
+    const SETTING: &str = "
+SettingName: \"binary\"
+NumberOfBodies: 2
+Mass: [0.5, 0.5]
+Position: [[-0.5, 0.0, 0.0], [0.5, 0.0, 0.0]]
+Velocity: [[0.0, -0.5, 0.0], [0.0, 0.5, 0.0]]
+Run:
+  InitialDt: 1.0e-3
+  MaxDt: 1.0e-2
+  AbsTol: 1.0e-13
+  EndTime: 6.283185307179586
+  OutputInterval: 1.5707963267948966
+";
+
+    #[test]
+    fn run_section() {
+        let run = io_config::read_run(SETTING).unwrap().unwrap();
+        assert_eq!(run.integrator, "DOP54");
+        approx::assert_abs_diff_eq!(run.max_dt, 1.0e-2, epsilon = 1.0e-18);
+        approx::assert_abs_diff_eq!(run.scale_up_factor, 1.01, epsilon = 1.0e-15);
+        let yaml = format!("{}  Ias15Epsilon: 1.0e-6\n  HermiteEta: 0.01\n", SETTING);
+        let own = io_config::read_run(&yaml).unwrap().unwrap();
+        approx::assert_abs_diff_eq!(own.ias15_epsilon, 1.0e-6, epsilon = 1.0e-18);
+        approx::assert_abs_diff_eq!(own.hermite_eta, 0.01, epsilon = 1.0e-18);
+        assert!(io_config::read_run("SettingName: \"none\"")
+            .unwrap()
+            .is_none());
+
+        let mut n_body: NBody = serde_yaml::from_str(SETTING).unwrap();
+        let mut run = run;
+        run.pn_order = Some(2.5);
+        assert_eq!(
+            prepare(&mut n_body, &run),
+            Err("SpeedOfLight is required for post-Newtonian terms")
+        );
+        n_body.speed_of_light = Some(10.0);
+        assert!(prepare(&mut n_body, &run).is_ok());
+        assert!(n_body.post_newtonian.pn25 && !n_body.post_newtonian.pn3);
+        run.pn_order = Some(1.5);
+        assert!(prepare(&mut n_body, &run).is_err());
+        run.pn_order = None;
+        run.max_dt = 1.0e-4;
+        assert_eq!(prepare(&mut n_body, &run), Err("MaxDt < InitialDt"));
+        run.max_dt = 1.0e-2;
+        run.ias15_epsilon = 0.0;
+        assert_eq!(prepare(&mut n_body, &run), Err("Ias15Epsilon <= 0"));
+        run.ias15_epsilon = 1.0e-9;
+        run.hermite_eta = -0.02;
+        assert_eq!(prepare(&mut n_body, &run), Err("HermiteEta <= 0"));
+        let run = Run {
+            integrator: "Euler".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(prepare(&mut n_body, &run), Err("unknown Integrator"));
+    }
+
+    #[test]
+    fn select_integrator() {
+        let mut n_body: NBody = serde_yaml::from_str(SETTING).unwrap();
+        let mut run = io_config::read_run(SETTING).unwrap().unwrap();
+        run.integrator = "RKF78".to_string();
+        prepare(&mut n_body, &run).unwrap();
+        let x = simulate(&n_body, &run, &mut Vec::new(), None, None).unwrap();
+        approx::assert_abs_diff_eq!(x.vec[0], -0.5, epsilon = 1.0e-10);
+        approx::assert_abs_diff_eq!(x.vec[1], 0.0, epsilon = 1.0e-10);
+
+        // symplectic, with the fixed step InitialDt, IAS15 and Hermite
+        for (name, epsilon) in [
+            ("Leapfrog", 1.0e-5),
+            ("Yoshida6", 1.0e-10),
+            ("IAS15", 1.0e-10),
+            ("Hermite", 1.0e-6),
+            ("WisdomHolman", 1.0e-10),
+        ]
+        .iter()
+        {
+            run.integrator = name.to_string();
+            prepare(&mut n_body, &run).unwrap();
+            let x = simulate(&n_body, &run, &mut Vec::new(), None, None).unwrap();
+            approx::assert_abs_diff_eq!(x.time, run.end_time, epsilon = 1.0e-15);
+            approx::assert_abs_diff_eq!(x.vec[0], -0.5, epsilon = *epsilon);
+            approx::assert_abs_diff_eq!(x.vec[1], 0.0, epsilon = *epsilon);
+        }
+        run.pn_order = Some(1.0);
+        n_body.speed_of_light = Some(10.0);
+        assert_eq!(
+            prepare(&mut n_body, &run),
+            Err("WisdomHolman is Newtonian only")
+        );
+        for name in ["Leapfrog", "ForestRuth", "Yoshida6"].iter() {
+            run.integrator = name.to_string();
+            assert_eq!(
+                prepare(&mut n_body, &run),
+                Err("Leapfrog, ForestRuth and Yoshida6 are Newtonian only, use AuxiliaryLeapfrog")
+            );
+        }
+        run.integrator = "AuxiliaryLeapfrog".to_string();
+        assert!(prepare(&mut n_body, &run).is_ok());
+    }
+
+    #[test]
+    fn reversibility() {
+        let mut n_body: NBody = serde_yaml::from_str(SETTING).unwrap();
+        let mut run = io_config::read_run(SETTING).unwrap().unwrap();
+        let x0 = n_body.to_smp_vector();
+        for name in [
+            "DOP54",
+            "DOP853",
+            "GBS",
+            "GaussLegendre6",
+            "Leapfrog",
+            "Yoshida6",
+            "IAS15",
+            "Hermite",
+            "WisdomHolman",
+        ]
+        .iter()
+        {
+            for mode in [Reversibility::NegativeDt, Reversibility::ReverseVelocities].iter() {
+                run.integrator = name.to_string();
+                run.reversibility = Some(*mode);
+                prepare(&mut n_body, &run).unwrap();
+                let mut out = Vec::new();
+                let x = simulate(&n_body, &run, &mut out, None, None).unwrap();
+                let out = String::from_utf8(out).unwrap();
+                let lines: Vec<&str> = out.lines().collect();
+                // header, the start, 4 outputs there and 4 back
+                assert_eq!(lines.len(), 10);
+                assert!(lines[5].starts_with("6.28318530717959 "));
+                assert_eq!(x.time, 0.0);
+                assert!((&x - &x0).norm() < 1.0e-10);
+            }
+        }
+
+        // backward from the start: half a period back is half a period ahead
+        run.integrator = "DOP54".to_string();
+        run.reversibility = None;
+        run.end_time = -std::f64::consts::PI;
+        prepare(&mut n_body, &run).unwrap();
+        let mut out = Vec::new();
+        let x = simulate(&n_body, &run, &mut out, None, None).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.lines().count(), 4);
+        assert_eq!(x.time, -std::f64::consts::PI);
+        approx::assert_abs_diff_eq!(x.vec[0], 0.5, epsilon = 1.0e-9);
+        approx::assert_abs_diff_eq!(x.vec[1], 0.0, epsilon = 1.0e-9);
+        approx::assert_abs_diff_eq!(x.vec[7], 0.5, epsilon = 1.0e-9);
+    }
+
+    #[test]
+    fn regularization() {
+        let mut n_body: NBody = serde_yaml::from_str(SETTING).unwrap();
+        let mut run = io_config::read_run(SETTING).unwrap().unwrap();
+        run.regularization_distance = Some(0.0);
+        assert_eq!(
+            prepare(&mut n_body, &run),
+            Err("RegularizationDistance <= 0")
+        );
+        run.regularization_distance = Some(2.0);
+        run.integrator = "Leapfrog".to_string();
+        assert_eq!(
+            prepare(&mut n_body, &run),
+            Err("KS regularization needs an Integrator of the Runge-Kutta or GBS family")
+        );
+        run.integrator = "DOP54".to_string();
+        run.dense_output = true;
+        assert_eq!(
+            prepare(&mut n_body, &run),
+            Err("KS regularization has no DenseOutput")
+        );
+        run.dense_output = false;
+
+        // the pair stays within the distance, the whole orbit is regularized
+        for name in ["DOP54", "GBS", "GaussLegendre6"].iter() {
+            run.integrator = name.to_string();
+            prepare(&mut n_body, &run).unwrap();
+            let mut out = Vec::new();
+            let x = simulate(&n_body, &run, &mut out, None, None).unwrap();
+            let out = String::from_utf8(out).unwrap();
+            assert_eq!(out.lines().count(), 6);
+            approx::assert_abs_diff_eq!(x.time, run.end_time, epsilon = 1.0e-12);
+            approx::assert_abs_diff_eq!(x.vec[0], -0.5, epsilon = 1.0e-8);
+            approx::assert_abs_diff_eq!(x.vec[1], 0.0, epsilon = 1.0e-8);
+        }
+    }
+
+    #[test]
+    fn step_control() {
+        let mut n_body: NBody = serde_yaml::from_str(SETTING).unwrap();
+        let mut run = io_config::read_run(SETTING).unwrap().unwrap();
+        assert_eq!(run.step_control, StepControl::PI);
+        assert_eq!(run.rel_tol, Tolerance::Scalar(0.0));
+
+        // positions and velocities of both bodies
+        run.abs_tol = Tolerance::PerComponent(vec![1.0e-12; 6]);
+        assert_eq!(
+            prepare(&mut n_body, &run),
+            Err("AbsTol needs one value per state component")
+        );
+        run.abs_tol = Tolerance::PerComponent(vec![1.0e-12; 12]);
+        run.step_control = StepControl::ScaleFactor;
+        assert_eq!(
+            prepare(&mut n_body, &run),
+            Err("StepControl ScaleFactor needs a scalar AbsTol")
+        );
+        run.step_control = StepControl::PI;
+        run.abs_tol = Tolerance::Scalar(0.0);
+        assert_eq!(prepare(&mut n_body, &run), Err("AbsTol <= 0"));
+        run.abs_tol = Tolerance::Scalar(1.0e-14);
+        run.rel_tol = Tolerance::Scalar(1.0e-12);
+        prepare(&mut n_body, &run).unwrap();
+        let x = simulate(&n_body, &run, &mut Vec::new(), None, None).unwrap();
+        approx::assert_abs_diff_eq!(x.vec[0], -0.5, epsilon = 1.0e-9);
+        approx::assert_abs_diff_eq!(x.vec[1], 0.0, epsilon = 1.0e-9);
+
+        // the former fixed scale factors
+        run.step_control = StepControl::ScaleFactor;
+        run.abs_tol = Tolerance::Scalar(1.0e-13);
+        prepare(&mut n_body, &run).unwrap();
+        let x = simulate(&n_body, &run, &mut Vec::new(), None, None).unwrap();
+        approx::assert_abs_diff_eq!(x.vec[0], -0.5, epsilon = 1.0e-9);
+    }
+
+    #[test]
+    fn dense_output() {
+        let mut n_body: NBody = serde_yaml::from_str(SETTING).unwrap();
+        let mut run = io_config::read_run(SETTING).unwrap().unwrap();
+        run.dense_output = true;
+        run.integrator = "RK4".to_string();
+        assert_eq!(
+            prepare(&mut n_body, &run),
+            Err("DenseOutput is only available for DOP54 and DOP853")
+        );
+        for name in ["DOP54", "DOP853"].iter() {
+            run.integrator = name.to_string();
+            run.max_dt = 1.0;
+            prepare(&mut n_body, &run).unwrap();
+            let mut out = Vec::new();
+            let x = simulate(&n_body, &run, &mut out, None, None).unwrap();
+            let out = String::from_utf8(out).unwrap();
+            assert_eq!(out.lines().count(), 6);
+            approx::assert_abs_diff_eq!(x.time, run.end_time);
+            approx::assert_abs_diff_eq!(x.vec[0], -0.5, epsilon = 1.0e-9);
+            approx::assert_abs_diff_eq!(x.vec[1], 0.0, epsilon = 1.0e-9);
+        }
+    }
+
+    #[test]
+    fn one_orbit() {
+        let mut n_body: NBody = serde_yaml::from_str(SETTING).unwrap();
+        let run = io_config::read_run(SETTING).unwrap().unwrap();
+        prepare(&mut n_body, &run).unwrap();
+
+        let mut out = Vec::new();
+        let x = simulate(&n_body, &run, &mut out, None, None).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        // header + t = 0, pi/2, pi, 3pi/2, 2pi
+        assert_eq!(lines.len(), 6);
+        assert!(lines[0].starts_with("# time x_body_0 y_body_0 z_body_0"));
+        // a quarter period: the first body is at (0, -0.5, 0)
+        let quarter: Vec<f64> = lines[2]
+            .split_whitespace()
+            .map(|v| v.parse().unwrap())
+            .collect();
+        approx::assert_abs_diff_eq!(quarter[0], 0.5 * std::f64::consts::PI, epsilon = 1.0e-14);
+        approx::assert_abs_diff_eq!(quarter[1], 0.0, epsilon = 1.0e-9);
+        approx::assert_abs_diff_eq!(quarter[2], -0.5, epsilon = 1.0e-9);
+
+        approx::assert_abs_diff_eq!(x.time, run.end_time, epsilon = 1.0e-15);
+        approx::assert_abs_diff_eq!(x.vec[0], -0.5, epsilon = 1.0e-9);
+        approx::assert_abs_diff_eq!(x.vec[1], 0.0, epsilon = 1.0e-9);
+    }
+
+    #[test]
+    fn diagnostics_stream() {
+        let mut n_body: NBody = serde_yaml::from_str(SETTING).unwrap();
+        let run = io_config::read_run(SETTING).unwrap().unwrap();
+        prepare(&mut n_body, &run).unwrap();
+
+        let mut out = Vec::new();
+        let mut diag = Vec::new();
+        simulate(&n_body, &run, &mut out, Some(&mut diag), None).unwrap();
+        let diag = String::from_utf8(diag).unwrap();
+        let lines: Vec<&str> = diag.lines().collect();
+        assert_eq!(lines.len(), 6);
+        assert!(lines[0].starts_with("# time energy_newton"));
+        let last: Vec<f64> = lines[5]
+            .split_whitespace()
+            .map(|v| v.parse().unwrap())
+            .collect();
+        // E = -m_1 m_2 / (2 a) = -0.125
+        approx::assert_abs_diff_eq!(last[1], -0.125, epsilon = 1.0e-10);
+        assert!(last[3] < 1.0e-9);
+    }
+
+    #[test]
+    fn events_stream() {
+        // the relative orbit is reflected at x = 0 and retraces itself: back at
+        // the start at t = pi and 2 pi
+        let setting = SETTING.replace(
+            "Run:\n",
+            "Mode: TwoBody
+Run:
+  DenseOutput: true
+  Events:
+    - Kind: PlaneCrossing
+      Name: wall
+      Bodies: [0]
+      Axis: 0
+      Direction: Rising
+      Action: {ScaleVelocity: -1.0}
+",
+        );
+        let mut n_body: NBody = serde_yaml::from_str(&setting).unwrap();
+        let mut run = io_config::read_run(&setting).unwrap().unwrap();
+        prepare(&mut n_body, &run).unwrap();
+
+        let mut out = Vec::new();
+        let mut events = Vec::new();
+        let x = simulate(&n_body, &run, &mut out, None, Some(&mut events)).unwrap();
+        approx::assert_abs_diff_eq!(x.time, run.end_time);
+        approx::assert_abs_diff_eq!(x.vec[0], -1.0, epsilon = 1.0e-9);
+        approx::assert_abs_diff_eq!(x.vec[4], -1.0, epsilon = 1.0e-9);
+        let events = String::from_utf8(events).unwrap();
+        let lines: Vec<&str> = events.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("# event time"));
+        for (k, line) in lines[1..].iter().enumerate() {
+            let values: Vec<&str> = line.split_whitespace().collect();
+            assert_eq!(values[0], "wall");
+            let t: f64 = values[1].parse().unwrap();
+            approx::assert_abs_diff_eq!(
+                t,
+                (k as f64 + 0.5) * std::f64::consts::PI,
+                epsilon = 1.0e-9
+            );
+        }
+
+        // stop at the first crossing
+        run.events[0].action = EventAction::Terminate;
+        let x = simulate(&n_body, &run, &mut Vec::new(), None, None).unwrap();
+        approx::assert_abs_diff_eq!(x.time, 0.5 * std::f64::consts::PI, epsilon = 1.0e-9);
+        approx::assert_abs_diff_eq!(x.vec[1], -1.0, epsilon = 1.0e-9);
+
+        run.reversibility = Some(Reversibility::NegativeDt);
+        assert_eq!(prepare(&mut n_body, &run), Err("Events need forward time"));
+        run.reversibility = None;
+        run.dense_output = false;
+        assert_eq!(prepare(&mut n_body, &run), Err("Events need DenseOutput"));
+        run.dense_output = true;
+        run.events[0].bodies = vec![1];
+        assert_eq!(
+            prepare(&mut n_body, &run),
+            Err("Bodies of the event out of range")
+        );
+    }
+}
@@ -0,0 +1,287 @@
+//
+// Error norm and step-size controller of the embedded Runge–Kutta methods.
+//
+// ref. Hairer, Nørsett, Wanner, Solving ordinary differential equations I (1993),
+// Sec. II.4 (scaled norm) and IV.2 / code DOPRI5 (PI controller, Gustafsson 1991).
+//
+use crate::num_core::smp_vector::SmpVector;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// Tolerance for the whole state or for every component (YAML: a number or a list).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Tolerance {
+    Scalar(f64),
+    PerComponent(Vec<f64>),
+}
+
+impl Tolerance {
+    #[inline(always)]
+    pub fn get(&self, i: usize) -> f64 {
+        match self {
+            Tolerance::Scalar(tol) => *tol,
+            Tolerance::PerComponent(tol) => tol[i],
+        }
+    }
+
+    // smallest value over the components
+    pub fn min(&self) -> f64 {
+        match self {
+            Tolerance::Scalar(tol) => *tol,
+            Tolerance::PerComponent(tol) => tol.iter().cloned().fold(f64::INFINITY, f64::min),
+        }
+    }
+
+    // a per-component tolerance must cover the state vector exactly
+    pub fn fits(&self, len: usize) -> bool {
+        match self {
+            Tolerance::Scalar(_) => true,
+            Tolerance::PerComponent(tol) => tol.len() == len,
+        }
+    }
+}
+
+// Scaled RMS norm sqrt(1/n sum_i (err_i / sc_i)^2) with
+// sc_i = atol_i + rtol_i max(|x0_i|, |x1_i|); a step is acceptable if it is <= 1.
+// atol_i > 0 keeps sc_i > 0 for components that stay at zero.
+pub fn error_norm(
+    err: &SmpVector,
+    x0: &SmpVector,
+    x1: &SmpVector,
+    atol: &Tolerance,
+    rtol: &Tolerance,
+) -> f64 {
+    let n = err.vec.len();
+    let mut sum = 0.0;
+    for i in 0..n {
+        let sc = atol.get(i) + rtol.get(i) * x0.vec[i].abs().max(x1.vec[i].abs());
+        let e = err.vec[i] / sc;
+        sum += e * e;
+    }
+    (sum / n as f64).sqrt()
+}
+
+// PI step-size controller
+//   dt_new = dt / fac, fac = err^(1/k - 0.75 beta) / err_old^beta / safety
+// clamped to dt_new / dt in [min_factor, max_factor], where k is one plus the
+// order of the error estimate and err_old the error of the last accepted step.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PIController {
+    pub atol: Tolerance,
+    pub rtol: Tolerance,
+    pub safety: f64,
+    pub min_factor: f64,
+    pub max_factor: f64,
+    // beta = 0 gives the classical I controller
+    pub beta: f64,
+    err_old: f64,
+    last_rejected: bool,
+}
+
+impl PIController {
+    // with the defaults of DOPRI5
+    pub fn new(atol: Tolerance, rtol: Tolerance) -> PIController {
+        PIController {
+            atol,
+            rtol,
+            safety: 0.9,
+            min_factor: 0.2,
+            max_factor: 10.0,
+            beta: 0.04,
+            err_old: 1.0e-4,
+            last_rejected: false,
+        }
+    }
+
+    // (accepted, proposed dt) after a step dt with the scaled error err
+    pub fn propose(&mut self, dt: f64, err: f64, k: f64) -> (bool, f64) {
+        let fac11 = err.powf(1.0 / k - 0.75 * self.beta);
+        if err <= 1.0 {
+            let fac = fac11 / self.err_old.powf(self.beta) / self.safety;
+            let fac = fac.max(1.0 / self.max_factor).min(1.0 / self.min_factor);
+            let mut dt_new = dt / fac;
+            // no growth right after a rejection
+            if self.last_rejected && dt_new.abs() > dt.abs() {
+                dt_new = dt;
+            }
+            self.err_old = err.max(1.0e-4);
+            self.last_rejected = false;
+            (true, dt_new)
+        } else {
+            self.last_rejected = true;
+            (false, dt / (fac11 / self.safety).min(1.0 / self.min_factor))
+        }
+    }
+}
+
+// dt with its size capped at dt_max, in either direction of time
+pub fn limit(dt: f64, dt_max: f64) -> f64 {
+    if dt.abs() > dt_max {
+        dt_max.copysign(dt)
+    } else {
+        dt
+    }
+}
+
+// Outcome of one adaptive step.
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct StepResult {
+    // the new state, or the old one when the step is rejected
+    pub x: SmpVector,
+    pub accepted: bool,
+    // the error norm of the step (scaled with a PIController, against abs_tol otherwise)
+    pub error: f64,
+    pub dt_used: f64,
+    // dt of the next step (the retry of a rejected one)
+    pub dt_proposed: f64,
+}
+
+// Counters of the adaptive steps of an integrator (Integrator::statistics).
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepStatistics {
+    pub accepted: i64,
+    pub rejected: i64,
+    // evaluations of the right-hand side
+    pub evaluations: i64,
+    // smallest and largest |dt| of the accepted steps
+    pub min_dt: f64,
+    pub max_dt: f64,
+}
+
+impl StepStatistics {
+    pub fn new() -> StepStatistics {
+        StepStatistics {
+            accepted: 0,
+            rejected: 0,
+            evaluations: 0,
+            min_dt: f64::INFINITY,
+            max_dt: 0.0,
+        }
+    }
+
+    pub fn record(&mut self, accepted: bool, dt: f64) {
+        if accepted {
+            self.accepted += 1;
+            self.min_dt = self.min_dt.min(dt.abs());
+            self.max_dt = self.max_dt.max(dt.abs());
+        } else {
+            self.rejected += 1;
+        }
+    }
+}
+
+impl Default for StepStatistics {
+    fn default() -> Self {
+        StepStatistics::new()
+    }
+}
+
+impl fmt::Display for StepStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} steps ({} rejected), {} RHS evaluations, dt in [{:.3e}, {:.3e}]",
+            self.accepted + self.rejected,
+            self.rejected,
+            self.evaluations,
+            self.min_dt,
+            self.max_dt
+        )
+    }
+}
+
+#[cfg(test)]
+pub mod step_control_tests {
+    extern crate approx;
+    use crate::num_core::smp_vector::SmpVector;
+    use crate::num_core::step_control::{
+        error_norm, limit, PIController, StepStatistics, Tolerance,
+    };
+
+    fn vector(vec: Vec<f64>) -> SmpVector {
+        SmpVector { time: 0.0, vec }
+    }
+
+    #[test]
+    fn scaled_norm() {
+        let x0 = vector(vec![1.0e8, 1.0e-3]);
+        let x1 = vector(vec![-2.0e8, 1.0e-3]);
+        let err = vector(vec![2.0, 1.1e-12]);
+        // sc = (1e-12 + 1e-8 * 2e8, 1e-12 + 1e-8 * 1e-3) = (2, 1.1e-11)
+        let rtol = Tolerance::Scalar(1.0e-8);
+        let norm = error_norm(&err, &x0, &x1, &Tolerance::Scalar(1.0e-12), &rtol);
+        approx::assert_relative_eq!(
+            norm,
+            (0.5 * (1.0 + 0.01_f64)).sqrt(),
+            max_relative = 1.0e-12
+        );
+
+        let atol = Tolerance::PerComponent(vec![1.0, 1.1e-12]);
+        let norm = error_norm(&err, &x0, &x1, &atol, &Tolerance::Scalar(0.0));
+        approx::assert_relative_eq!(norm, 2.5_f64.sqrt(), max_relative = 1.0e-12);
+        assert!(atol.fits(2) && !atol.fits(3) && Tolerance::Scalar(1.0).fits(3));
+        approx::assert_abs_diff_eq!(Tolerance::PerComponent(vec![1.0, -1.0]).min(), -1.0);
+
+        let atol: Tolerance = serde_yaml::from_str("[1.0e-3, 1.0e-6]").unwrap();
+        assert_eq!(atol, Tolerance::PerComponent(vec![1.0e-3, 1.0e-6]));
+        let atol: Tolerance = serde_yaml::from_str("1.0e-3").unwrap();
+        assert_eq!(atol, Tolerance::Scalar(1.0e-3));
+    }
+
+    #[test]
+    fn pi_controller() {
+        let mut pi = PIController::new(Tolerance::Scalar(1.0e-10), Tolerance::Scalar(0.0));
+        // a tiny error: growth limited by max_factor
+        let (accepted, dt) = pi.propose(1.0, 1.0e-20, 5.0);
+        assert!(accepted);
+        approx::assert_relative_eq!(dt, 10.0, max_relative = 1.0e-15);
+        // a huge error: shrink limited by min_factor
+        let (accepted, dt) = pi.propose(1.0, 1.0e20, 5.0);
+        assert!(!accepted);
+        approx::assert_relative_eq!(dt, 0.2, max_relative = 1.0e-15);
+        // no growth right after a rejection
+        let (accepted, dt) = pi.propose(0.2, 1.0e-20, 5.0);
+        assert!(accepted);
+        approx::assert_relative_eq!(dt, 0.2, max_relative = 1.0e-15);
+        // err = 1 is accepted with the safety factor; after the tiny errors
+        // (err_old = 1e-4) the PI term err_old^beta damps the step further
+        let (accepted, dt) = pi.propose(1.0, 1.0, 5.0);
+        assert!(accepted);
+        approx::assert_relative_eq!(dt, 0.9 * 1.0e-4_f64.powf(0.04), max_relative = 1.0e-15);
+        let (_, dt) = pi.propose(1.0, 1.0, 5.0);
+        approx::assert_relative_eq!(dt, 0.9, max_relative = 1.0e-15);
+        // backward in time the same in size, and no growth after a rejection
+        let (accepted, dt) = pi.propose(-1.0, 1.0e20, 5.0);
+        assert!(!accepted);
+        approx::assert_relative_eq!(dt, -0.2, max_relative = 1.0e-15);
+        let (_, dt) = pi.propose(-0.2, 1.0e-20, 5.0);
+        approx::assert_relative_eq!(dt, -0.2, max_relative = 1.0e-15);
+        assert_eq!(limit(-3.0, 2.0), -2.0);
+        assert_eq!(limit(1.0, 2.0), 1.0);
+    }
+
+    #[test]
+    fn statistics() {
+        let mut stats = StepStatistics::new();
+        stats.record(true, 0.1);
+        stats.record(false, 1.0);
+        stats.record(true, 0.4);
+        stats.evaluations = 18;
+        assert_eq!((stats.accepted, stats.rejected), (2, 1));
+        approx::assert_abs_diff_eq!(stats.min_dt, 0.1);
+        approx::assert_abs_diff_eq!(stats.max_dt, 0.4);
+        assert_eq!(
+            stats.to_string(),
+            "3 steps (1 rejected), 18 RHS evaluations, dt in [1.000e-1, 4.000e-1]"
+        );
+
+        // backward steps count by their size
+        let mut stats = StepStatistics::new();
+        stats.record(true, -0.4);
+        stats.record(true, -0.1);
+        approx::assert_abs_diff_eq!(stats.min_dt, 0.1);
+        approx::assert_abs_diff_eq!(stats.max_dt, 0.4);
+    }
+}
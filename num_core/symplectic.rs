@@ -0,0 +1,543 @@
+//
+// Symplectic and time-reversible integrators with a fixed step dt.
+//
+// The state is split into positions q (the first DIM * points components of the
+// NBody state) and the rest p (velocities, then spins): q' = v, p' = f(q, v).
+// Drifts move q with v, kicks move p with f, and their compositions are
+// symmetric in time, so the energy error stays bounded instead of drifting.
+// This holds only if f does not depend on v: a kick evaluates f at the velocity
+// before it, so the velocity-dependent post-Newtonian and spin terms need
+// AuxiliaryLeapfrog.
+//
+//  - Leapfrog: kick-drift-kick, order 2 (Newtonian only).
+//  - ForestRuth, Yoshida6: leapfrog composed with the weights of Forest and
+//    Ruth (order 4) and of Yoshida's solution A (order 6) (Newtonian only).
+//  - WisdomHolman: Kepler drifts of the Jacobi coordinates around the first
+//    body and kicks by the interaction between the others (Newtonian only).
+//  - AuxiliaryLeapfrog: drift-kick-drift with an auxiliary velocity w in the
+//    kick for forces that depend on v (post-Newtonian), order 2:
+//    w += h/2 f(q, v), v += h f(q, w), w += h/2 f(q, v).
+//
+// ref. Yoshida, Phys. Lett. A 150 (1990) 262. Forest and Ruth, Physica D 43
+// (1990) 105. Wisdom and Holman, AJ 102 (1991) 1528. Rein and Tamayo, MNRAS 452
+// (2015) 376. Hellström and Mikkola, Celest. Mech. Dyn. Astr. 106 (2010) 143.
+//
+use crate::num_core::integrator::Integrator;
+use crate::num_core::n_body::{Mode, NBody, DIM};
+use crate::num_core::smp_vector::SmpVector;
+use crate::num_core::step_control::{PIController, StepStatistics};
+
+// names accepted by Symplectic::from_name (YAML: Run: Integrator)
+pub const NAMES: [&str; 5] = [
+    "Leapfrog",
+    "ForestRuth",
+    "Yoshida6",
+    "WisdomHolman",
+    "AuxiliaryLeapfrog",
+];
+
+// Jacobi coordinates of the bodies and the Kepler problems of WisdomHolman.
+// Coordinate 0 is the center of mass, i >= 1 body i relative to the center of
+// mass of the bodies before it.
+pub struct Jacobi {
+    // G m_i
+    mu: Vec<f64>,
+    // G (m_0 + ... + m_i)
+    eta: Vec<f64>,
+}
+
+impl Jacobi {
+    pub fn new(mu: Vec<f64>) -> Jacobi {
+        let eta = mu
+            .iter()
+            .scan(0.0, |sum, m| {
+                *sum += m;
+                Some(*sum)
+            })
+            .collect();
+        Jacobi { mu, eta }
+    }
+
+    // G M_i of the Kepler problem of coordinate i >= 1; with m'_i the reduced
+    // mass, m'_i M_i = m_0 m_i
+    fn kepler_mu(&self, i: usize) -> f64 {
+        self.mu[0] * self.eta[i] / self.eta[i - 1]
+    }
+
+    // positions, velocities or accelerations (DIM per body) to Jacobi ones
+    pub fn jacobi(&self, x: &[f64]) -> Vec<f64> {
+        let mut ret = vec![0.0; x.len()];
+        let mut sum = [0.0; DIM];
+        for (i, mu) in self.mu.iter().enumerate() {
+            for d in 0..DIM {
+                if i > 0 {
+                    ret[DIM * i + d] = x[DIM * i + d] - sum[d] / self.eta[i - 1];
+                }
+                sum[d] += mu * x[DIM * i + d];
+            }
+        }
+        let total = self.eta[self.mu.len() - 1];
+        for d in 0..DIM {
+            ret[d] = sum[d] / total;
+        }
+        ret
+    }
+
+    // and back
+    pub fn inertial(&self, x: &[f64]) -> Vec<f64> {
+        let mut ret = vec![0.0; x.len()];
+        let n = self.mu.len();
+        // sum_{j <= i} mu_j x_j, from the center of mass down to body 0
+        let mut sum = [0.0; DIM];
+        for d in 0..DIM {
+            sum[d] = self.eta[n - 1] * x[d];
+        }
+        for i in (1..n).rev() {
+            for d in 0..DIM {
+                let com = (sum[d] - self.mu[i] * x[DIM * i + d]) / self.eta[i];
+                ret[DIM * i + d] = x[DIM * i + d] + com;
+                sum[d] = self.eta[i - 1] * com;
+            }
+        }
+        for d in 0..DIM {
+            ret[d] = sum[d] / self.mu[0];
+        }
+        ret
+    }
+}
+
+// Stumpff functions c2(z) = (1 - cos sqrt z) / z and c3(z) = (sqrt z - sin sqrt z) / sqrt z^3
+fn stumpff(z: f64) -> (f64, f64) {
+    if z.abs() < 1.0e-2 {
+        let c2 = 0.5 - z / 24.0 + z * z / 720.0 - z * z * z / 40320.0;
+        let c3 = 1.0 / 6.0 - z / 120.0 + z * z / 5040.0 - z * z * z / 362880.0;
+        (c2, c3)
+    } else if z > 0.0 {
+        let s = z.sqrt();
+        ((1.0 - s.cos()) / z, (s - s.sin()) / (s * z))
+    } else {
+        let s = (-z).sqrt();
+        ((s.cosh() - 1.0) / -z, (s.sinh() - s) / (s * -z))
+    }
+}
+
+// Two-body motion r'' = -mu r / |r|^3 over dt with the universal variable and
+// the f and g functions (any eccentricity).
+pub fn kepler_drift(mu: f64, r: &mut [f64], v: &mut [f64], dt: f64) {
+    let dot = |a: &[f64], b: &[f64]| -> f64 { (0..DIM).map(|d| a[d] * b[d]).sum() };
+    let r0 = dot(r, r).sqrt();
+    let rv = dot(r, v);
+    let alpha = 2.0 / r0 - dot(v, v) / mu;
+    let sqrt_mu = mu.sqrt();
+    // universal anomaly chi by Newton's method on Kepler's equation
+    let mut chi = sqrt_mu * alpha.abs() * dt;
+    let mut c2 = 0.5;
+    let mut c3 = 1.0 / 6.0;
+    for _ in 0..100 {
+        let z = alpha * chi * chi;
+        let c = stumpff(z);
+        c2 = c.0;
+        c3 = c.1;
+        let chi2 = chi * chi;
+        let t = rv / sqrt_mu * chi2 * c2 + (1.0 - alpha * r0) * chi2 * chi * c3 + r0 * chi;
+        let r = rv / sqrt_mu * chi * (1.0 - z * c3) + (1.0 - alpha * r0) * chi2 * c2 + r0;
+        let delta = (t - sqrt_mu * dt) / r;
+        chi -= delta;
+        if delta.abs() <= 1.0e-15 * chi.abs() {
+            let c = stumpff(alpha * chi * chi);
+            c2 = c.0;
+            c3 = c.1;
+            break;
+        }
+    }
+    let chi2 = chi * chi;
+    let f = 1.0 - chi2 / r0 * c2;
+    let g = dt - chi2 * chi * c3 / sqrt_mu;
+    let mut r1 = [0.0; DIM];
+    for d in 0..DIM {
+        r1[d] = f * r[d] + g * v[d];
+    }
+    let r1_norm = dot(&r1, &r1).sqrt();
+    let f_dot = sqrt_mu / (r1_norm * r0) * (alpha * chi2 * chi * c3 - chi);
+    let g_dot = 1.0 - chi2 / r1_norm * c2;
+    for d in 0..DIM {
+        let v1 = f_dot * r[d] + g_dot * v[d];
+        r[d] = r1[d];
+        v[d] = v1;
+    }
+}
+
+pub enum Base {
+    Leapfrog,
+    WisdomHolman(Jacobi),
+    Auxiliary,
+}
+
+pub struct Symplectic {
+    base: Base,
+    // the base step of h is composed of steps of weights[i] h
+    weights: Vec<f64>,
+    // number of position components at the start of the state
+    positions: usize,
+    dt: f64,
+    stats: StepStatistics,
+    // AuxiliaryLeapfrog: w (the rest of the state) and the state it belongs to
+    aux: Vec<f64>,
+    x_aux: SmpVector,
+}
+
+impl Symplectic {
+    pub fn new(base: Base, weights: Vec<f64>, positions: usize, dt: f64) -> Symplectic {
+        Symplectic {
+            base,
+            weights,
+            positions,
+            dt,
+            stats: StepStatistics::new(),
+            aux: Vec::new(),
+            x_aux: SmpVector {
+                time: f64::NAN,
+                vec: Vec::new(),
+            },
+        }
+    }
+
+    // One of NAMES for the state of n_body, with the step dt.
+    pub fn from_name(name: &str, n_body: &NBody, dt: f64) -> Result<Symplectic, &'static str> {
+        check(name, n_body)?;
+        let positions = DIM * n_body.num_of_points();
+        let (base, weights) = match name {
+            "Leapfrog" => (Base::Leapfrog, vec![1.0]),
+            "ForestRuth" => {
+                let w1 = 1.0 / (2.0 - 2.0_f64.cbrt());
+                (Base::Leapfrog, vec![w1, 1.0 - 2.0 * w1, w1])
+            }
+            "Yoshida6" => {
+                let w = [-1.17767998417887, 0.235573213359357, 0.784513610477560];
+                let w0 = 1.0 - 2.0 * (w[0] + w[1] + w[2]);
+                (Base::Leapfrog, vec![w[2], w[1], w[0], w0, w[0], w[1], w[2]])
+            }
+            "WisdomHolman" => (
+                Base::WisdomHolman(Jacobi::new(n_body.gravitational_mass())),
+                vec![1.0],
+            ),
+            "AuxiliaryLeapfrog" => (Base::Auxiliary, vec![1.0]),
+            _ => return Err("unknown Integrator"),
+        };
+        Ok(Symplectic::new(base, weights, positions, dt))
+    }
+
+    // p += c f(q, v)
+    fn kick(&self, func: &dyn Fn(&SmpVector) -> SmpVector, x: &mut SmpVector, c: f64) {
+        let n = self.positions;
+        let f = func(x);
+        let mut acc = f.vec[n..].to_vec();
+        if let Base::WisdomHolman(jacobi) = &self.base {
+            // only the interaction: the Kepler accelerations -G M_i x'_i / r'_i^3
+            // of the Jacobi coordinates are taken out, they belong to the drift
+            let q = jacobi.jacobi(&x.vec[..n]);
+            let mut a = jacobi.jacobi(&acc[..n]);
+            for i in 1..n / DIM {
+                let r = &q[DIM * i..DIM * (i + 1)];
+                let r2: f64 = r.iter().map(|v| v * v).sum();
+                let mu = jacobi.kepler_mu(i) / (r2 * r2.sqrt());
+                for d in 0..DIM {
+                    a[DIM * i + d] += mu * r[d];
+                }
+            }
+            acc[..n].copy_from_slice(&jacobi.inertial(&a));
+        }
+        for (pk, ak) in x.vec[n..].iter_mut().zip(acc.iter()) {
+            *pk += c * ak;
+        }
+    }
+
+    // q += c v, or the Kepler motion of the Jacobi coordinates over c
+    fn drift(&self, x: &mut SmpVector, c: f64) {
+        let n = self.positions;
+        match &self.base {
+            Base::WisdomHolman(jacobi) => {
+                let mut q = jacobi.jacobi(&x.vec[..n]);
+                let mut v = jacobi.jacobi(&x.vec[n..2 * n]);
+                for d in 0..DIM {
+                    q[d] += c * v[d];
+                }
+                for i in 1..n / DIM {
+                    let range = DIM * i..DIM * (i + 1);
+                    kepler_drift(jacobi.kepler_mu(i), &mut q[range.clone()], &mut v[range], c);
+                }
+                x.vec[..n].copy_from_slice(&jacobi.inertial(&q));
+                x.vec[n..2 * n].copy_from_slice(&jacobi.inertial(&v));
+            }
+            _ => {
+                let (q, p) = x.vec.split_at_mut(n);
+                for (qk, vk) in q.iter_mut().zip(p.iter()) {
+                    *qk += c * vk;
+                }
+            }
+        }
+    }
+
+    // w += h/2 f(q, v), v += h f(q, w), w += h/2 f(q, v)
+    fn auxiliary_kick(
+        &self,
+        func: &dyn Fn(&SmpVector) -> SmpVector,
+        x: &mut SmpVector,
+        aux: &mut [f64],
+        h: f64,
+    ) {
+        let n = self.positions;
+        let half_kick = |x: &SmpVector, aux: &mut [f64]| {
+            let f = func(x);
+            for (wk, fk) in aux.iter_mut().zip(f.vec[n..].iter()) {
+                *wk += 0.5 * h * fk;
+            }
+        };
+        half_kick(x, aux);
+        let mut xw = x.clone();
+        xw.vec[n..].copy_from_slice(aux);
+        let f = func(&xw);
+        for (pk, fk) in x.vec[n..].iter_mut().zip(f.vec[n..].iter()) {
+            *pk += h * fk;
+        }
+        half_kick(x, aux);
+    }
+
+    // One step of dt from x; aux is w of AuxiliaryLeapfrog. Returns the new
+    // state and the number of right-hand side evaluations.
+    fn advance(
+        &self,
+        func: &dyn Fn(&SmpVector) -> SmpVector,
+        x: &SmpVector,
+        aux: &mut [f64],
+    ) -> (SmpVector, i64) {
+        let h = self.dt;
+        let w = &self.weights;
+        let mut y = x.clone();
+        let mut evaluations = 0;
+        match self.base {
+            Base::Auxiliary => {
+                // drift-kick-drift, the drifts between two kicks merged
+                self.drift(&mut y, 0.5 * w[0] * h);
+                for (i, wi) in w.iter().enumerate() {
+                    self.auxiliary_kick(func, &mut y, aux, wi * h);
+                    let next = w.get(i + 1).unwrap_or(&0.0);
+                    self.drift(&mut y, 0.5 * (wi + next) * h);
+                    evaluations += 3;
+                }
+            }
+            _ => {
+                // kick-drift-kick, the kicks between two drifts merged
+                self.kick(func, &mut y, 0.5 * w[0] * h);
+                for (i, wi) in w.iter().enumerate() {
+                    self.drift(&mut y, wi * h);
+                    let next = w.get(i + 1).unwrap_or(&0.0);
+                    self.kick(func, &mut y, 0.5 * (wi + next) * h);
+                }
+                evaluations += w.len() as i64 + 1;
+            }
+        }
+        y.time = h + x.time;
+        (y, evaluations)
+    }
+}
+
+// Whether the integrator name suits the bodies.
+pub fn check(name: &str, n_body: &NBody) -> Result<(), &'static str> {
+    if ["Leapfrog", "ForestRuth", "Yoshida6"].contains(&name) && n_body.post_newtonian.is_enabled()
+    {
+        return Err("Leapfrog, ForestRuth and Yoshida6 are Newtonian only, use AuxiliaryLeapfrog");
+    }
+    if name == "WisdomHolman" {
+        if n_body.mode != Mode::NBody {
+            return Err("WisdomHolman needs Mode: NBody");
+        }
+        if n_body.post_newtonian.is_enabled() {
+            return Err("WisdomHolman is Newtonian only");
+        }
+    }
+    Ok(())
+}
+
+impl Integrator for Symplectic {
+    // AuxiliaryLeapfrog starts with w = v
+    fn step(&self, func: &dyn Fn(&SmpVector) -> SmpVector, x: &SmpVector) -> SmpVector {
+        let mut aux = x.vec[self.positions..].to_vec();
+        self.advance(func, x, &mut aux).0
+    }
+
+    // every step is accepted; w of AuxiliaryLeapfrog is carried over while the
+    // steps continue from the last one
+    fn adaptive_step(
+        &mut self,
+        func: &dyn Fn(&SmpVector) -> SmpVector,
+        x: &SmpVector,
+    ) -> SmpVector {
+        if !(self.x_aux.time == x.time && self.x_aux.vec == x.vec) {
+            self.aux = x.vec[self.positions..].to_vec();
+        }
+        let mut aux = std::mem::take(&mut self.aux);
+        let (y, evaluations) = self.advance(func, x, &mut aux);
+        self.aux = aux;
+        self.x_aux.clone_from(&y);
+        self.stats.record(true, self.dt);
+        self.stats.evaluations += evaluations;
+        y
+    }
+
+    fn set_delta_t(&mut self, dt: f64) {
+        self.dt = dt;
+    }
+
+    fn get_delta_t(&self) -> f64 {
+        self.dt
+    }
+
+    fn get_num_of_steps(&self) -> i64 {
+        self.stats.accepted
+    }
+
+    fn get_num_of_dt_switch(&self) -> i64 {
+        0
+    }
+
+    // fixed step: no error control
+    fn set_controller(&mut self, _controller: PIController) {}
+
+    fn statistics(&self) -> &StepStatistics {
+        &self.stats
+    }
+}
+
+#[cfg(test)]
+pub mod symplectic_tests {
+    extern crate approx;
+    use crate::num_core::integrator::Integrator;
+    use crate::num_core::n_body::NBody;
+    use crate::num_core::smp_vector::SmpVector;
+    use crate::num_core::symplectic::{self, kepler_drift, Base, Jacobi, Symplectic};
+    use crate::num_core::test_problems;
+    use std::f64::consts::PI;
+
+    // Sun with two Jupiter-mass planets at a = 1 and 2 (circular)
+    fn planets() -> NBody {
+        let v2 = (1.001_f64 / 2.0).sqrt();
+        let yaml = format!(
+            "
+SettingName: \"planets\"
+NumberOfBodies: 3
+Mass: [1.0, 1.0e-3, 1.0e-3]
+Position: [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 2.0, 0.0]]
+Velocity: [[0.0, 0.0, 0.0], [0.0, {}, 0.0], [{}, 0.0, 0.0]]
+",
+            1.001_f64.sqrt(),
+            -v2
+        );
+        serde_yaml::from_str(&yaml).unwrap()
+    }
+
+    #[test]
+    fn kepler() {
+        // a full period of the eccentric orbit, and the apocenter at half of it
+        let (mut r, mut v) = ([-0.4, 0.0, 0.0], [0.0, -2.0, 0.0]);
+        kepler_drift(1.0, &mut r, &mut v, PI);
+        approx::assert_abs_diff_eq!(r[0], 1.6, epsilon = 1.0e-13);
+        approx::assert_abs_diff_eq!(v[1], 0.5, epsilon = 1.0e-13);
+        kepler_drift(1.0, &mut r, &mut v, PI);
+        approx::assert_abs_diff_eq!(r[0], -0.4, epsilon = 1.0e-13);
+        approx::assert_abs_diff_eq!(r[1], 0.0, epsilon = 1.0e-13);
+        approx::assert_abs_diff_eq!(v[1], -2.0, epsilon = 1.0e-13);
+        // hyperbolic orbit: energy and angular momentum are kept
+        let (mut r, mut v) = ([1.0, 0.0, 0.0], [0.0, 3.0, 0.0]);
+        kepler_drift(1.0, &mut r, &mut v, 2.0);
+        let r1 = (r[0] * r[0] + r[1] * r[1]).sqrt();
+        approx::assert_abs_diff_eq!(
+            0.5 * (v[0] * v[0] + v[1] * v[1]) - 1.0 / r1,
+            3.5,
+            epsilon = 1.0e-12
+        );
+        approx::assert_abs_diff_eq!(r[0] * v[1] - r[1] * v[0], 3.0, epsilon = 1.0e-12);
+
+        let jacobi = Jacobi::new(vec![1.0, 2.0, 3.0]);
+        let x = vec![1.0, 2.0, 3.0, -1.0, 0.5, 2.0, 0.0, 1.0, -3.0];
+        let y = jacobi.inertial(&jacobi.jacobi(&x));
+        for (a, b) in x.iter().zip(y.iter()) {
+            approx::assert_abs_diff_eq!(a, b, epsilon = 1.0e-14);
+        }
+    }
+
+    #[test]
+    fn order() {
+        let n_body = test_problems::kepler();
+        let x = n_body.to_smp_vector();
+        let (mut r, mut v) = ([-0.4, 0.0, 0.0], [0.0, -2.0, 0.0]);
+        kepler_drift(1.0, &mut r, &mut v, 1.0);
+        let error = |name: &str, dt: f64| {
+            let mut sol = Symplectic::from_name(name, &n_body, dt).unwrap();
+            let y = sol.integrate_to(1.0, &n_body.eom(), &x);
+            // relative coordinate
+            (y.vec[0] - y.vec[3] - r[0]).abs() + (y.vec[1] - y.vec[4] - r[1]).abs()
+        };
+        for (name, order) in [("Leapfrog", 2.0), ("ForestRuth", 4.0), ("Yoshida6", 6.0)].iter() {
+            let ratio = error(name, 0.02) / error(name, 0.01);
+            approx::assert_relative_eq!(ratio.log2(), order, max_relative = 0.1);
+        }
+        // WisdomHolman is exact for two bodies
+        assert!(error("WisdomHolman", 0.25) < 1.0e-12);
+    }
+
+    #[test]
+    fn energy_error() {
+        // 20 inner orbits with 50 steps per orbit
+        let n_body = planets();
+        let x = n_body.to_smp_vector();
+        let func = n_body.eom();
+        let e0 = n_body.newton_energy(&x);
+        let max_error = |name: &str| {
+            let mut sol = Symplectic::from_name(name, &n_body, 2.0 * PI / 50.0).unwrap();
+            let mut y = x.clone();
+            let mut max: f64 = 0.0;
+            for _ in 0..1000 {
+                y = sol.adaptive_step(&func, &y);
+                max = max.max(((n_body.newton_energy(&y) - e0) / e0).abs());
+            }
+            assert_eq!(sol.statistics().accepted, 1000);
+            max
+        };
+        let leapfrog = max_error("Leapfrog");
+        let wisdom_holman = max_error("WisdomHolman");
+        assert!(leapfrog < 1.0e-2);
+        // the Kepler motion is exact: only the interaction of the planets is left
+        assert!(wisdom_holman < 5.0e-2 * leapfrog);
+        assert!(max_error("Yoshida6") < 1.0e-3 * leapfrog);
+    }
+
+    #[test]
+    fn auxiliary_velocity() {
+        // no drag: circles of radius |v| / b = 1 around the origin
+        let lorentz = test_problems::lorentz(1.0, 0.0);
+        let x = SmpVector {
+            time: 0.0,
+            vec: vec![1.0, 0.0, 0.0, 0.0, -1.0, 0.0],
+        };
+        let run = |dt: f64, end_time: f64| {
+            let mut sol = Symplectic::new(Base::Auxiliary, vec![1.0], 3, dt);
+            let y = sol.integrate_to(end_time, &lorentz, &x);
+            assert_eq!(sol.statistics().evaluations, 3 * sol.get_num_of_steps());
+            y
+        };
+        let error = |dt: f64| {
+            let y = run(dt, 1.0);
+            (y.vec[0] - 1.0_f64.cos()).abs() + (y.vec[1] + 1.0_f64.sin()).abs()
+        };
+        let ratio = error(0.02) / error(0.01);
+        approx::assert_relative_eq!(ratio.log2(), 2.0, max_relative = 0.1);
+        // no drift of |v| over 1000 turns
+        let y = run(0.05, 2000.0 * PI);
+        approx::assert_abs_diff_eq!(y.vec[3].hypot(y.vec[4]), 1.0, epsilon = 1.0e-2);
+
+        let n_body = test_problems::kepler();
+        assert!(symplectic::check("AuxiliaryLeapfrog", &n_body).is_ok());
+        assert!(Symplectic::from_name("Euler", &n_body, 0.1).is_err());
+    }
+}
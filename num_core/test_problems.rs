@@ -0,0 +1,75 @@
+//
+// Problems shared by the tests of several modules (compiled with cfg(test) only).
+//
+use crate::num_core::n_body::NBody;
+use crate::num_core::smp_vector::SmpVector;
+
+// eccentric Kepler orbit (e = 0.6, a = r_p / (1 - e) = 1), pericenter at t = 0
+const KEPLER: &str = "
+SettingName: \"kepler\"
+NumberOfBodies: 2
+Mass: [0.5, 0.5]
+Position: [[-0.2, 0.0, 0.0], [0.2, 0.0, 0.0]]
+Velocity: [[0.0, -1.0, 0.0], [0.0, 1.0, 0.0]]
+";
+
+pub const KEPLER_PERIOD: f64 = 2.0 * std::f64::consts::PI;
+
+pub fn kepler() -> NBody {
+    serde_yaml::from_str(KEPLER).unwrap()
+}
+
+// relative Kepler problem r'' = -r / |r|^3 (G m = 1) in 2 or 3 dimensions,
+// state [r, v]
+pub fn relative_kepler(x: &SmpVector) -> SmpVector {
+    let n = x.vec.len() / 2;
+    let r3 = x.vec[..n].iter().map(|r| r * r).sum::<f64>().powf(1.5);
+    let mut vec = x.vec[n..].to_vec();
+    vec.extend(x.vec[..n].iter().map(|r| -r / r3));
+    SmpVector { time: x.time, vec }
+}
+
+// its energy v^2 / 2 - 1 / r
+pub fn kepler_energy(x: &SmpVector) -> f64 {
+    let n = x.vec.len() / 2;
+    let r = x.vec[..n].iter().map(|r| r * r).sum::<f64>().sqrt();
+    0.5 * x.vec[n..].iter().map(|v| v * v).sum::<f64>() - 1.0 / r
+}
+
+// pericenter of the relative orbit with a = 1 (period 2 pi) and eccentricity e
+pub fn pericenter(e: f64) -> SmpVector {
+    let r = 1.0 - e;
+    SmpVector {
+        time: 0.0,
+        vec: vec![r, 0.0, 0.0, 0.0, ((1.0 + e) / r).sqrt(), 0.0],
+    }
+}
+
+// x' = x, x = e^t from 1
+pub fn exponential(x: &SmpVector) -> SmpVector {
+    x.clone()
+}
+
+// harmonic oscillator x'' = -x
+pub fn oscillator(x: &SmpVector) -> SmpVector {
+    SmpVector {
+        time: x.time,
+        vec: vec![x.vec[1], -x.vec[0]],
+    }
+}
+
+// charged particle in a magnetic field b along z with a drag k, state [r, v]:
+// a = v x B - k v, circles of radius |v| / b that shrink as exp(-k t)
+pub fn lorentz(b: f64, k: f64) -> impl Fn(&SmpVector) -> SmpVector + Copy {
+    move |x: &SmpVector| SmpVector {
+        time: x.time,
+        vec: vec![
+            x.vec[3],
+            x.vec[4],
+            x.vec[5],
+            b * x.vec[4] - k * x.vec[3],
+            -b * x.vec[3] - k * x.vec[4],
+            -k * x.vec[5],
+        ],
+    }
+}
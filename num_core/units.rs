@@ -0,0 +1,176 @@
+//
+// Unit systems of the setting file.
+//
+// The equations of motion are written with G = 1. Any other system is mapped to
+// them by integrating with the gravitational parameters G m (and G S for the
+// spins); positions, velocities and time keep the units of the input.
+//
+use serde::{Deserialize, Serialize};
+
+// CODATA 2018 [m^3 kg^-1 s^-2]
+pub const GRAVITATIONAL_CONSTANT_SI: f64 = 6.67430e-11;
+// [m / s]
+pub const SPEED_OF_LIGHT_SI: f64 = 299_792_458.0;
+// nominal solar gravitational parameter, IAU 2015 Resolution B3 [m^3 s^-2]
+pub const SOLAR_GM_SI: f64 = 1.327_124_400_18e20;
+// IAU 2012 Resolution B2 [m]
+pub const ASTRONOMICAL_UNIT_SI: f64 = 1.495_978_707e11;
+// [s]
+pub const DAY_SI: f64 = 86_400.0;
+// Julian year [s]
+pub const YEAR_SI: f64 = 365.25 * DAY_SI;
+
+// Unit system of the input (YAML: Units) and of the output (YAML: OutputUnits).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+pub enum Units {
+    // G = 1, no physical scale; SpeedOfLight must be given
+    #[default]
+    NBody,
+    // G = c = 1 with the solar mass as the unit of mass
+    Geometric,
+    // m, kg, s
+    SI,
+    // AU, solar mass, Julian year
+    Astronomical,
+    // AU, solar mass, day
+    AstronomicalDay,
+}
+
+// Factors that convert quantities from one unit system to another.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Conversion {
+    pub length: f64,
+    pub mass: f64,
+    pub time: f64,
+}
+
+impl Conversion {
+    pub fn identity() -> Conversion {
+        Conversion {
+            length: 1.0,
+            mass: 1.0,
+            time: 1.0,
+        }
+    }
+
+    pub fn velocity(&self) -> f64 {
+        self.length / self.time
+    }
+
+    // angular momentum and spin
+    pub fn angular_momentum(&self) -> f64 {
+        self.mass * self.length * self.length / self.time
+    }
+
+    #[allow(dead_code)]
+    pub fn energy(&self) -> f64 {
+        self.mass * self.velocity() * self.velocity()
+    }
+}
+
+impl Units {
+    // (length [m], mass [kg], time [s]) of one unit, None for N-body units
+    pub fn scale(&self) -> Option<(f64, f64, f64)> {
+        let solar_mass = SOLAR_GM_SI / GRAVITATIONAL_CONSTANT_SI;
+        match self {
+            Units::NBody => None,
+            Units::Geometric => Some((
+                SOLAR_GM_SI / SPEED_OF_LIGHT_SI.powi(2),
+                solar_mass,
+                SOLAR_GM_SI / SPEED_OF_LIGHT_SI.powi(3),
+            )),
+            Units::SI => Some((1.0, 1.0, 1.0)),
+            Units::Astronomical => Some((ASTRONOMICAL_UNIT_SI, solar_mass, YEAR_SI)),
+            Units::AstronomicalDay => Some((ASTRONOMICAL_UNIT_SI, solar_mass, DAY_SI)),
+        }
+    }
+
+    // Newton's constant in this unit system
+    pub fn gravitational_constant(&self) -> f64 {
+        match self.scale() {
+            None => 1.0,
+            Some((l, m, t)) => GRAVITATIONAL_CONSTANT_SI * m * t * t / (l * l * l),
+        }
+    }
+
+    // physical speed of light in this unit system, None for N-body units
+    pub fn speed_of_light(&self) -> Option<f64> {
+        self.scale().map(|(l, _, t)| SPEED_OF_LIGHT_SI * t / l)
+    }
+
+    // factors that convert a quantity in self into the units of to
+    pub fn conversion(&self, to: Units) -> Result<Conversion, &'static str> {
+        if *self == to {
+            return Ok(Conversion::identity());
+        }
+        match (self.scale(), to.scale()) {
+            (Some((l1, m1, t1)), Some((l2, m2, t2))) => Ok(Conversion {
+                length: l1 / l2,
+                mass: m1 / m2,
+                time: t1 / t2,
+            }),
+            _ => Err("N-body units cannot be converted to physical units"),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod units_tests {
+    extern crate approx;
+    use crate::num_core::units::{Units, SPEED_OF_LIGHT_SI, YEAR_SI};
+
+    #[test]
+    fn gravitational_constant() {
+        approx::assert_abs_diff_eq!(Units::NBody.gravitational_constant(), 1.0);
+        approx::assert_relative_eq!(
+            Units::Geometric.gravitational_constant(),
+            1.0,
+            max_relative = 1.0e-14
+        );
+        approx::assert_relative_eq!(
+            Units::Geometric.speed_of_light().unwrap(),
+            1.0,
+            max_relative = 1.0e-14
+        );
+        // Kepler's third law: G M_sun = 4 pi^2 AU^3 / yr^2 up to the Earth's mass
+        // and the difference between the sidereal and the Julian year
+        approx::assert_relative_eq!(
+            Units::Astronomical.gravitational_constant(),
+            39.476_926_414_251_94,
+            max_relative = 1.0e-14
+        );
+        approx::assert_relative_eq!(
+            Units::AstronomicalDay.gravitational_constant().sqrt(),
+            0.017_202_098_95,
+            max_relative = 1.0e-9
+        );
+        approx::assert_relative_eq!(
+            Units::SI.speed_of_light().unwrap(),
+            SPEED_OF_LIGHT_SI,
+            max_relative = 1.0e-15
+        );
+        assert_eq!(Units::NBody.speed_of_light(), None);
+    }
+
+    #[test]
+    fn conversion() {
+        let to_si = Units::Astronomical.conversion(Units::SI).unwrap();
+        approx::assert_relative_eq!(to_si.time, YEAR_SI, max_relative = 1.0e-15);
+        approx::assert_relative_eq!(to_si.mass, 1.988_409_870_967_742e30, max_relative = 1.0e-14);
+        let back = Units::SI.conversion(Units::Astronomical).unwrap();
+        approx::assert_relative_eq!(
+            to_si.angular_momentum() * back.angular_momentum(),
+            1.0,
+            max_relative = 1.0e-14
+        );
+        // G M_sun / c^2 in metres
+        let geometric = Units::Geometric.conversion(Units::SI).unwrap();
+        approx::assert_relative_eq!(geometric.length, 1_476.625_038, max_relative = 1.0e-9);
+        let day = Units::AstronomicalDay
+            .conversion(Units::Astronomical)
+            .unwrap();
+        approx::assert_relative_eq!(day.velocity(), 365.25, max_relative = 1.0e-15);
+        assert!(Units::NBody.conversion(Units::SI).is_err());
+        assert_eq!(Units::NBody.conversion(Units::NBody).unwrap().energy(), 1.0);
+    }
+}